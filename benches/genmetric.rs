@@ -0,0 +1,68 @@
+#![feature(test)]
+
+extern crate test;
+extern crate rand;
+extern crate voronoi;
+
+use rand::{Rng, thread_rng};
+use voronoi::{voronoi_with_metric, Metric, Point, VoronoiOptions};
+
+const BOX_SIZE: f64 = 800.;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test::Bencher;
+
+    fn generate_points(count: usize) -> Vec<Point> {
+        let mut rng = thread_rng();
+        (0..count).map(|_| Point::new(rng.next_f64() * BOX_SIZE, rng.next_f64() * BOX_SIZE)).collect()
+    }
+
+    // points on a coarse grid, where many pairs are on diagonals or lines
+    // parallel to the axes and are equally near over whole areas
+    fn generate_grid_points(count: usize) -> Vec<Point> {
+        let mut rng = thread_rng();
+        let mut points: Vec<Point> = (0..count).map(|_| Point::new((rng.next_f64() * 40.).floor() * 20., (rng.next_f64() * 40.).floor() * 20.)).collect();
+        points.sort_by(|a, b| (a.x(), a.y()).partial_cmp(&(b.x(), b.y())).unwrap());
+        points.dedup();
+        points
+    }
+
+    #[bench]
+    fn bench_100_points_manhattan(b: &mut Bencher) {
+        let points = generate_points(100);
+
+        b.iter(|| {
+            voronoi_with_metric(points.clone(), BOX_SIZE, Metric::Manhattan, &VoronoiOptions::default()).unwrap();
+        });
+    }
+
+    #[bench]
+    fn bench_1000_points_manhattan(b: &mut Bencher) {
+        let points = generate_points(1000);
+
+        b.iter(|| {
+            voronoi_with_metric(points.clone(), BOX_SIZE, Metric::Manhattan, &VoronoiOptions::default()).unwrap();
+        });
+    }
+
+    #[bench]
+    fn bench_1000_points_chebyshev(b: &mut Bencher) {
+        let points = generate_points(1000);
+
+        b.iter(|| {
+            voronoi_with_metric(points.clone(), BOX_SIZE, Metric::Chebyshev, &VoronoiOptions::default()).unwrap();
+        });
+    }
+
+    #[bench]
+    fn bench_1000_grid_points_manhattan(b: &mut Bencher) {
+        let points = generate_grid_points(1000);
+
+        b.iter(|| {
+            voronoi_with_metric(points.clone(), BOX_SIZE, Metric::Manhattan, &VoronoiOptions::default()).unwrap();
+        });
+    }
+}
@@ -0,0 +1,50 @@
+#![feature(test)]
+
+extern crate test;
+extern crate rand;
+extern crate voronoi;
+
+use rand::{Rng, thread_rng};
+use voronoi::{segment_voronoi, Point, Site, VoronoiOptions};
+
+const BOX_SIZE: f64 = 800.;
+const TOLERANCE: f64 = 0.1;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test::Bencher;
+
+    // points and short segments, one to each cell of a grid over the box so
+    // that none touch
+    fn generate_sites(count: usize) -> Vec<Site> {
+        let mut rng = thread_rng();
+        let side = (count as f64).sqrt().ceil() as usize;
+        let cell = BOX_SIZE / side as f64;
+        (0..count).map(|place| {
+            let center = Point::new(((place % side) as f64 + 0.2 + rng.next_f64() * 0.6) * cell, ((place / side) as f64 + 0.2 + rng.next_f64() * 0.6) * cell);
+            let angle = rng.next_f64() * 2. * std::f64::consts::PI;
+            let offset = Point::new(angle.cos(), angle.sin()) * (cell * 0.15);
+            if place % 3 == 0 { Site::Point(center) } else { Site::Segment(center - offset, center + offset) }
+        }).collect()
+    }
+
+    #[bench]
+    fn bench_100_sites(b: &mut Bencher) {
+        let sites = generate_sites(100);
+
+        b.iter(|| {
+            segment_voronoi(sites.clone(), BOX_SIZE, TOLERANCE, &VoronoiOptions::default()).unwrap();
+        });
+    }
+
+    #[bench]
+    fn bench_1000_sites(b: &mut Bencher) {
+        let sites = generate_sites(1000);
+
+        b.iter(|| {
+            segment_voronoi(sites.clone(), BOX_SIZE, TOLERANCE, &VoronoiOptions::default()).unwrap();
+        });
+    }
+}
@@ -26,6 +26,28 @@ mod tests {
         vec
     }
 
+    // points along a diagonal, which the sweep meets in order of x:
+    // an unbalanced beachline would be a single path
+    fn generate_sorted_points(count: usize) -> Vec<Point> {
+        let mut rng = thread_rng();
+        (0..count).map(|i| {
+            let t = i as f64 / count as f64 * BOX_SIZE;
+            Point::new(t + rng.next_f64() * 0.01, BOX_SIZE - t + rng.next_f64() * 0.01)
+        }).collect()
+    }
+
+    // points in a few tight clusters
+    fn generate_clustered_points(count: usize) -> Vec<Point> {
+        let mut rng = thread_rng();
+        let centers: Vec<Point> = (0..10).map(|_| Point::new(rng.next_f64() * BOX_SIZE, rng.next_f64() * BOX_SIZE)).collect();
+        (0..count).map(|i| {
+            let center = centers[i % centers.len()];
+            let angle = rng.next_f64() * 2. * ::std::f64::consts::PI;
+            let radius = rng.next_f64() * rng.next_f64() * 10.;
+            Point::new(center.x() + radius * angle.cos(), center.y() + radius * angle.sin())
+        }).collect()
+    }
+
     #[bench]
     fn bench_1_point(b: &mut Bencher) {
         let points = vec![Point::new(0.0, 1.0)];
@@ -53,4 +75,40 @@ mod tests {
             voronoi(points.clone(), BOX_SIZE);
         });
     }
+
+    #[bench]
+    fn bench_1000_sorted_points(b: &mut Bencher) {
+        let points = generate_sorted_points(1000);
+
+        b.iter(|| {
+            voronoi(points.clone(), BOX_SIZE);
+        });
+    }
+
+    #[bench]
+    fn bench_10000_sorted_points(b: &mut Bencher) {
+        let points = generate_sorted_points(10000);
+
+        b.iter(|| {
+            voronoi(points.clone(), BOX_SIZE);
+        });
+    }
+
+    #[bench]
+    fn bench_1000_clustered_points(b: &mut Bencher) {
+        let points = generate_clustered_points(1000);
+
+        b.iter(|| {
+            voronoi(points.clone(), BOX_SIZE);
+        });
+    }
+
+    #[bench]
+    fn bench_10000_clustered_points(b: &mut Bencher) {
+        let points = generate_clustered_points(10000);
+
+        b.iter(|| {
+            voronoi(points.clone(), BOX_SIZE);
+        });
+    }
 }
@@ -0,0 +1,171 @@
+use point::Point;
+use dcel::DCEL;
+use voronoi::{VoronoiOptions, prepare_weighted_sites, additive_in_region};
+use error::VoronoiError;
+use bounding_box::BoundingBox;
+
+/// Computes the additively weighted Voronoi diagram of a set of weighted points,
+/// clipped to a bounding box. This is also known as the Apollonius diagram.
+///
+/// The distance from a location to a site is the distance to its point less its
+/// weight, as for seeds that start to grow at different times, and the cell of a
+/// site is where its distance is least. The cells are bounded by branches of
+/// hyperbolas, which become paths of straight edges within `tolerance` of them.
+/// A site whose circle, centered at its point with its weight for radius, lies
+/// within the circle of another site has an empty cell. Returns the Doubly
+/// Connected Edge List, together with the indices of the input sites whose cells
+/// are empty, in increasing order; these have no face. Sites whose cells miss
+/// the bounding box have no face either, but are not listed.
+///
+/// Coincident points are treated as by `power_diagram`. Fails as it does, and
+/// with `VoronoiError::InvalidTolerance` unless the tolerance is positive and finite.
+pub fn apollonius_diagram<B: Into<BoundingBox>>(sites: Vec<(Point, f64)>, bbox: B, tolerance: f64, options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let bbox = bbox.into();
+    if !bbox.is_valid() {
+        return Err(VoronoiError::InvalidBoundingBox);
+    }
+    if tolerance <= 0. || !tolerance.is_finite() {
+        return Err(VoronoiError::InvalidTolerance);
+    }
+    let (sites, weights, hidden) = prepare_weighted_sites(sites, options)?;
+
+    let (dcel, mut empty) = additive_in_region(&sites, &weights, &bbox.corners(), tolerance, options)?;
+    empty.extend(hidden);
+    empty.sort();
+    Ok((dcel, empty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcel::{make_site_polygons, make_site_polygons_with_holes, make_polygons};
+    use test_utils::{assert_fills_box, assert_grid_owned, clearly_nearest};
+    use validate::Validated;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn distance(pt: Point, site: (Point, f64)) -> f64 {
+        (pt - site.0).dot(pt - site.0).sqrt() - site.1
+    }
+
+    // the faces tile the box, each location on a grid lies in the face of the
+    // site nearest it, unless it is within the tolerance of a boundary, and no
+    // location is nearest a site listed as empty
+    fn assert_apollonius_cells(sites: &[(Point, f64)], size: f64, tolerance: f64) {
+        let (dcel, empty) = apollonius_diagram(sites.to_vec(), size, tolerance, &VoronoiOptions::default()).unwrap().validated();
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_fills_box(&faces, size);
+        for face in &faces {
+            assert!(!empty.contains(&face.0));
+        }
+
+        assert_grid_owned(&faces, size, |pt| {
+            let distance = |index: usize| distance(pt, sites[index]);
+            let nearest = clearly_nearest(sites.len(), distance, 0.).unwrap();
+            assert!(!empty.contains(&nearest), "empty site {} owns {:?}", nearest, pt);
+            // the difference in distance changes by at most 2 per unit moved
+            clearly_nearest(sites.len(), distance, 4. * tolerance)
+        });
+    }
+
+    #[test]
+    fn equal_weights_give_voronoi_diagram() {
+        let points = vec![Point::new(1.0, 2.0), Point::new(7.0, 3.0), Point::new(4.0, 8.0), Point::new(5.5, 5.0)];
+        let sites: Vec<(Point, f64)> = points.iter().map(|&pt| (pt, 0.5)).collect();
+        let (dcel, empty) = apollonius_diagram(sites, 10., 1e-3, &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        let polys = make_polygons(&dcel);
+        let expected = make_polygons(&::voronoi::voronoi(points, 10.));
+        assert_eq!(polys.len(), expected.len());
+        for (poly, expected) in polys.iter().zip(&expected) {
+            assert_eq!(poly.len(), expected.len());
+            for (&pt, &expected) in poly.iter().zip(expected) {
+                assert!((pt - expected).dot(pt - expected).sqrt() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn boundary_follows_hyperbola() {
+        let sites = vec![(Point::new(3.0, 5.0), 2.0), (Point::new(7.0, 5.0), 0.0)];
+        let tolerance = 1e-3;
+        let (dcel, empty) = apollonius_diagram(sites.clone(), 10., tolerance, &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        let faces = make_site_polygons(&dcel);
+        assert_eq!(faces.len(), 2);
+        // the vertices between the cells lie on the branch, and where it leaves
+        // the box, the edge is within the tolerance of it
+        let boundary: Vec<Point> = faces[0].1.iter().cloned().filter(|pt| pt.x() > 0. && pt.x() < 10.).collect();
+        assert!(boundary.len() > 4);
+        for pt in &boundary {
+            let gap = (distance(*pt, sites[0]) - distance(*pt, sites[1])).abs();
+            if pt.y() > 0. && pt.y() < 10. {
+                assert!(gap < 1e-9);
+            } else {
+                assert!(gap < 2. * tolerance);
+            }
+        }
+        // the branch bends around the lighter site, passing through (6, 5)
+        assert!(boundary.iter().all(|pt| pt.x() >= 6.0 - 1e-9));
+        assert_apollonius_cells(&sites, 10., tolerance);
+    }
+
+    #[test]
+    fn site_within_heavier_circle_is_empty() {
+        let sites = vec![(Point::new(5.0, 5.0), 3.0), (Point::new(6.0, 5.5), 1.0), (Point::new(1.0, 1.0), 0.0), (Point::new(9.0, 8.0), 0.5)];
+        let (dcel, empty) = apollonius_diagram(sites.clone(), 10., 1e-3, &VoronoiOptions::default()).unwrap().validated();
+        assert_eq!(empty, vec![1]);
+        assert_eq!(make_site_polygons(&dcel).len(), 3);
+        assert_apollonius_cells(&sites, 10., 1e-3);
+    }
+
+    #[test]
+    fn top_row_with_weights() {
+        // the first two sites reach the beachline at once, from different heights
+        let sites = vec![(Point::new(2.0, 9.0), 0.0), (Point::new(6.0, 7.0), 2.0), (Point::new(4.0, 2.0), 1.0)];
+        assert_apollonius_cells(&sites, 10., 1e-3);
+    }
+
+    #[test]
+    fn site_reaching_vertex() {
+        // the lowest site starts its arc just as the arc above it vanishes
+        let sites = vec![(Point::new(40.0, 0.0), 3.0), (Point::new(20.0, 40.0), 3.0), (Point::new(60.0, 10.0), 3.0)];
+        assert_apollonius_cells(&sites, 100., 1e-2);
+    }
+
+    #[test]
+    fn collinear_sites() {
+        let sites = vec![(Point::new(40.0, 10.0), 7.0), (Point::new(20.0, 10.0), 1.0), (Point::new(10.0, 10.0), 5.0)];
+        assert_apollonius_cells(&sites, 100., 1e-2);
+    }
+
+    #[test]
+    fn coincident_sites_keep_heaviest() {
+        let sites = vec![(Point::new(3.0, 3.0), 1.0), (Point::new(7.0, 7.0), 0.0), (Point::new(3.0, 3.0), 2.0)];
+        let (dcel, empty) = apollonius_diagram(sites, 10., 1e-3, &VoronoiOptions::default()).unwrap().validated();
+        assert_eq!(empty, vec![0]);
+        let faces = make_site_polygons(&dcel);
+        assert_eq!(faces.iter().map(|&(site, _)| site).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn invalid_input_rejected() {
+        let options = VoronoiOptions::default();
+        let sites = vec![(Point::new(3.0, 3.0), 1.0), (Point::new(7.0, 7.0), f64::INFINITY)];
+        assert_eq!(apollonius_diagram(sites, 10., 1e-3, &options).err(), Some(VoronoiError::NonFiniteWeight { index: 1 }));
+        for &tolerance in &[0., -1., f64::NAN, f64::INFINITY] {
+            let sites = vec![(Point::new(3.0, 3.0), 1.0)];
+            assert_eq!(apollonius_diagram(sites, 10., tolerance, &options).err(), Some(VoronoiError::InvalidTolerance));
+        }
+    }
+
+    #[test]
+    fn random_weights() {
+        for seed in 0..10 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let sites: Vec<(Point, f64)> = (0..40)
+                .map(|_| (Point::new(rng.next_f64() * 100., rng.next_f64() * 100.), rng.next_f64() * 20.))
+                .collect();
+            assert_apollonius_cells(&sites, 100., 0.01);
+        }
+    }
+}
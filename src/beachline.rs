@@ -1,13 +1,25 @@
 use std::fmt;
+use std::cmp::Ordering;
 use point::Point;
-use geometry::get_breakpoint_x;
+use geometry::{get_breakpoint_x, breakpoint_side, Weighting};
+use error::VoronoiError;
 
 const NIL: usize = !0;
 type TripleSite = (Point, Point, Point);
 
+// The beachline is a treap: the breakpoints are kept in heap order by random
+// priorities, and rotated to restore it whenever breakpoints are added. This
+// keeps its depth logarithmic in expectation, whatever the order of the sites.
+// Arcs are always leaves, and are never rotated.
 pub struct BeachLine {
     pub nodes: Vec<BeachNode>,
     pub root: usize,
+    // indices of nodes that have left the tree, to be reused
+    free_nodes: Vec<usize>,
+    // state of the xorshift generator for breakpoint priorities
+    priority_state: u64,
+    // how the weights of the sites shape their arcs
+    pub weighting: Weighting,
 }
 
 impl fmt::Debug for BeachLine {
@@ -16,10 +28,10 @@ impl fmt::Debug for BeachLine {
 
         for (index, node) in self.nodes.iter().enumerate() {
             beachline_disp.push_str(format!("{}: {:?}", index, node).as_str());
-            beachline_disp.push_str("\n");
+            beachline_disp.push('\n');
         }
 
-        write!(f, "\nRoot: {}\n{}", self.root, beachline_disp)
+        write!(f, "\nRoot: {}\nFree: {:?}\n{}", self.root, self.free_nodes, beachline_disp)
     }
 }
 
@@ -28,22 +40,23 @@ pub struct BeachNode {
     pub left_child: Option<usize>,
     pub right_child: Option<usize>,
     pub item: BeachItem,
+    pub priority: u64, // treap priority, zero for leaves
 }
 
 impl fmt::Debug for BeachNode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "p: {:?}, l: {:?}, r: {:?}, item: {:?}", self.parent, self.left_child, self.right_child, self.item)
+        write!(f, "p: {:?}, l: {:?}, r: {:?}, pri: {}, item: {:?}", self.parent, self.left_child, self.right_child, self.priority, self.item)
     }
 }
 
 impl BeachNode {
     fn make_root(item: BeachItem) -> Self {
-        BeachNode { parent: None, left_child: None, right_child: None, item: item}
+        BeachNode { parent: None, left_child: None, right_child: None, item, priority: 0 }
     }
 
     pub fn make_arc(parent: Option<usize>, item: BeachItem) -> Self {
         if let BeachItem::Leaf(_) = item {
-            BeachNode { parent: parent, left_child: None, right_child: None, item: item}
+            BeachNode { parent, left_child: None, right_child: None, item, priority: 0 }
         } else {
             panic!("make_arc can only make Leaf items!");
         }
@@ -66,25 +79,35 @@ impl fmt::Debug for BeachItem {
 
 pub struct Arc {
     pub site: Point,
+    // the far end of the segment whose side the arc is, or the site itself for a point
+    pub end: Point,
+    pub site_index: usize, // index of site in the input
     pub site_event: Option<usize>, // index to circle event in EventQueue
 }
 
 impl fmt::Debug for Arc {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "site: {:?}, site_event: {:?}", self.site, self.site_event)
+        write!(f, "site {}: {:?}, site_event: {:?}", self.site_index, self.site, self.site_event)
     }
 }
 
 impl Arc {
-    pub fn new(site: Point, site_event: Option<usize>) -> Self {
-        Arc { site: site, site_event: site_event }
+    pub fn new(site: Point, site_index: usize, site_event: Option<usize>) -> Self {
+        Arc { site, end: site, site_index, site_event }
     }
 }
 
 pub struct BreakPoint {
     pub left_site: Point,
     pub right_site: Point,
+    // the weights of the sites, as the beachline weighting takes them, zero for a Voronoi diagram
+    pub left_weight: f64,
+    pub right_weight: f64,
+    // the far ends of the segments whose sides the arcs are, as in Arc
+    pub left_end: Point,
+    pub right_end: Point,
     pub halfedge: usize, // index of halfedge
+    pub vanished_site: Option<Point>, // site of the arc that vanished where this breakpoint began
 }
 
 impl fmt::Debug for BreakPoint {
@@ -94,38 +117,134 @@ impl fmt::Debug for BreakPoint {
 }
 
 impl BreakPoint {
-    pub fn new(left_site: Point, right_site: Point, halfedge: usize) -> Self {
-        BreakPoint { left_site: left_site, right_site: right_site, halfedge: halfedge }
+    // the sites are given with their weights, as (site, weight)
+    pub fn new(left: (Point, f64), right: (Point, f64), halfedge: usize) -> Self {
+        BreakPoint { left_site: left.0, right_site: right.0, left_weight: left.1, right_weight: right.1, left_end: left.0, right_end: right.0, halfedge, vanished_site: None }
+    }
+
+    // between the arcs of a segment diagram, which are unweighted
+    pub fn between(left: &Arc, right: &Arc, halfedge: usize) -> Self {
+        BreakPoint { left_end: left.end, right_end: right.end, ..BreakPoint::new((left.site, 0.), (right.site, 0.), halfedge) }
     }
 }
 
 impl BeachLine {
     pub fn new() -> Self {
-        BeachLine { nodes: vec![], root: NIL }
+        BeachLine { nodes: vec![], root: NIL, free_nodes: vec![], priority_state: 0x2545_f491_4f6c_dd1d, weighting: Weighting::Power }
+    }
+
+    // Store a node, in the place of a freed node if there is one.
+    // return: the index of the node
+    pub fn add_node(&mut self, node: BeachNode) -> usize {
+        if let Some(index) = self.free_nodes.pop() {
+            self.nodes[index] = node;
+            index
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        }
+    }
+
+    // Release a node that is no longer in the tree, so that its place can
+    // be reused. Its links are cleared, so that it can't be walked from.
+    pub fn free_node(&mut self, index: usize) {
+        let node = &mut self.nodes[index];
+        node.parent = None;
+        node.left_child = None;
+        node.right_child = None;
+        self.free_nodes.push(index);
+    }
+
+    // The number of nodes in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.free_nodes.len()
+    }
+
+    // A fresh priority for a breakpoint. The generator is seeded the same way
+    // every time, so that the shape of the tree, and so the diagram, is reproducible.
+    pub fn next_priority(&mut self) -> u64 {
+        let mut x = self.priority_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.priority_state = x;
+        x
+    }
+
+    // Rotate the breakpoint `node` up past its ancestors until its parent
+    // has a higher priority. The in-order sequence of arcs and breakpoints
+    // is unchanged, and since both nodes of every rotation are breakpoints,
+    // every breakpoint keeps two children.
+    pub fn rebalance(&mut self, node: usize) -> Result<(), VoronoiError> {
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].priority >= self.nodes[node].priority { break; }
+            self.rotate_up(node)?;
+        }
+        Ok(())
+    }
+
+    fn rotate_up(&mut self, node: usize) -> Result<(), VoronoiError> {
+        let parent = self.nodes[node].parent.ok_or(VoronoiError::InternalInconsistency("can't rotate the root"))?;
+        let grandparent = self.nodes[parent].parent;
+        let inner_child = if self.nodes[parent].left_child == Some(node) {
+            let inner_child = self.nodes[node].right_child;
+            self.nodes[parent].left_child = inner_child;
+            self.nodes[node].right_child = Some(parent);
+            inner_child
+        } else {
+            let inner_child = self.nodes[node].left_child;
+            self.nodes[parent].right_child = inner_child;
+            self.nodes[node].left_child = Some(parent);
+            inner_child
+        };
+        let inner_child = inner_child.ok_or(VoronoiError::InternalInconsistency("only breakpoints can be rotated"))?;
+
+        self.nodes[inner_child].parent = Some(parent);
+        self.nodes[parent].parent = Some(node);
+        self.nodes[node].parent = grandparent;
+        self.replace_child(grandparent, parent, node)
     }
 
     pub fn is_empty(&self) -> bool {
-        self.nodes.is_empty()
+        self.len() == 0
     }
 
-    pub fn insert_point(&mut self, pt: Point) {
-        let this_arc = Arc {site: pt, site_event: None};
+    pub fn insert_point(&mut self, pt: Point, index: usize) {
+        let this_arc = Arc::new(pt, index, None);
         let this_item = BeachItem::Leaf(this_arc);
         let this_node = BeachNode::make_root(this_item);
-        self.nodes.push(this_node);
-        self.root = self.nodes.len() - 1;
+        self.root = self.add_node(this_node);
+    }
+
+    // Point the parent of node `old` at node `new` instead.
+    // A parent of `None` means that `old` was the root.
+    pub fn replace_child(&mut self, parent: Option<usize>, old: usize, new: usize) -> Result<(), VoronoiError> {
+        if let Some(parent_ind) = parent {
+            let parent_node = &mut self.nodes[parent_ind];
+            if parent_node.right_child == Some(old) {
+                parent_node.right_child = Some(new);
+            } else if parent_node.left_child == Some(old) {
+                parent_node.left_child = Some(new);
+            } else {
+                return Err(VoronoiError::InternalInconsistency("beachline parent does not acknowledge child"));
+            }
+        } else {
+            self.root = new;
+        }
+        Ok(())
     }
 
-    pub fn get_arc_above(&self, pt: Point) -> usize {
-        if self.is_empty() { panic!("can't get_arc_above on empty beachline!"); }
+    pub fn get_arc_above(&self, pt: Point) -> Result<usize, VoronoiError> {
+        if self.is_empty() {
+            return Err(VoronoiError::InternalInconsistency("no arc above site on empty beachline"));
+        }
         let mut current_node = self.root;
         loop {
             match self.nodes[current_node].item {
-                BeachItem::Leaf(_) => { return current_node; }
+                BeachItem::Leaf(_) => { return Ok(current_node); }
                 BeachItem::Internal(ref breakpoint) => {
-                    let x_bp = get_breakpoint_x(breakpoint, pt.y());
-                    if pt.x() < x_bp { current_node = self.nodes[current_node].left_child.unwrap(); }
-                    else { current_node = self.nodes[current_node].right_child.unwrap(); }
+                    let child = if breakpoint_side(breakpoint, pt, self.weighting) == Ordering::Less { self.nodes[current_node].left_child } else { self.nodes[current_node].right_child };
+                    current_node = child.ok_or(VoronoiError::InternalInconsistency("beachline breakpoint is missing a child"))?;
                 }
             }
         }
@@ -170,7 +289,7 @@ impl BeachLine {
             current_node = current_parent;
             current_parent = self.nodes[current_parent.unwrap()].parent;
         }
-        return current_parent;
+        current_parent
     }
 
     pub fn get_left_arc(&self, node: Option<usize>) -> Option<usize> {
@@ -189,9 +308,10 @@ impl BeachLine {
         let left_site = self.get_site(left_arc);
         let left_left_site = self.get_site(left_left_arc);
 
-        if this_site.is_some() && left_site.is_some() && left_left_site.is_some() {
-            Some((left_left_site.unwrap(), left_site.unwrap(), this_site.unwrap()))
-        } else { None }
+        match (left_left_site, left_site, this_site) {
+            (Some(left_left_site), Some(left_site), Some(this_site)) => Some((left_left_site, left_site, this_site)),
+            _ => None,
+        }
     }
 
     pub fn get_rightward_triple(&self, node: usize) -> Option<TripleSite> {
@@ -202,9 +322,10 @@ impl BeachLine {
         let right_site = self.get_site(right_arc);
         let right_right_site = self.get_site(right_right_arc);
 
-        if this_site.is_some() && right_site.is_some() && right_right_site.is_some() {
-            Some((this_site.unwrap(), right_site.unwrap(), right_right_site.unwrap()))
-        } else { None }
+        match (this_site, right_site, right_right_site) {
+            (Some(this_site), Some(right_site), Some(right_right_site)) => Some((this_site, right_site, right_right_site)),
+            _ => None,
+        }
     }
 
     pub fn get_centered_triple(&self, node: usize) -> Option<TripleSite> {
@@ -215,9 +336,10 @@ impl BeachLine {
         let right_site = self.get_site(right_arc);
         let left_site = self.get_site(left_arc);
 
-        if this_site.is_some() && right_site.is_some() && left_site.is_some() {
-            Some((left_site.unwrap(), this_site.unwrap(), right_site.unwrap()))
-        } else { None }
+        match (left_site, this_site, right_site) {
+            (Some(left_site), Some(this_site), Some(right_site)) => Some((left_site, this_site, right_site)),
+            _ => None,
+        }
     }
 
     pub fn get_site(&self, node: Option<usize>) -> Option<Point> {
@@ -230,27 +352,135 @@ impl BeachLine {
         })
     }
 
-    pub fn set_right_site(&mut self, node: usize, site: Point) {
+    // the site and end of an arc, as in Arc
+    pub fn get_site_end(&self, node: usize) -> Result<(Point, Point), VoronoiError> {
+        if let BeachItem::Leaf(ref arc) = self.nodes[node].item {
+            Ok((arc.site, arc.end))
+        } else {
+            Err(VoronoiError::InternalInconsistency("target of get_site_end should be a leaf"))
+        }
+    }
+
+    pub fn get_site_index(&self, node: usize) -> Result<usize, VoronoiError> {
+        if let BeachItem::Leaf(ref arc) = self.nodes[node].item {
+            Ok(arc.site_index)
+        } else {
+            Err(VoronoiError::InternalInconsistency("target of get_site_index should be a leaf"))
+        }
+    }
+
+    // Take the site of an arc, with its end, as the right site of a breakpoint
+    pub fn set_right_site(&mut self, node: usize, arc: usize, weight: f64) -> Result<(), VoronoiError> {
+        let (site, end) = self.get_site_end(arc)?;
         if let BeachItem::Internal(ref mut bp) = self.nodes[node].item {
             bp.right_site = site;
+            bp.right_end = end;
+            bp.right_weight = weight;
+            Ok(())
         } else {
-            panic!("target of set_site should be internal");
+            Err(VoronoiError::InternalInconsistency("target of set_site should be internal"))
         }
     }
 
-    pub fn set_left_site(&mut self, node: usize, site: Point) {
+    pub fn set_left_site(&mut self, node: usize, arc: usize, weight: f64) -> Result<(), VoronoiError> {
+        let (site, end) = self.get_site_end(arc)?;
         if let BeachItem::Internal(ref mut bp) = self.nodes[node].item {
             bp.left_site = site;
+            bp.left_end = end;
+            bp.left_weight = weight;
+            Ok(())
+        } else {
+            Err(VoronoiError::InternalInconsistency("target of set_site should be internal"))
+        }
+    }
+
+    pub fn get_vanished_site(&self, node: usize) -> Result<Option<Point>, VoronoiError> {
+        if let BeachItem::Internal(ref breakpoint) = self.nodes[node].item {
+            Ok(breakpoint.vanished_site)
+        } else {
+            Err(VoronoiError::InternalInconsistency("target of get_vanished_site should be internal"))
+        }
+    }
+
+    pub fn get_breakpoint_x(&self, node: usize, yl: f64) -> Result<f64, VoronoiError> {
+        if let BeachItem::Internal(ref breakpoint) = self.nodes[node].item {
+            Ok(get_breakpoint_x(breakpoint, yl, self.weighting))
         } else {
-            panic!("target of set_site should be internal");
+            Err(VoronoiError::InternalInconsistency("target of get_breakpoint_x should be internal"))
         }
     }
 
-    pub fn get_edge(&self, node: usize) -> usize {
+    pub fn get_edge(&self, node: usize) -> Result<usize, VoronoiError> {
         if let BeachItem::Internal(ref breakpoint) = self.nodes[node].item {
-            breakpoint.halfedge
+            Ok(breakpoint.halfedge)
         } else {
-            panic!("target of get_edge should be internal");
+            Err(VoronoiError::InternalInconsistency("target of get_edge should be internal"))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_missing_child_is_an_error() {
+        let mut beachline = BeachLine::new();
+        beachline.insert_point(Point::new(1.0, 2.0), 0);
+        beachline.insert_point(Point::new(3.0, 4.0), 1);
+        assert!(beachline.replace_child(Some(0), 1, 1).is_err());
+        assert!(beachline.replace_child(None, 0, 1).is_ok());
+        assert_eq!(beachline.root, 1);
+    }
+
+    // arcs a, b, c under breakpoints ab and bc, with bc the child of ab
+    fn two_breakpoints(priority_ab: u64, priority_bc: u64) -> BeachLine {
+        let (a, b, c) = (Point::new(0.0, 3.0), Point::new(1.0, 2.0), Point::new(2.0, 1.0));
+        let mut beachline = BeachLine::new();
+        beachline.nodes = vec![
+            BeachNode { parent: None, left_child: Some(1), right_child: Some(2), item: BeachItem::Internal(BreakPoint::new((a, 0.0), (b, 0.0), 0)), priority: priority_ab },
+            BeachNode::make_arc(Some(0), BeachItem::Leaf(Arc::new(a, 0, None))),
+            BeachNode { parent: Some(0), left_child: Some(3), right_child: Some(4), item: BeachItem::Internal(BreakPoint::new((b, 0.0), (c, 0.0), 2)), priority: priority_bc },
+            BeachNode::make_arc(Some(2), BeachItem::Leaf(Arc::new(b, 1, None))),
+            BeachNode::make_arc(Some(2), BeachItem::Leaf(Arc::new(c, 2, None))),
+        ];
+        beachline.root = 0;
+        beachline
+    }
+
+    fn in_order(beachline: &BeachLine) -> Vec<usize> {
+        let mut nodes = vec![beachline.tree_minimum(beachline.root)];
+        while let Some(next) = beachline.successor(*nodes.last().unwrap()) {
+            nodes.push(next);
+        }
+        nodes
+    }
+
+    #[test]
+    fn rebalance_rotates_higher_priority_up() {
+        let mut beachline = two_breakpoints(1, 2);
+        beachline.rebalance(2).unwrap();
+        assert_eq!(beachline.root, 2);
+        assert_eq!(beachline.nodes[2].parent, None);
+        assert_eq!(beachline.nodes[0].parent, Some(2));
+        assert_eq!(beachline.nodes[3].parent, Some(0));
+        assert_eq!(in_order(&beachline), vec![1, 0, 3, 2, 4]);
+    }
+
+    #[test]
+    fn rebalance_keeps_heap_order() {
+        let mut beachline = two_breakpoints(2, 1);
+        beachline.rebalance(2).unwrap();
+        assert_eq!(beachline.root, 0);
+        assert_eq!(in_order(&beachline), vec![1, 0, 3, 2, 4]);
+    }
+
+    #[test]
+    fn accessors_check_node_kind() {
+        let mut beachline = BeachLine::new();
+        beachline.insert_point(Point::new(1.0, 2.0), 7);
+        assert_eq!(beachline.get_site_index(0), Ok(7));
+        assert!(beachline.get_edge(0).is_err());
+        assert!(beachline.set_left_site(0, 0, 0.0).is_err());
+    }
+}
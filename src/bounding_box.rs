@@ -0,0 +1,154 @@
+use point::Point;
+
+/// An axis-aligned rectangle, used to clip the Voronoi diagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundingBox {
+    /// Corner with the smallest coordinates
+    pub min: Point,
+    /// Corner with the largest coordinates
+    pub max: Point,
+}
+
+impl BoundingBox {
+    /// Constructs a new `BoundingBox` from two opposite corners.
+    pub fn new(min: Point, max: Point) -> Self {
+        BoundingBox { min, max }
+    }
+
+    /// The smallest `BoundingBox` containing all the points.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if there are no points.
+    pub fn enclosing(points: &[Point]) -> Self {
+        if points.is_empty() { panic!("can't enclose an empty set of points"); }
+        let mut min = points[0];
+        let mut max = points[0];
+        for pt in points {
+            min = Point::new(min.x().min(pt.x()), min.y().min(pt.y()));
+            max = Point::new(max.x().max(pt.x()), max.y().max(pt.y()));
+        }
+        BoundingBox::new(min, max)
+    }
+
+    /// The corners, in counterclockwise order starting from `min`.
+    pub fn corners(&self) -> [Point; 4] {
+        [self.min, Point::new(self.max.x(), self.min.y()), self.max, Point::new(self.min.x(), self.max.y())]
+    }
+
+    /// Extent along the x axis.
+    pub fn width(&self) -> f64 {
+        self.max.x() - self.min.x()
+    }
+
+    /// Extent along the y axis.
+    pub fn height(&self) -> f64 {
+        self.max.y() - self.min.y()
+    }
+
+    /// The center of the rectangle.
+    pub fn center(&self) -> Point {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Length of the diagonal.
+    pub fn diagonal(&self) -> f64 {
+        (self.width() * self.width() + self.height() * self.height()).sqrt()
+    }
+
+    /// Does the rectangle have a positive, finite area?
+    pub fn is_valid(&self) -> bool {
+        self.width() > 0. && self.height() > 0. && self.diagonal().is_finite()
+    }
+
+    /// Is the point within the rectangle, including its boundary?
+    pub fn contains(&self, pt: Point) -> bool {
+        pt.x() >= self.min.x() && pt.x() <= self.max.x() && pt.y() >= self.min.y() && pt.y() <= self.max.y()
+    }
+}
+
+/// The square `[0, size] x [0, size]`.
+impl From<f64> for BoundingBox {
+    fn from(size: f64) -> Self {
+        BoundingBox::new(Point::new(0., 0.), Point::new(size, size))
+    }
+}
+
+// The nearest point of the box to a point
+pub fn clamp_to_box(pt: Point, bbox: &BoundingBox) -> Point {
+    Point::new(pt.x().max(bbox.min.x()).min(bbox.max.x()), pt.y().max(bbox.min.y()).min(bbox.max.y()))
+}
+
+// How far inside a side of the box a point is
+pub fn side_offset(pt: Point, side: usize, bbox: &BoundingBox) -> f64 {
+    match side {
+        0 => pt.y() - bbox.min.y(),
+        1 => bbox.max.x() - pt.x(),
+        2 => bbox.max.y() - pt.y(),
+        _ => pt.x() - bbox.min.x(),
+    }
+}
+
+/// A grid of square cells over a set of boxes, each listed in every cell it
+/// overlaps, so that the boxes near a place can be found without looking at
+/// every one.
+pub struct BoxGrid {
+    extent: BoundingBox,
+    side: f64,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl BoxGrid {
+    /// A grid over the boxes with about as many cells as boxes.
+    pub fn new(boxes: &[BoundingBox]) -> Self {
+        let corners: Vec<Point> = boxes.iter().flat_map(|area| vec![area.min, area.max]).collect();
+        let extent = if corners.is_empty() { BoundingBox::new(Point::new(0., 0.), Point::new(0., 0.)) } else { BoundingBox::enclosing(&corners) };
+        let longest = extent.width().max(extent.height());
+        let side = if longest > 0. { longest / (boxes.len() as f64).sqrt() } else { 1. };
+        let (cols, rows) = ((extent.width() / side) as usize + 1, (extent.height() / side) as usize + 1);
+        let mut grid = BoxGrid { extent, side, cols, rows, cells: vec![vec![]; cols * rows] };
+        for (index, area) in boxes.iter().enumerate() {
+            let (low, high) = (grid.cell(area.min), grid.cell(area.max));
+            for row in low.1..=high.1 {
+                for col in low.0..=high.0 {
+                    grid.cells[row * cols + col].push(index);
+                }
+            }
+        }
+        grid
+    }
+
+    /// The side of a cell.
+    pub fn side(&self) -> f64 {
+        self.side
+    }
+
+    // the cell a point lies in, or the nearest cell to it
+    fn cell(&self, pt: Point) -> (usize, usize) {
+        let col = ((pt.x() - self.extent.min.x()) / self.side).max(0.) as usize;
+        let row = ((pt.y() - self.extent.min.y()) / self.side).max(0.) as usize;
+        (col.min(self.cols - 1), row.min(self.rows - 1))
+    }
+
+    /// The boxes listed in the cells that an area overlaps, in order. These
+    /// include every box that overlaps the area.
+    pub fn near(&self, area: &BoundingBox) -> Vec<usize> {
+        let (low, high) = (self.cell(area.min), self.cell(area.max));
+        let mut near = vec![];
+        for row in low.1..=high.1 {
+            for col in low.0..=high.0 {
+                near.extend_from_slice(&self.cells[row * self.cols + col]);
+            }
+        }
+        near.sort();
+        near.dedup();
+        near
+    }
+
+    /// Does an area cover every box?
+    pub fn covers(&self, area: &BoundingBox) -> bool {
+        area.contains(self.extent.min) && area.contains(self.extent.max)
+    }
+}
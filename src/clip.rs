@@ -0,0 +1,277 @@
+use ordered_float::OrderedFloat;
+use point::Point;
+use dcel::{DCEL, add_line, add_ring, clear_left_site};
+use geometry::{polygon_contains, segments_touch};
+use predicates::orient2d;
+use error::VoronoiError;
+
+/// Checks that a polygon is convex and returns it in counterclockwise order,
+/// without repeated or collinear vertices.
+/// Returns None if the polygon is not convex, has no area, or has non-finite coordinates.
+pub fn normalize_convex_polygon(polygon: &[Point]) -> Option<Vec<Point>> {
+    if polygon.iter().any(|pt| !pt.x().is_finite() || !pt.y().is_finite()) { return None; }
+
+    let mut result: Vec<Point> = polygon.to_vec();
+    loop {
+        let num_pts = result.len();
+        if num_pts < 3 { return None; }
+        let redundant = (0..num_pts).find(|&index| {
+            let prev = result[(index + num_pts - 1) % num_pts];
+            let next = result[(index + 1) % num_pts];
+            result[index] == next || orient2d(prev, result[index], next) == 0.
+        });
+        match redundant {
+            Some(index) => { result.remove(index); }
+            None => break,
+        }
+    }
+
+    let num_pts = result.len();
+    if orient2d(result[0], result[1], result[2]) < 0. {
+        result.reverse();
+    }
+
+    // every vertex lies to the left of every side, which also rules out
+    // polygons that wind around more than once
+    for index in 0..num_pts {
+        let start = result[index];
+        let end = result[(index + 1) % num_pts];
+        if result.iter().any(|&pt| orient2d(start, end, pt) < 0.) { return None; }
+    }
+    Some(result)
+}
+
+/// Checks that a polygon with holes is valid and returns it with the outer
+/// boundary counterclockwise and the holes clockwise, without repeated vertices.
+///
+/// Returns None if any ring has non-finite coordinates or no area, if any
+/// boundaries touch or cross, or if a hole is not inside the outer boundary
+/// or is inside another hole.
+pub fn normalize_polygon_with_holes(outer: &[Point], holes: &[Vec<Point>]) -> Option<(Vec<Point>, Vec<Vec<Point>>)> {
+    let outer = normalize_ring(outer, true)?;
+    let mut normalized_holes = vec![];
+    for hole in holes {
+        normalized_holes.push(normalize_ring(hole, false)?);
+    }
+
+    let mut rings = vec![&outer];
+    rings.extend(normalized_holes.iter());
+    let sides: Vec<(usize, usize, [Point; 2])> = rings.iter().enumerate()
+        .flat_map(|(ring_index, ring)| (0..ring.len()).map(move |index| {
+            (ring_index, index, [ring[index], ring[(index + 1) % ring.len()]])
+        }))
+        .collect();
+    for (first, &(ring1, index1, side1)) in sides.iter().enumerate() {
+        for &(ring2, index2, side2) in &sides[first + 1..] {
+            if ring1 == ring2 {
+                let num_pts = rings[ring1].len();
+                // neighbouring sides share a corner, but must not fold back onto each other
+                if index2 == index1 + 1 || (index1 == 0 && index2 == num_pts - 1) {
+                    let (start, corner, end) = if index2 == index1 + 1 { (side1[0], side1[1], side2[1]) } else { (side2[0], side2[1], side1[1]) };
+                    if orient2d(start, corner, end) == 0. && (end - corner).dot(start - corner) > 0. { return None; }
+                    continue;
+                }
+            }
+            if segments_touch(side1, side2) { return None; }
+        }
+    }
+
+    // with no crossings, one vertex of each ring decides containment
+    for (index, hole) in normalized_holes.iter().enumerate() {
+        if !polygon_contains(&outer, hole[0]) { return None; }
+        if normalized_holes.iter().enumerate().any(|(other, other_hole)| other != index && polygon_contains(other_hole, hole[0])) {
+            return None;
+        }
+    }
+    Some((outer, normalized_holes))
+}
+
+// The ring without repeated vertices, in the given orientation
+fn normalize_ring(ring: &[Point], counterclockwise: bool) -> Option<Vec<Point>> {
+    if ring.iter().any(|pt| !pt.x().is_finite() || !pt.y().is_finite()) { return None; }
+    let mut result: Vec<Point> = vec![];
+    for &pt in ring {
+        if result.last() != Some(&pt) { result.push(pt); }
+    }
+    while result.len() > 1 && result.first() == result.last() { result.pop(); }
+    if result.len() < 3 { return None; }
+
+    let area = (0..result.len()).fold(0., |area, index| area + result[index].cross(result[(index + 1) % result.len()]));
+    if area == 0. { return None; }
+    if (area > 0.) != counterclockwise { result.reverse(); }
+    Some(result)
+}
+
+/// Clip a DCEL to a polygon with holes, as returned by normalize_polygon_with_holes.
+///
+/// The DCEL must already be clipped to a convex region containing the polygon
+/// strictly inside it. Each ring is added with add_ring, then every vertex
+/// off the rings, outside the polygon or inside a hole, is removed along
+/// with its halfedges, as is every edge joining two points of the rings
+/// outside the polygon. Faces are not affected. Sites are given as
+/// (input index, point). Fails if the halfedge cycles of the DCEL are broken.
+pub fn clip_to_polygon_with_holes(dcel: &mut DCEL, outer: &[Point], holes: &[Vec<Point>], sites: &[(usize, Point)]) -> Result<(), VoronoiError> {
+    let outside = |pt: Point| !polygon_contains(outer, pt) || holes.iter().any(|hole| polygon_contains(hole, pt));
+
+    let mut on_ring = vec![];
+    for ring in Some(outer).into_iter().chain(holes.iter().map(|hole| &hole[..])) {
+        // a ring crossing no edges lies inside the cell of the nearest site
+        let nearest = sites.iter()
+            .min_by_key(|&&(_, pt)| OrderedFloat((pt - ring[0]).dot(pt - ring[0])))
+            .map(|&(index, _)| index);
+        for edge in add_ring(ring, nearest, dcel)? {
+            on_ring.push(edge);
+            on_ring.push(dcel.halfedges[edge].twin);
+        }
+    }
+    let mut is_ring_edge = vec![false; dcel.halfedges.len()];
+    let mut is_ring_vertex = vec![false; dcel.vertices.len()];
+    for edge in on_ring {
+        is_ring_edge[edge] = true;
+        is_ring_vertex[dcel.halfedges[edge].origin] = true;
+    }
+
+    dcel.set_prev()?;
+
+    for (vert, &ring_vertex) in is_ring_vertex.iter().enumerate() {
+        if ring_vertex || !dcel.vertices[vert].alive { continue; }
+        if outside(dcel.vertices[vert].coordinates) {
+            dcel.remove_vertex(vert)?;
+        }
+    }
+
+    // an edge between two points of the rings may still cut across the outside
+    for (edge, &ring_edge) in is_ring_edge.iter().enumerate() {
+        if ring_edge || !dcel.halfedges[edge].is_alive() { continue; }
+        let twin = dcel.halfedges[edge].twin;
+        if !is_ring_vertex[dcel.halfedges[edge].origin] || !is_ring_vertex[dcel.halfedges[twin].origin] { continue; }
+        let midpoint = (dcel.get_origin(edge) + dcel.get_origin(twin)) * 0.5;
+        if outside(midpoint) {
+            dcel.remove_edge(edge);
+        }
+    }
+    Ok(())
+}
+
+/// Clip a DCEL to a convex polygon, given in counterclockwise order.
+///
+/// Each side of the polygon is added with add_line, and every vertex
+/// outside the polygon is removed along with its halfedges. A vertex that
+/// add_line placed on a side counts as lying on it, wherever rounding put it.
+/// Edges along the sides have no site outside them. All the edges of the
+/// DCEL must be bounded. Faces are not affected.
+/// Fails if the halfedge cycles of the DCEL are broken.
+pub fn clip_to_convex_polygon(dcel: &mut DCEL, polygon: &[Point]) -> Result<(), VoronoiError> {
+    // the sides meet at the corners, which add_line splits edges at
+    let mut side_edges = vec![];
+    for (index, &start) in polygon.iter().enumerate() {
+        let end = polygon[(index + 1) % polygon.len()];
+        side_edges.push(add_line([start, end], dcel)?);
+    }
+
+    dcel.set_prev()?;
+
+    let mut vertex_sides = vec![vec![]; dcel.vertices.len()];
+    for (side, edges) in side_edges.into_iter().enumerate() {
+        for edge in edges {
+            let twin = dcel.halfedges[edge].twin;
+            clear_left_site(dcel, twin);
+            for &vertex in &[dcel.halfedges[edge].origin, dcel.halfedges[twin].origin] {
+                if !vertex_sides[vertex].contains(&side) { vertex_sides[vertex].push(side); }
+            }
+        }
+    }
+    for (vert, sides) in vertex_sides.iter().enumerate() {
+        if !dcel.vertices[vert].alive { continue; }
+        let this_pt = dcel.vertices[vert].coordinates;
+        if outside_convex_polygon(this_pt, polygon, sides) {
+            dcel.remove_vertex(vert)?;
+        }
+    }
+    Ok(())
+}
+
+// Is the point strictly outside one of the sides, other than those it lies on?
+fn outside_convex_polygon(pt: Point, polygon: &[Point], on_sides: &[usize]) -> bool {
+    (0..polygon.len()).any(|index| {
+        !on_sides.contains(&index) && orient2d(polygon[index], polygon[(index + 1) % polygon.len()], pt) < 0.
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clockwise_polygon_reversed() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(0.0, 1.0), Point::new(1.0, 0.0)];
+        let normalized = normalize_convex_polygon(&polygon).unwrap();
+        assert_eq!(normalized, vec![Point::new(1.0, 0.0), Point::new(0.0, 1.0), Point::new(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn collinear_vertices_dropped() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(2.0, 0.0),
+                           Point::new(2.0, 2.0), Point::new(2.0, 2.0), Point::new(0.0, 2.0)];
+        let normalized = normalize_convex_polygon(&polygon).unwrap();
+        assert_eq!(normalized.len(), 4);
+    }
+
+    #[test]
+    fn non_convex_polygon_rejected() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(1.0, 0.5), Point::new(2.0, 2.0), Point::new(0.0, 2.0)];
+        assert_eq!(normalize_convex_polygon(&polygon), None);
+    }
+
+    #[test]
+    fn pentagram_rejected() {
+        let pentagon: Vec<Point> = (0..5).map(|i| {
+            let angle = i as f64 * 2.0 * ::std::f64::consts::PI / 5.0;
+            Point::new(angle.cos(), angle.sin())
+        }).collect();
+        let pentagram: Vec<Point> = (0..5).map(|i| pentagon[(2 * i) % 5]).collect();
+        assert!(normalize_convex_polygon(&pentagon).is_some());
+        assert_eq!(normalize_convex_polygon(&pentagram), None);
+    }
+
+    fn square(min: f64, max: f64) -> Vec<Point> {
+        vec![Point::new(min, min), Point::new(max, min), Point::new(max, max), Point::new(min, max)]
+    }
+
+    #[test]
+    fn rings_oriented() {
+        let (outer, holes) = normalize_polygon_with_holes(&square(0.0, 10.0), &[square(4.0, 6.0)]).unwrap();
+        assert_eq!(outer, square(0.0, 10.0));
+        let clockwise: Vec<Point> = square(4.0, 6.0).into_iter().rev().collect();
+        assert_eq!(holes, vec![clockwise]);
+    }
+
+    #[test]
+    fn closing_vertex_dropped() {
+        let mut ring = square(0.0, 10.0);
+        ring.push(Point::new(0.0, 0.0));
+        let (outer, _) = normalize_polygon_with_holes(&ring, &[]).unwrap();
+        assert_eq!(outer.len(), 4);
+    }
+
+    #[test]
+    fn self_intersecting_ring_rejected() {
+        let bowtie = vec![Point::new(0.0, 0.0), Point::new(2.0, 2.0), Point::new(2.0, 0.0), Point::new(0.0, 2.0)];
+        assert_eq!(normalize_polygon_with_holes(&bowtie, &[]), None);
+    }
+
+    #[test]
+    fn misplaced_holes_rejected() {
+        let outer = square(0.0, 10.0);
+        assert_eq!(normalize_polygon_with_holes(&outer, &[square(20.0, 30.0)]), None);
+        assert_eq!(normalize_polygon_with_holes(&outer, &[square(5.0, 15.0)]), None);
+        assert_eq!(normalize_polygon_with_holes(&outer, &[square(2.0, 8.0), square(4.0, 6.0)]), None);
+        assert_eq!(normalize_polygon_with_holes(&outer, &[square(2.0, 5.0), square(5.0, 8.0)]), None);
+    }
+
+    #[test]
+    fn degenerate_polygon_rejected() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(1.0, 1.0), Point::new(2.0, 2.0)];
+        assert_eq!(normalize_convex_polygon(&polygon), None);
+    }
+}
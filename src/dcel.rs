@@ -1,10 +1,24 @@
 use std::fmt;
+use std::cmp::Ordering;
+use ordered_float::OrderedFloat;
+use fnv::FnvHashMap;
 use point::Point;
-use geometry::{Segment, segment_intersection};
+use geometry::{Segment, Weighting, polygon_contains, weighted_distance};
+use predicates::orient2d;
+use error::VoronoiError;
+use bounding_box::{BoundingBox, clamp_to_box, side_offset};
 
 const NIL: usize = !0;
+// Distance, relative to the length of a line, within which a vertex is
+// taken to lie on the line, and an edge to pass through an end of it
+const LINE_TOLERANCE: f64 = 1e-9;
+// a face as the index of its site, its outer boundary and its holes
+pub type SitePolygonWithHoles = (usize, Vec<Point>, Vec<Vec<Point>>);
+// the index of a site, with the neighbors across its edges and those edges
+type SiteNeighbors = (usize, Vec<(Neighbor, Segment)>);
 
 /// Doubly Connected Edge List representation of a subdivision of the plane.
+#[derive(Default)]
 pub struct DCEL {
     /// Vertices
     pub vertices: Vec<Vertex>,
@@ -17,9 +31,7 @@ pub struct DCEL {
 impl DCEL {
     /// Construct an empty DCEL
     pub fn new() -> Self {
-        DCEL {vertices: vec![],
-            halfedges: vec![],
-            faces: vec![]}
+        DCEL::default()
     }
 
     /// Add two halfedges that are twins
@@ -38,28 +50,27 @@ impl DCEL {
     /// Get the origin of a halfedge by index
     pub fn get_origin(&self, edge: usize) -> Point {
         let origin_ind = self.halfedges[edge].origin;
-        return self.vertices[origin_ind].coordinates;
+        self.vertices[origin_ind].coordinates
     }
 
-    /// Set the previous edge of all halfedges
-    /// Assumes that the DCEL is well-formed.
-    pub fn set_prev(&mut self) {
+    /// Set the previous edge of all halfedges.
+    /// Fails if the next pointers of a live halfedge do not lead back to it.
+    pub fn set_prev(&mut self) -> Result<(), VoronoiError> {
         let mut seen_edges = vec![false; self.halfedges.len()];
         for edge_ind in 0..self.halfedges.len() {
-            if seen_edges[edge_ind] { continue; }
-            let mut current_ind = edge_ind;
-            seen_edges[current_ind];
-            loop {
+            if seen_edges[edge_ind] || !self.halfedges[edge_ind].alive { continue; }
+            for current_ind in cycle_edges(self, edge_ind)? {
                 let next_edge = self.halfedges[current_ind].next;
                 self.halfedges[next_edge].prev = current_ind;
-                current_ind = next_edge;
                 seen_edges[current_ind] = true;
-                if current_ind == edge_ind { break; }
             }
         }
+        Ok(())
     }
 
-    fn remove_edge(&mut self, edge: usize) {
+    /// Remove a halfedge and its twin.
+    /// Does not affect faces!!
+    pub fn remove_edge(&mut self, edge: usize) {
         let edge_prev = self.halfedges[edge].prev;
         let edge_next = self.halfedges[edge].next;
         let twin = self.halfedges[edge].twin;
@@ -71,31 +82,139 @@ impl DCEL {
         self.halfedges[twin_prev].next = edge_next;
         self.halfedges[twin_next].prev = edge_prev;
 
+        // keep the endpoints pointing at a remaining halfedge, if they have one
+        let origin = self.halfedges[edge].origin;
+        if self.vertices[origin].incident_edge == edge && twin_next != edge {
+            self.vertices[origin].incident_edge = twin_next;
+        }
+        let twin_origin = self.halfedges[twin].origin;
+        if self.vertices[twin_origin].incident_edge == twin && edge_next != twin {
+            self.vertices[twin_origin].incident_edge = edge_next;
+        }
+
         self.halfedges[edge].alive = false;
         self.halfedges[twin].alive = false;
     }
 
-    fn get_edges_around_vertex(&self, vertex: usize) -> Vec<usize> {
-        let mut result = vec![];
-        let start_edge = self.vertices[vertex].incident_edge;
-        let mut current_edge = start_edge;
-        loop {
-            result.push(current_edge);
-            let current_twin = self.halfedges[current_edge].twin;
-            current_edge = self.halfedges[current_twin].next;
-            if current_edge == start_edge { break; }
+    /// Remove the dead vertices, halfedges and faces, and renumber the rest.
+    ///
+    /// Vertices that no remaining halfedge starts from are removed too.
+    /// Afterwards every entry is alive, every index refers to a remaining
+    /// entry, and the previous edges are set. Returns the new index of
+    /// every old halfedge, or None for the halfedges that were removed.
+    pub fn compact(&mut self) -> Vec<Option<usize>> {
+        let mut vertex_used = vec![false; self.vertices.len()];
+        for halfedge in &self.halfedges {
+            if halfedge.alive && halfedge.origin != NIL { vertex_used[halfedge.origin] = true; }
+        }
+        let vertex_map = new_indices(vertex_used.iter().zip(&self.vertices).map(|(&used, vertex)| used && vertex.alive));
+        let halfedge_map = new_indices(self.halfedges.iter().map(|halfedge| halfedge.alive));
+        let face_map = new_indices(self.faces.iter().map(|face| face.alive));
+        let remap = |map: &[usize], index: usize| if index == NIL { NIL } else { map[index] };
+
+        let old_vertices = ::std::mem::take(&mut self.vertices);
+        for (index, mut vertex) in old_vertices.into_iter().enumerate() {
+            if vertex_map[index] == NIL { continue; }
+            vertex.incident_edge = remap(&halfedge_map, vertex.incident_edge);
+            self.vertices.push(vertex);
+        }
+
+        let old_halfedges = ::std::mem::take(&mut self.halfedges);
+        for (index, mut halfedge) in old_halfedges.into_iter().enumerate() {
+            if halfedge_map[index] == NIL { continue; }
+            halfedge.origin = remap(&vertex_map, halfedge.origin);
+            halfedge.twin = remap(&halfedge_map, halfedge.twin);
+            halfedge.next = remap(&halfedge_map, halfedge.next);
+            halfedge.face = remap(&face_map, halfedge.face);
+            halfedge.prev = NIL;
+            self.halfedges.push(halfedge);
+        }
+
+        let old_faces = ::std::mem::take(&mut self.faces);
+        for (index, mut face) in old_faces.into_iter().enumerate() {
+            if face_map[index] == NIL { continue; }
+            face.outer_component = remap(&halfedge_map, face.outer_component);
+            face.inner_components = face.inner_components.iter().map(|&edge| remap(&halfedge_map, edge)).collect();
+            self.faces.push(face);
+        }
+
+        // the previous edges, and the incident edges of vertices whose
+        // incident edge was removed, follow from the remaining halfedges
+        for index in 0..self.halfedges.len() {
+            let next = self.halfedges[index].next;
+            if next != NIL { self.halfedges[next].prev = index; }
+            let origin = self.halfedges[index].origin;
+            if origin != NIL && self.vertices[origin].incident_edge == NIL {
+                self.vertices[origin].incident_edge = index;
+            }
+        }
+
+        halfedge_map.into_iter().map(|index| if index == NIL { None } else { Some(index) }).collect()
+    }
+
+    fn get_edges_around_vertex(&self, vertex: usize) -> Result<Vec<usize>, VoronoiError> {
+        get_edges_from(self, self.vertices[vertex].incident_edge)
+    }
+
+    /// For each site, the cells across its edges, with the shared edge segments.
+    ///
+    /// The sites are in order of index. A neighbor appears once for every
+    /// edge it shares, so several times if the edge is cut by a hole in the
+    /// clip region. Edges on the boundary of the clip region have the
+    /// `Boundary` neighbor. In an unbounded diagram, the edges running out to
+    /// infinity are left out. This should be used after add_faces.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic on any error reported by `try_site_neighbors`.
+    pub fn site_neighbors(&self) -> Vec<SiteNeighbors> {
+        self.try_site_neighbors().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Lists the neighbors of each site, as for `site_neighbors`.
+    ///
+    /// Fails with `VoronoiError::BrokenCycle` if a face boundary does not
+    /// lead back to where it started.
+    pub fn try_site_neighbors(&self) -> Result<Vec<SiteNeighbors>, VoronoiError> {
+        let mut result: Vec<SiteNeighbors> = vec![];
+        let mut faces: Vec<&Face> = self.faces.iter().filter(|face| face.alive && face.site != NIL).collect();
+        faces.sort_by_key(|face| face.site);
+
+        for face in faces {
+            if result.last().map(|&(site, _)| site) != Some(face.site) {
+                result.push((face.site, vec![]));
+            }
+            let neighbors = &mut result.last_mut().unwrap().1;
+            for &start_edge in Some(&face.outer_component).into_iter().chain(face.inner_components.iter()) {
+                for edge in cycle_edges(self, start_edge)? {
+                    let next_edge = self.halfedges[edge].next;
+                    if self.halfedges[edge].origin == NIL || self.halfedges[next_edge].origin == NIL { continue; }
+                    let twin = self.halfedges[edge].twin;
+                    let twin_site = self.faces[self.halfedges[twin].face].site;
+                    let neighbor = if twin_site == NIL { Neighbor::Boundary } else { Neighbor::Site(twin_site) };
+                    neighbors.push((neighbor, [self.get_origin(edge), self.get_origin(next_edge)]));
+                }
+            }
         }
-        return result;
+        Ok(result)
     }
 
     /// Remove a vertex and all attached halfedges.
     /// Does not affect faces!!
-    pub fn remove_vertex(&mut self, vertex: usize) {
-        let vertex_edges = self.get_edges_around_vertex(vertex);
+    /// Fails if the halfedges around the vertex do not lead back to its incident edge.
+    pub fn remove_vertex(&mut self, vertex: usize) -> Result<(), VoronoiError> {
+        // a vertex whose halfedges are all gone is isolated
+        let incident_edge = self.vertices[vertex].incident_edge;
+        if !self.halfedges[incident_edge].alive {
+            self.vertices[vertex].alive = false;
+            return Ok(());
+        }
+        let vertex_edges = self.get_edges_around_vertex(vertex)?;
         for edge in vertex_edges {
             self.remove_edge(edge);
         }
         self.vertices[vertex].alive = false;
+        Ok(())
     }
 }
 
@@ -129,6 +248,215 @@ impl fmt::Debug for DCEL {
     }
 }
 
+/// Handle of a vertex in a DCEL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VertexId(pub usize);
+
+/// Handle of a halfedge in a DCEL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HalfEdgeId(pub usize);
+
+/// Handle of a face in a DCEL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FaceId(pub usize);
+
+/// Read-only traversal of the DCEL, through typed handles.
+///
+/// Handles must come from the same DCEL. Only live entries are visited, but
+/// indexing with a handle to a removed entry is not checked; compacted
+/// diagrams, as returned by `voronoi`, have none.
+impl DCEL {
+    /// The live vertices
+    pub fn vertex_ids<'a>(&'a self) -> impl Iterator<Item = VertexId> + 'a {
+        self.vertices.iter().enumerate().filter(|&(_, vertex)| vertex.alive).map(|(index, _)| VertexId(index))
+    }
+
+    /// The live halfedges
+    pub fn halfedge_ids<'a>(&'a self) -> impl Iterator<Item = HalfEdgeId> + 'a {
+        self.halfedges.iter().enumerate().filter(|&(_, halfedge)| halfedge.alive).map(|(index, _)| HalfEdgeId(index))
+    }
+
+    /// The live faces
+    pub fn face_ids<'a>(&'a self) -> impl Iterator<Item = FaceId> + 'a {
+        self.faces.iter().enumerate().filter(|&(_, face)| face.alive).map(|(index, _)| FaceId(index))
+    }
+
+    /// The coordinates of a vertex
+    pub fn position(&self, vertex: VertexId) -> Point {
+        self.vertices[vertex.0].coordinates
+    }
+
+    /// The vertex a halfedge starts from, or None if it comes from infinity
+    pub fn origin(&self, edge: HalfEdgeId) -> Option<VertexId> {
+        to_handle(self.halfedges[edge.0].origin).map(VertexId)
+    }
+
+    /// The vertex a halfedge ends at, or None if it runs out to infinity
+    pub fn destination(&self, edge: HalfEdgeId) -> Option<VertexId> {
+        self.origin(self.twin(edge))
+    }
+
+    /// The start and end of a halfedge, as for `origin` and `destination`
+    pub fn endpoints(&self, edge: HalfEdgeId) -> (Option<VertexId>, Option<VertexId>) {
+        (self.origin(edge), self.destination(edge))
+    }
+
+    /// The halfedge running the other way along the same edge
+    pub fn twin(&self, edge: HalfEdgeId) -> HalfEdgeId {
+        HalfEdgeId(self.halfedges[edge.0].twin)
+    }
+
+    /// The next halfedge around the face to the left
+    pub fn next(&self, edge: HalfEdgeId) -> HalfEdgeId {
+        HalfEdgeId(self.halfedges[edge.0].next)
+    }
+
+    /// The previous halfedge around the face to the left, if it has been set
+    pub fn prev(&self, edge: HalfEdgeId) -> Option<HalfEdgeId> {
+        to_handle(self.halfedges[edge.0].prev).map(HalfEdgeId)
+    }
+
+    /// The face to the left of a halfedge, once faces have been added
+    pub fn face(&self, edge: HalfEdgeId) -> Option<FaceId> {
+        to_handle(self.halfedges[edge.0].face).map(FaceId)
+    }
+
+    /// A halfedge on the outer boundary of a face
+    pub fn outer_component(&self, face: FaceId) -> HalfEdgeId {
+        HalfEdgeId(self.faces[face.0].outer_component)
+    }
+
+    /// A halfedge on the boundary of each hole in a face
+    pub fn inner_components<'a>(&'a self, face: FaceId) -> impl Iterator<Item = HalfEdgeId> + 'a {
+        self.faces[face.0].inner_components.iter().map(|&edge| HalfEdgeId(edge))
+    }
+
+    /// The index of the input point whose cell a face is, as for `Face::site`
+    pub fn face_site(&self, face: FaceId) -> Option<usize> {
+        self.faces[face.0].site()
+    }
+
+    /// The halfedges following `edge` around its face, starting with `edge`
+    pub fn cycle<'a>(&'a self, edge: HalfEdgeId) -> Cycle<'a> {
+        Cycle { dcel: self, start: edge.0, current: edge.0, remaining: self.halfedges.len() }
+    }
+
+    /// The halfedges with a face to their left: its outer boundary,
+    /// counterclockwise, then the boundary of each hole, clockwise
+    pub fn face_halfedges<'a>(&'a self, face: FaceId) -> FaceHalfEdges<'a> {
+        FaceHalfEdges {
+            dcel: self,
+            cycle: Some(self.cycle(self.outer_component(face))),
+            holes: self.faces[face.0].inner_components.iter(),
+        }
+    }
+
+    /// The halfedges starting from a vertex, in clockwise order
+    pub fn vertex_halfedges<'a>(&'a self, vertex: VertexId) -> VertexHalfEdges<'a> {
+        let start = self.vertices[vertex.0].incident_edge;
+        VertexHalfEdges { dcel: self, start, current: start, remaining: self.halfedges.len() }
+    }
+
+    /// The faces across the edges of a face, each once, in the order of
+    /// the halfedges of `face_halfedges`
+    pub fn adjacent_faces(&self, face: FaceId) -> ::std::vec::IntoIter<FaceId> {
+        let mut result: Vec<FaceId> = vec![];
+        for edge in self.face_halfedges(face) {
+            if let Some(other) = self.face(self.twin(edge)) {
+                if other != face && !result.contains(&other) {
+                    result.push(other);
+                }
+            }
+        }
+        result.into_iter()
+    }
+}
+
+fn to_handle(index: usize) -> Option<usize> {
+    if index == NIL { None } else { Some(index) }
+}
+
+/// Iterator over the halfedges of a cycle, following next pointers.
+///
+/// If the next pointers never lead back to the start, the iteration stops
+/// after as many halfedges as the DCEL has; `DCEL::validate` reports such cycles.
+#[derive(Debug)]
+pub struct Cycle<'a> {
+    dcel: &'a DCEL,
+    start: usize,
+    current: usize, // NIL once the cycle is done
+    remaining: usize,
+}
+
+impl<'a> Iterator for Cycle<'a> {
+    type Item = HalfEdgeId;
+
+    fn next(&mut self) -> Option<HalfEdgeId> {
+        if self.current >= self.dcel.halfedges.len() || self.remaining == 0 { return None; }
+        self.remaining -= 1;
+        let edge = self.current;
+        self.current = self.dcel.halfedges[edge].next;
+        if self.current == self.start { self.current = NIL; }
+        Some(HalfEdgeId(edge))
+    }
+}
+
+/// Iterator over the halfedges bounding a face, holes included.
+#[derive(Debug)]
+pub struct FaceHalfEdges<'a> {
+    dcel: &'a DCEL,
+    cycle: Option<Cycle<'a>>,
+    holes: ::std::slice::Iter<'a, usize>,
+}
+
+impl<'a> Iterator for FaceHalfEdges<'a> {
+    type Item = HalfEdgeId;
+
+    fn next(&mut self) -> Option<HalfEdgeId> {
+        loop {
+            if let Some(edge) = self.cycle.as_mut().and_then(|cycle| cycle.next()) {
+                return Some(edge);
+            }
+            let hole = *self.holes.next()?;
+            self.cycle = Some(self.dcel.cycle(HalfEdgeId(hole)));
+        }
+    }
+}
+
+/// Iterator over the halfedges starting from a vertex.
+///
+/// Like `Cycle`, it stops after as many halfedges as the DCEL has if it
+/// never comes back around to the start.
+#[derive(Debug)]
+pub struct VertexHalfEdges<'a> {
+    dcel: &'a DCEL,
+    start: usize,
+    current: usize, // NIL once around the vertex
+    remaining: usize,
+}
+
+impl<'a> Iterator for VertexHalfEdges<'a> {
+    type Item = HalfEdgeId;
+
+    fn next(&mut self) -> Option<HalfEdgeId> {
+        if self.current >= self.dcel.halfedges.len() || self.remaining == 0 { return None; }
+        self.remaining -= 1;
+        let edge = self.current;
+        self.current = rotate_around_origin(self.dcel, edge);
+        if self.current == self.start { self.current = NIL; }
+        Some(HalfEdgeId(edge))
+    }
+}
+
+/// The cell on the other side of an edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Neighbor {
+    /// The cell of the site with this index
+    Site(usize),
+    /// Outside the clip region
+    Boundary,
+}
+
 /// A vertex of a DCEL
 pub struct Vertex {
     /// (x, y) coordinates
@@ -145,6 +473,29 @@ impl fmt::Debug for Vertex {
     }
 }
 
+/// An infinite edge of an unbounded Voronoi diagram.
+///
+/// An edge that is a whole line is given as two rays from the same point,
+/// and neither of its halfedges has an origin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    /// The halfedge running out to infinity along the ray.
+    /// Its twin comes back from infinity, and so has no origin.
+    pub halfedge: usize, // index of halfedge
+    /// The start of the ray: the origin of `halfedge`, or a point
+    /// on the edge if the edge is a whole line
+    pub origin: Point,
+    /// Unit vector along the ray
+    pub direction: Point,
+}
+
+impl Ray {
+    /// The point at the given distance along the ray
+    pub fn point_at(&self, distance: f64) -> Point {
+        self.origin + self.direction * distance
+    }
+}
+
 /// A halfedge of a DCEL
 pub struct HalfEdge {
     /// The index of the vertex at the start of the halfedge
@@ -155,6 +506,7 @@ pub struct HalfEdge {
     pub next: usize, // index of halfedge
     face: usize, // index of face
     prev: usize, // index of halfedge
+    site: usize, // index of the site whose cell lies to the left
     alive: bool,
 }
 
@@ -167,7 +519,12 @@ impl fmt::Debug for HalfEdge {
 impl HalfEdge {
     /// Construct an empty halfedge
     pub fn new() -> Self {
-        HalfEdge {origin: NIL, twin: NIL, next: NIL, face: NIL, prev: NIL, alive: true}
+        HalfEdge {origin: NIL, twin: NIL, next: NIL, face: NIL, prev: NIL, site: NIL, alive: true}
+    }
+
+    /// False if the halfedge has been deleted
+    pub fn is_alive(&self) -> bool {
+        self.alive
     }
 }
 
@@ -175,29 +532,85 @@ impl HalfEdge {
 /// A face of a DCEL
 pub struct Face {
     outer_component: usize, // index of halfedge
+    inner_components: Vec<usize>, // index of a halfedge on each hole
+    site: usize, // index of site
     alive: bool,
 }
 
 impl fmt::Display for Face {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "outer: {}", self.outer_component)
+        write!(f, "outer: {}, inner: {:?}, site: {:?}", self.outer_component, self.inner_components, self.site())
     }
 }
 
 impl Face {
     /// Construct a new face, given an attached halfedge index
     pub fn new(edge: usize) -> Self {
-        Face {outer_component: edge, alive: true}
+        Face {outer_component: edge, inner_components: vec![], site: NIL, alive: true}
+    }
+
+    /// The index of the input point whose Voronoi cell this face is.
+    /// None for the unbounded face outside the bounding box, and for holes
+    /// in the clip region.
+    pub fn site(&self) -> Option<usize> {
+        if self.site == NIL { None } else { Some(self.site) }
     }
 }
 
-/// Construct faces for a DCEL.
+/// Contract a halfedge and its twin to a point.
 ///
-/// # Panics
+/// The origin of `edge` is merged into the origin of its twin, which
+/// inherits all the remaining halfedges of both vertices.
+/// The next pointers around both vertices must already be set; if they
+/// do not lead around the vertices, this fails without changing the DCEL.
+pub fn contract_edge(dcel: &mut DCEL, edge: usize) -> Result<(), VoronoiError> {
+    let twin = dcel.halfedges[edge].twin;
+    let from = dcel.halfedges[edge].origin;
+    let to = dcel.halfedges[twin].origin;
+
+    // find the halfedges that lead into the edge and into its twin
+    let into_edge = edge_into(dcel, edge)?;
+    let into_twin = edge_into(dcel, twin)?;
+
+    // the other halfedges leaving 'from' now leave 'to'
+    for current_edge in get_edges_from(dcel, edge)?.into_iter().skip(1) {
+        dcel.halfedges[current_edge].origin = to;
+    }
+
+    let edge_next = dcel.halfedges[edge].next;
+    let twin_next = dcel.halfedges[twin].next;
+    dcel.halfedges[into_edge].next = edge_next;
+    dcel.halfedges[into_twin].next = twin_next;
+    dcel.vertices[to].incident_edge = edge_next;
+
+    dcel.halfedges[edge].alive = false;
+    dcel.halfedges[twin].alive = false;
+    // an edge that has both ends at one vertex already leaves it
+    if from != to { dcel.vertices[from].alive = false; }
+    Ok(())
+}
+
+// the halfedges leaving the origin of `edge`, clockwise from it
+fn get_edges_from(dcel: &DCEL, edge: usize) -> Result<Vec<usize>, VoronoiError> {
+    walk_cycle(dcel, edge, |current| rotate_around_origin(dcel, current))
+}
+
+// the halfedge whose next is `edge`, which comes into the origin of `edge`
+fn edge_into(dcel: &DCEL, edge: usize) -> Result<usize, VoronoiError> {
+    get_edges_from(dcel, edge)?.into_iter()
+        .map(|outgoing| dcel.halfedges[outgoing].twin)
+        .find(|&incoming| dcel.halfedges[incoming].next == edge)
+        .ok_or(VoronoiError::BrokenCycle { halfedge: edge })
+}
+
+/// Construct faces for a DCEL.
 ///
-/// This method will panic if the DCEL has any faces already.
-pub fn add_faces(dcel: &mut DCEL) {
-    if !dcel.faces.is_empty() { panic!("add_faces only works on DCELs with no faces");}
+/// Fails if the DCEL has any faces already, or with `BrokenCycle` if the
+/// next pointers of a halfedge do not lead back to it through live halfedges.
+pub fn add_faces(dcel: &mut DCEL) -> Result<(), VoronoiError> {
+    if !dcel.faces.is_empty() {
+        return Err(VoronoiError::InternalInconsistency("add_faces only works on DCELs with no faces"));
+    }
     let num_halfedges = dcel.halfedges.len();
     let mut seen_edges = vec![false; num_halfedges];
 
@@ -212,144 +625,927 @@ pub fn add_faces(dcel: &mut DCEL) {
         let new_face = Face::new(edge_index);
         dcel.faces.push(new_face);
 
-        let mut current_edge = edge_index;
-        loop {
+        for current_edge in cycle_edges(dcel, edge_index)? {
+            if !dcel.halfedges[current_edge].alive {
+                return Err(VoronoiError::BrokenCycle { halfedge: edge_index });
+            }
             seen_edges[current_edge] = true;
             dcel.halfedges[current_edge].face = face_index;
-            current_edge = dcel.halfedges[current_edge].next;
-            if current_edge == edge_index { break; }
+            if dcel.faces[face_index].site == NIL {
+                dcel.faces[face_index].site = dcel.halfedges[current_edge].site;
+            }
         }
     }
     info!("Generated faces for {} edges.", processed_edges);
+    attach_holes(dcel)
+}
+
+// A clockwise cycle that lies inside a bounded face, without touching its
+// boundary, is a hole in that face rather than a face of its own.
+fn attach_holes(dcel: &mut DCEL) -> Result<(), VoronoiError> {
+    // unbounded faces count as neither holes nor containers
+    let mut areas = Vec::with_capacity(dcel.faces.len());
+    for face in &dcel.faces {
+        let bounded = is_bounded_cycle(dcel, face.outer_component)?;
+        areas.push(if bounded { signed_area(&cycle_polygon(dcel, face.outer_component)?) } else { 0. });
+    }
+
+    for hole_index in 0..dcel.faces.len() {
+        if areas[hole_index] >= 0. { continue; }
+        let hole_edge = dcel.faces[hole_index].outer_component;
+        let hole_vertex = dcel.halfedges[hole_edge].origin;
+        let hole_pt = dcel.vertices[hole_vertex].coordinates;
+
+        // the smallest face around the hole
+        let mut container = NIL;
+        for (face_index, face) in dcel.faces.iter().enumerate() {
+            if !face.alive || areas[face_index] <= 0. { continue; }
+            if container != NIL && areas[face_index] >= areas[container] { continue; }
+            let cycle = cycle_edges(dcel, face.outer_component)?;
+            if cycle.iter().any(|&edge| dcel.halfedges[edge].origin == hole_vertex) { continue; }
+            let polygon: Vec<Point> = cycle.iter().map(|&edge| dcel.get_origin(edge)).collect();
+            if polygon_contains(&polygon, hole_pt) {
+                container = face_index;
+            }
+        }
+        if container == NIL { continue; }
+
+        for edge in cycle_edges(dcel, hole_edge)? {
+            dcel.halfedges[edge].face = container;
+        }
+        dcel.faces[container].inner_components.push(hole_edge);
+        dcel.faces[hole_index].alive = false;
+    }
+    Ok(())
+}
+
+/// Record the site whose cell lies to the left of a halfedge.
+/// Faces take their site from the halfedges around them in add_faces.
+pub fn set_left_site(dcel: &mut DCEL, edge: usize, site: usize) {
+    dcel.halfedges[edge].site = site;
+}
+
+/// Record that no site's cell lies to the left of a halfedge, as outside
+/// the clip region.
+pub fn clear_left_site(dcel: &mut DCEL, edge: usize) {
+    dcel.halfedges[edge].site = NIL;
+}
+
+/// The site whose cell lies to the left of a halfedge, if it is recorded.
+pub fn left_site(dcel: &DCEL, edge: usize) -> Option<usize> {
+    let site = dcel.halfedges[edge].site;
+    if site == NIL { None } else { Some(site) }
+}
+
+/// Assign a site to the bounded faces that have none.
+///
+/// A region that lies entirely within one cell (for instance, a diagram of
+/// a single site) has no Voronoi edges to carry the site to its face.
+/// Such a face is convex, and belongs to the site nearest its vertex centroid.
+/// Sites are given as (input index, point). For a weighted diagram, weights
+/// holds the weight of each site, by input index, and nearness is by the
+/// weighted distance; it is empty for a Voronoi diagram.
+pub fn label_enclosed_faces(dcel: &mut DCEL, sites: &[(usize, Point)], weights: &[f64], weighting: Weighting) -> Result<(), VoronoiError> {
+    label_enclosed_faces_with(dcel, |centroid| sites.iter()
+        .min_by_key(|&&(index, pt)| OrderedFloat(weighted_distance(weighting, pt, weights.get(index).cloned().unwrap_or(0.), centroid)))
+        .map(|&(index, _)| index))
+}
+
+/// Assign a site to the bounded faces that have none, as `site_at` gives
+/// it for their vertex centroid, as in `label_enclosed_faces`.
+pub fn label_enclosed_faces_with<F: Fn(Point) -> Option<usize>>(dcel: &mut DCEL, site_at: F) -> Result<(), VoronoiError> {
+    for face_index in 0..dcel.faces.len() {
+        let face = &dcel.faces[face_index];
+        if !face.alive || face.site != NIL { continue; }
+        let polygon = face_polygon(dcel, face)?;
+        if signed_area(&polygon) <= 0. { continue; }
+
+        let sum = polygon.iter().fold(Point::new(0., 0.), |sum, &pt| sum + pt);
+        let centroid = sum * (1. / polygon.len() as f64);
+        if let Some(site) = site_at(centroid) {
+            dcel.faces[face_index].site = site;
+        }
+    }
+    Ok(())
 }
 
-// does not handle the case where line goes through dcel vertex
 /// Add a line segment to a DCEL.
 ///
-/// Vertices and halfedges are constructed and mutated as necessary.
-/// Faces are not affected. This should be used before add_faces.
-pub fn add_line(seg: Segment, dcel: &mut DCEL) {
-    let mut intersections = get_line_intersections(seg, dcel);
-    intersections.sort_by(|a, b| a.0.cmp(&b.0));
-    let start_pt = if seg[0] < seg[1] { seg[0] } else { seg[1] };
-    let end_pt   = if seg[0] < seg[1] { seg[1] } else { seg[0] };
+/// The line is split at every vertex it passes through and every edge it
+/// crosses, and the edges it crosses are split where it crosses them.
+/// Where the line runs along an existing edge, that edge is kept and no
+/// new edge is added beside it. Vertices within rounding distance of the
+/// line count as lying on it, and an edge that passes within rounding
+/// distance of an end of the line is split at that end, so that lines
+/// meeting at their ends meet at one vertex. New halfedges have no site.
+/// Faces are not affected. This should be used before add_faces, on a
+/// DCEL whose edges are all bounded.
+///
+/// Returns the halfedges running along the line, in order from its start.
+/// Fails if the halfedges around a vertex on the line do not form a cycle.
+pub fn add_line(seg: Segment, dcel: &mut DCEL) -> Result<Vec<usize>, VoronoiError> {
+    let [start_pt, end_pt] = seg;
+    let direction = end_pt - start_pt;
+    let length = direction.dot(direction).sqrt();
+    let tolerance = LINE_TOLERANCE * length;
+    let position = |pt: Point| (pt - start_pt).dot(direction);
+    // which side of the line a point is on, or zero if it is on the line
+    let side = |pt: Point| {
+        let orientation = orient2d(start_pt, end_pt, pt);
+        if orientation.abs() <= tolerance * length { 0. } else { orientation }
+    };
+    let on_line = |pt: Point| side(pt) == 0. && (-tolerance * length..=(length + tolerance) * length).contains(&position(pt));
 
-    let (mut line_needs_next, mut line_needs_prev, _) = add_twins_from_pt(start_pt, dcel);
-    dcel.halfedges[line_needs_prev].next = line_needs_next;
-    let prev_pt = start_pt;
+    let mut stops = vec![];
+    let mut splits: Vec<(usize, Point)> = vec![];
+    let mut seen_halfedges = vec![false; dcel.halfedges.len()];
+    for (edge, halfedge) in dcel.halfedges.iter().enumerate() {
+        let twin = halfedge.twin;
+        if seen_halfedges[edge] || !halfedge.alive { continue; }
+        seen_halfedges[edge] = true;
+        seen_halfedges[twin] = true;
+        if halfedge.origin == NIL || dcel.halfedges[twin].origin == NIL { continue; }
+        let (edge_start, edge_end) = (halfedge.origin, dcel.halfedges[twin].origin);
+        let [a, b] = [dcel.vertices[edge_start].coordinates, dcel.vertices[edge_end].coordinates];
 
-    for (int_pt, this_cut_edge) in intersections {
-        let (new_line_needs_next, new_line_needs_prev, new_pt_ind) = add_twins_from_pt(int_pt, dcel);
-        dcel.halfedges[line_needs_prev].origin = new_pt_ind;
+        // the endpoints of the edge that lie on the line are stops as they are
+        for &(vertex, pt) in &[(edge_start, a), (edge_end, b)] {
+            if on_line(pt) { stops.push(vertex); }
+        }
 
-        let mut cut_edge = this_cut_edge;
-        if makes_left_turn(prev_pt, int_pt, dcel.get_origin(this_cut_edge)) {
-            cut_edge = dcel.halfedges[cut_edge].twin;
+        // an edge that the line ends on, or along, is split at the ends
+        let ends_on_edge: Vec<Point> = seg.iter().cloned().filter(|&pt| passes_near([a, b], pt, tolerance)).collect();
+        if !ends_on_edge.is_empty() {
+            splits.extend(ends_on_edge.into_iter().map(|pt| (edge, pt)));
+            continue;
+        }
+        let (side_a, side_b) = (side(a), side(b));
+        if side_a * side_b < 0. && orient2d(a, b, start_pt) * orient2d(a, b, end_pt) < 0. {
+            splits.push((edge, line_crossing(seg, [a, b], side_a, side_b)));
         }
+    }
 
-        let old_cut_next = dcel.halfedges[cut_edge].next;
-        let old_cut_twin = dcel.halfedges[cut_edge].twin;
-        dcel.halfedges[cut_edge].next = line_needs_prev;
+    // an edge along the line may be split twice, from its origin outwards
+    splits.sort_by_key(|&(edge, pt)| (edge, OrderedFloat((pt - dcel.get_origin(edge)).dot(pt - dcel.get_origin(edge)))));
+    // crossings that round to the same point meet at one vertex
+    let mut last_split = (NIL, NIL);
+    for (edge, pt) in splits {
+        let piece = if last_split.0 == edge { last_split.1 } else { edge };
+        let vertex = match stops.iter().find(|&&vertex| dcel.vertices[vertex].coordinates == pt) {
+            Some(&vertex) => vertex,
+            None => {
+                dcel.vertices.push(Vertex { coordinates: pt, incident_edge: NIL, alive: true });
+                stops.push(dcel.vertices.len() - 1);
+                dcel.vertices.len() - 1
+            }
+        };
+        let rest = split_edge(dcel, piece, vertex)?;
+        last_split = (edge, rest);
+    }
 
-        let cut_ext_ind = dcel.halfedges.len();
-        let cut_ext_he = HalfEdge { origin: new_pt_ind, next: old_cut_next, twin: old_cut_twin, face: NIL, prev: NIL, alive: true };
-        dcel.halfedges.push(cut_ext_he);
-        dcel.halfedges[line_needs_next].next = cut_ext_ind;
+    // the ends of the line that touch nothing are new vertices
+    for &pt in &seg {
+        let near = |vertex: &usize| (dcel.vertices[*vertex].coordinates - pt).dot(dcel.vertices[*vertex].coordinates - pt) <= tolerance * tolerance;
+        if !stops.iter().any(near) {
+            stops.push(dcel.vertices.len());
+            dcel.vertices.push(Vertex { coordinates: pt, incident_edge: NIL, alive: true });
+        }
+    }
 
-        let old_twin_next = dcel.halfedges[old_cut_twin].next;
-        dcel.halfedges[old_cut_twin].next = new_line_needs_next;
+    stops.sort_by_key(|&vertex| (OrderedFloat(position(dcel.vertices[vertex].coordinates)), vertex));
+    stops.dedup();
+    let mut line_edges = vec![];
+    for pair in stops.windows(2) {
+        let existing = outgoing_edges(dcel, pair[0])?.into_iter()
+            .find(|&edge| dcel.halfedges[dcel.halfedges[edge].twin].origin == pair[1]);
+        line_edges.push(match existing {
+            Some(edge) => edge,
+            None => join_vertices(dcel, pair[0], pair[1])?,
+        });
+    }
+    Ok(line_edges)
+}
 
-        let twin_ext_ind = dcel.halfedges.len();
-        let twin_ext_he = HalfEdge { origin: new_pt_ind, next: old_twin_next, twin: cut_edge, face: NIL, prev: NIL, alive: true };
-        dcel.halfedges.push(twin_ext_he);
-        dcel.halfedges[new_line_needs_prev].next = twin_ext_ind;
+// Does the edge pass within `tolerance` of the point, between its ends,
+// without either end lying that close to it?
+fn passes_near(edge: Segment, pt: Point, tolerance: f64) -> bool {
+    let [a, b] = edge;
+    let length = (b - a).dot(b - a).sqrt();
+    let near = |end: Point| (end - pt).dot(end - pt) <= tolerance * tolerance;
+    !near(a) && !near(b) && (pt - a).dot(b - a) > 0. && (pt - b).dot(a - b) > 0.
+        && (b - a).cross(pt - a).abs() <= tolerance * length
+}
+
+// Where a segment crosses an edge whose ends have the given orientations
+// relative to it. The crossing lies exactly on a horizontal or vertical
+// segment, and is measured from the nearer end of the edge; an edge of
+// slope one through a corner of the bounding box meets it exactly.
+fn line_crossing(seg: Segment, edge: Segment, orient_a: f64, orient_b: f64) -> Point {
+    let [a, b] = if orient_a.abs() <= orient_b.abs() { edge } else { [edge[1], edge[0]] };
+    if seg[0].x() == seg[1].x() {
+        let x = seg[0].x();
+        Point::new(x, a.y() + (x - a.x()) * ((b.y() - a.y()) / (b.x() - a.x())))
+    } else if seg[0].y() == seg[1].y() {
+        let y = seg[0].y();
+        Point::new(a.x() + (y - a.y()) * ((b.x() - a.x()) / (b.y() - a.y())), y)
+    } else {
+        edge[0] + (edge[1] - edge[0]) * (orient_a / (orient_a - orient_b))
+    }
+}
+
+/// Add a vertex with no halfedges at a point, and return its index.
+pub fn add_vertex(dcel: &mut DCEL, pt: Point) -> usize {
+    dcel.vertices.push(Vertex { coordinates: pt, incident_edge: NIL, alive: true });
+    dcel.vertices.len() - 1
+}
+
+/// Add a path of edges through existing vertices, in order, fitting each
+/// edge into the rotation of the halfedges around its ends.
+///
+/// The halfedges running along the path take the site `left`, and their
+/// twins the site `right`. The path must not cross itself or any edge
+/// already in the DCEL. Faces are not affected. This should be used before add_faces.
+///
+/// Returns the halfedges running along the path, in order.
+/// Fails if the halfedges around a vertex on the path do not form a cycle.
+pub fn add_path(dcel: &mut DCEL, path: &[usize], left: Option<usize>, right: Option<usize>) -> Result<Vec<usize>, VoronoiError> {
+    let mut path_edges = vec![];
+    for pair in path.windows(2) {
+        let edge = join_vertices(dcel, pair[0], pair[1])?;
+        let twin = dcel.halfedges[edge].twin;
+        dcel.halfedges[edge].site = left.unwrap_or(NIL);
+        dcel.halfedges[twin].site = right.unwrap_or(NIL);
+        path_edges.push(edge);
+    }
+    Ok(path_edges)
+}
+
+/// The vertices at the ends of edges, found by where they lie, so that ends
+/// within `snap` of each other can be made one vertex.
+pub struct Ends {
+    snap: f64,
+    vertices: Vec<usize>,
+    cells: FnvHashMap<(i64, i64), Vec<usize>>,
+}
+
+impl Ends {
+    /// No ends yet, to be made one vertex within `snap` of each other.
+    pub fn new(snap: f64) -> Self {
+        Ends { snap, vertices: vec![], cells: FnvHashMap::default() }
+    }
+
+    /// The corners of the box as new vertices, which are the first ends.
+    pub fn with_corners(dcel: &mut DCEL, bbox: &BoundingBox, snap: f64) -> Self {
+        let mut ends = Ends::new(snap);
+        for &corner in &bbox.corners() {
+            let vertex = add_vertex(dcel, corner);
+            ends.insert(dcel, vertex);
+        }
+        ends
+    }
+
+    /// The vertices, in the order they were added.
+    pub fn vertices(&self) -> &[usize] {
+        &self.vertices
+    }
+
+    // the cell of a grid with sides of `snap` in which a point lies
+    fn cell(&self, pt: Point) -> (i64, i64) {
+        ((pt.x() / self.snap).floor() as i64, (pt.y() / self.snap).floor() as i64)
+    }
 
-        dcel.halfedges[cut_edge].twin = twin_ext_ind;
-        dcel.halfedges[old_cut_twin].twin = cut_ext_ind;
+    /// The first vertex added within `snap` of a point, if any.
+    pub fn find(&self, dcel: &DCEL, pt: Point) -> Option<usize> {
+        let (col, row) = self.cell(pt);
+        let mut found: Option<usize> = None;
+        for cell in (col - 1..col + 2).flat_map(|col| (row - 1..row + 2).map(move |row| (col, row))) {
+            for &vertex in self.cells.get(&cell).map_or(&[][..], |vertices| &vertices[..]) {
+                let offset = dcel.vertices[vertex].coordinates - pt;
+                if offset.dot(offset) <= self.snap * self.snap && found.map(|first| vertex < first).unwrap_or(true) {
+                    found = Some(vertex);
+                }
+            }
+        }
+        found
+    }
 
-        line_needs_next = new_line_needs_next;
-        line_needs_prev = new_line_needs_prev;
+    /// Add a vertex of the DCEL as an end.
+    pub fn insert(&mut self, dcel: &DCEL, vertex: usize) {
+        let cell = self.cell(dcel.vertices[vertex].coordinates);
+        self.cells.entry(cell).or_default().push(vertex);
+        self.vertices.push(vertex);
     }
+}
 
-    dcel.halfedges[line_needs_next].next = line_needs_prev;
-    let end_vertex_ind = dcel.vertices.len();
-    let end_vertex = Vertex { coordinates: end_pt, incident_edge: line_needs_prev, alive: true };
-    dcel.vertices.push(end_vertex);
-    dcel.halfedges[line_needs_prev].origin = end_vertex_ind;
+/// The vertex at the end of an edge, shared with the other ends within their
+/// `snap` of it. An end on a side of the box, numbered counterclockwise from
+/// the bottom, is put exactly on it.
+pub fn end_vertex(dcel: &mut DCEL, ends: &mut Ends, pt: Point, side: Option<usize>, bbox: &BoundingBox) -> usize {
+    let mut pt = clamp_to_box(pt, bbox);
+    match side {
+        Some(0) => pt = Point::new(pt.x(), bbox.min.y()),
+        Some(1) => pt = Point::new(bbox.max.x(), pt.y()),
+        Some(2) => pt = Point::new(pt.x(), bbox.max.y()),
+        Some(_) => pt = Point::new(bbox.min.x(), pt.y()),
+        None => {}
+    }
+    if let Some(vertex) = ends.find(dcel, pt) { return vertex; }
+    let vertex = add_vertex(dcel, pt);
+    ends.insert(dcel, vertex);
+    vertex
 }
 
-/// Do the three points, in this order, make a left turn?
-pub fn makes_left_turn(pt1: Point, pt2: Point, pt3: Point) -> bool {
-    let x1 = pt1.x();
-    let x2 = pt2.x();
-    let x3 = pt3.x();
-    let y1 = pt1.y();
-    let y2 = pt2.y();
-    let y3 = pt3.y();
+/// Add the sides of the box, split at the ends within their `snap` of them,
+/// starting from the corners. Each stretch of a side takes the site that
+/// `site_at` gives for its middle.
+pub fn close_box<F: Fn(Point) -> Option<usize>>(dcel: &mut DCEL, ends: &Ends, bbox: &BoundingBox, site_at: F) -> Result<(), VoronoiError> {
+    let corners = bbox.corners();
+    for side in 0..4 {
+        let (from, to) = (corners[side], corners[(side + 1) % 4]);
+        let mut on_side: Vec<usize> = ends.vertices().iter().cloned().filter(|&vertex| side_offset(dcel.vertices[vertex].coordinates, side, bbox).abs() <= ends.snap).collect();
+        on_side.sort_by_key(|&vertex| OrderedFloat((dcel.vertices[vertex].coordinates - from).dot(to - from)));
+        for pair in on_side.windows(2) {
+            let middle = (dcel.vertices[pair[0]].coordinates + dcel.vertices[pair[1]].coordinates) * 0.5;
+            add_path(dcel, pair, site_at(middle), None)?;
+        }
+    }
+    Ok(())
+}
 
-    (x2 - x1) * (y3 - y1) - (y2 - y1) * (x3 - x1) > 0.
+/// Bend an edge into a path through the given points, in order from the
+/// origin of the halfedge, adding a vertex at each.
+///
+/// The halfedges of the path keep the sites of the halfedge and its twin.
+/// This should be used before add_faces.
+pub fn bend_edge(dcel: &mut DCEL, edge: usize, points: &[Point]) -> Result<(), VoronoiError> {
+    let mut rest = edge;
+    for &pt in points {
+        let vertex = dcel.vertices.len();
+        dcel.vertices.push(Vertex { coordinates: pt, incident_edge: NIL, alive: true });
+        rest = split_edge(dcel, rest, vertex)?;
+    }
+    Ok(())
 }
 
-fn add_twins_from_pt(start_pt: Point, dcel: &mut DCEL) -> (usize, usize, usize) {
-    let (twin1, twin2) = dcel.add_twins();
+// Split a halfedge and its twin at a vertex on the edge. The halfedge keeps
+// its origin and now ends at the vertex; returns the new halfedge from the
+// vertex to the old end of the halfedge.
+fn split_edge(dcel: &mut DCEL, edge: usize, vertex: usize) -> Result<usize, VoronoiError> {
+    let twin = dcel.halfedges[edge].twin;
+    let (rest, rest_twin) = (dcel.halfedges.len(), dcel.halfedges.len() + 1);
+    let (edge_next, twin_next) = (dcel.halfedges[edge].next, dcel.halfedges[twin].next);
+    let (edge_site, twin_site) = (dcel.halfedges[edge].site, dcel.halfedges[twin].site);
 
-    let start_vertex = Vertex { coordinates: start_pt, incident_edge: twin1, alive: true };
-    let start_vertex_ind = dcel.vertices.len();
-    dcel.vertices.push(start_vertex);
+    dcel.halfedges.push(HalfEdge { origin: vertex, next: edge_next, twin, face: NIL, prev: NIL, site: edge_site, alive: true });
+    dcel.halfedges.push(HalfEdge { origin: vertex, next: twin_next, twin: edge, face: NIL, prev: NIL, site: twin_site, alive: true });
+    dcel.halfedges[edge].twin = rest_twin;
+    dcel.halfedges[twin].twin = rest;
+    attach_to_vertex(dcel, vertex, rest)?;
+    attach_to_vertex(dcel, vertex, rest_twin)?;
+    Ok(rest)
+}
 
-    dcel.halfedges[twin1].origin = start_vertex_ind;
+// the halfedges leaving a vertex, none if it is isolated
+fn outgoing_edges(dcel: &DCEL, vertex: usize) -> Result<Vec<usize>, VoronoiError> {
+    let incident_edge = dcel.vertices[vertex].incident_edge;
+    if incident_edge == NIL { return Ok(vec![]); }
+    get_edges_from(dcel, incident_edge)
+}
 
-    (twin1, twin2, start_vertex_ind)
+// Add an edge between two vertices, fitting it into the rotation around
+// each. Returns the new halfedge running from `from` to `to`.
+fn join_vertices(dcel: &mut DCEL, from: usize, to: usize) -> Result<usize, VoronoiError> {
+    let (edge, twin) = dcel.add_twins();
+    dcel.halfedges[edge].origin = from;
+    dcel.halfedges[twin].origin = to;
+    attach_to_vertex(dcel, from, edge)?;
+    attach_to_vertex(dcel, to, twin)?;
+    Ok(edge)
 }
 
-fn get_line_intersections(seg: Segment, dcel: &DCEL) -> Vec<(Point, usize)> {
-    let mut intersections = vec![];
-    let mut seen_halfedges = vec![false; dcel.halfedges.len()];
-    for (index, halfedge) in dcel.halfedges.iter().enumerate() {
-        let twin = halfedge.twin;
-        if seen_halfedges[index] || seen_halfedges[twin] || !halfedge.alive { continue; }
-        let this_seg = [dcel.get_origin(index), dcel.get_origin(twin)];
-        let this_intersection = segment_intersection(seg, this_seg);
-        if let Some(int_pt) = this_intersection { intersections.push((int_pt, index)); }
-        seen_halfedges[index] = true;
-        seen_halfedges[twin] = true;
+// Fit a halfedge leaving a vertex into the rotation of the halfedges around
+// the vertex, setting the next pointers of its twin and of the halfedge
+// coming in just counterclockwise of it. The halfedge must not yet be in
+// any rotation, and its twin must have an origin.
+fn attach_to_vertex(dcel: &mut DCEL, vertex: usize, outgoing: usize) -> Result<(), VoronoiError> {
+    let incoming = dcel.halfedges[outgoing].twin;
+    let others = outgoing_edges(dcel, vertex)?;
+    if others.is_empty() {
+        dcel.halfedges[incoming].next = outgoing;
+        dcel.vertices[vertex].incident_edge = outgoing;
+        return Ok(());
+    }
+
+    // the new halfedge goes clockwise after the one just counterclockwise of it
+    let center = dcel.vertices[vertex].coordinates;
+    let direction_of = |edge: usize| dcel.get_origin(dcel.halfedges[edge].twin);
+    let new_direction = direction_of(outgoing);
+    let by_angle = |&first: &usize, &second: &usize| angle_order(center, direction_of(first), direction_of(second));
+    let after = others.iter().cloned()
+        .filter(|&other| angle_order(center, direction_of(other), new_direction) == Ordering::Greater)
+        .min_by(&by_angle)
+        .or_else(|| others.iter().cloned().min_by(&by_angle))
+        .ok_or(VoronoiError::BrokenCycle { halfedge: outgoing })?;
+    let after_twin = dcel.halfedges[after].twin;
+    dcel.halfedges[incoming].next = dcel.halfedges[after_twin].next;
+    dcel.halfedges[after_twin].next = outgoing;
+    Ok(())
+}
+
+// The order of the directions from center to a and to b, counterclockwise
+// from the positive x axis. Exact, using orient2d.
+fn angle_order(center: Point, a: Point, b: Point) -> Ordering {
+    let upper = |pt: Point| pt.y() > center.y() || (pt.y() == center.y() && pt.x() > center.x());
+    match (upper(a), upper(b)) {
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        _ => 0.0.partial_cmp(&orient2d(center, a, b)).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Add a closed ring of line segments to a DCEL, splitting the edges it crosses.
+///
+/// Each side of the ring is added with add_line, so the ring may pass
+/// through vertices of the DCEL and run along its edges, but must not
+/// cross itself. The halfedges running along the ring in the given order
+/// take the site of the cell to their left; if the ring meets no edges they
+/// take `fallback_site`. The halfedges running the other way have no site.
+/// Faces are not affected. This should be used before add_faces.
+///
+/// Returns the halfedges running along the ring, in order.
+/// Fails if the halfedges around a vertex on the ring do not form a cycle.
+pub fn add_ring(ring: &[Point], fallback_site: Option<usize>, dcel: &mut DCEL) -> Result<Vec<usize>, VoronoiError> {
+    let mut forward_edges = vec![];
+    for (index, &start) in ring.iter().enumerate() {
+        forward_edges.extend(add_line([start, ring[(index + 1) % ring.len()]], dcel)?);
+    }
+    let mut on_ring = vec![false; dcel.halfedges.len()];
+    for &edge in &forward_edges {
+        on_ring[edge] = true;
+        on_ring[dcel.halfedges[edge].twin] = true;
+        clear_left_site(dcel, dcel.halfedges[edge].twin);
     }
-    return intersections;
+
+    // a new piece of the ring is in the cell of the edge before it around
+    // that cell, unless that edge is on the ring too
+    for &edge in &forward_edges {
+        if dcel.halfedges[edge].site != NIL { continue; }
+        for outgoing in outgoing_edges(dcel, dcel.halfedges[edge].origin)? {
+            let incoming = dcel.halfedges[outgoing].twin;
+            if dcel.halfedges[incoming].next == edge && !on_ring[incoming] {
+                dcel.halfedges[edge].site = dcel.halfedges[incoming].site;
+            }
+        }
+    }
+
+    // between the edges it meets, the ring stays in the same cell
+    let labelled = forward_edges.iter().position(|&edge| dcel.halfedges[edge].site != NIL);
+    let mut site = match labelled {
+        Some(position) => dcel.halfedges[forward_edges[position]].site,
+        None => fallback_site.unwrap_or(NIL),
+    };
+    let start = labelled.unwrap_or(0);
+    for offset in 0..forward_edges.len() {
+        let edge = forward_edges[(start + offset) % forward_edges.len()];
+        if dcel.halfedges[edge].site == NIL {
+            dcel.halfedges[edge].site = site;
+        } else {
+            site = dcel.halfedges[edge].site;
+        }
+    }
+    Ok(forward_edges)
 }
 
 /// Constructs the line segments of the Voronoi diagram.
 pub fn make_line_segments(dcel: &DCEL) -> Vec<Segment> {
     let mut result = vec![];
     for halfedge in &dcel.halfedges {
-        if halfedge.origin != NIL && halfedge.next != NIL && halfedge.alive {
-            if dcel.halfedges[halfedge.next].origin != NIL {
-                result.push([dcel.vertices[halfedge.origin].coordinates,
-                    dcel.get_origin(halfedge.next)])
-            }
+        if halfedge.origin != NIL && halfedge.next != NIL && halfedge.alive
+            && dcel.halfedges[halfedge.next].origin != NIL {
+            result.push([dcel.vertices[halfedge.origin].coordinates,
+                dcel.get_origin(halfedge.next)])
         }
     }
     result
 }
 
 /// Constructs the faces of the Voronoi diagram.
+///
+/// The polygons are ordered by the index of their site in the input. A site
+/// without a face, as a duplicate or a site outside the box, has no polygon,
+/// so use `make_site_polygons` to match polygons to input points.
+///
+/// # Panics
+///
+/// This function will panic on any error reported by `try_make_polygons`.
 pub fn make_polygons(dcel: &DCEL) -> Vec<Vec<Point>> {
+    try_make_polygons(dcel).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Constructs the faces of the Voronoi diagram, as for `make_polygons`.
+///
+/// Fails with `VoronoiError::BrokenCycle` if a face boundary does not
+/// lead back to where it started.
+pub fn try_make_polygons(dcel: &DCEL) -> Result<Vec<Vec<Point>>, VoronoiError> {
+    let faces = try_make_site_polygons_with_holes(dcel)?;
+    Ok(faces.into_iter().map(|(_, poly, _)| poly).collect())
+}
+
+/// Constructs the faces of the Voronoi diagram, each paired with the
+/// index of the input point that generated it.
+///
+/// The pairs are ordered by site index. A cell that meets the clip region
+/// in several pieces has one polygon for each. The polygons are the outer
+/// boundaries of the faces; see `make_site_polygons_with_holes` for their holes.
+///
+/// # Panics
+///
+/// This function will panic if a face boundary is broken, as for `make_polygons`.
+pub fn make_site_polygons(dcel: &DCEL) -> Vec<(usize, Vec<Point>)> {
+    make_site_polygons_with_holes(dcel).into_iter().map(|(site, poly, _)| (site, poly)).collect()
+}
+
+/// Constructs the faces of the Voronoi diagram, each paired with the
+/// index of the input point that generated it and followed by its holes.
+///
+/// Outer boundaries are counterclockwise, holes are clockwise.
+/// The faces are ordered by site index. Unbounded faces are left out.
+///
+/// # Panics
+///
+/// This function will panic on any error reported by `try_make_site_polygons_with_holes`.
+pub fn make_site_polygons_with_holes(dcel: &DCEL) -> Vec<SitePolygonWithHoles> {
+    try_make_site_polygons_with_holes(dcel).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Constructs the faces of the Voronoi diagram with their holes, as for
+/// `make_site_polygons_with_holes`.
+///
+/// Fails with `VoronoiError::BrokenCycle` if a face boundary does not
+/// lead back to where it started.
+pub fn try_make_site_polygons_with_holes(dcel: &DCEL) -> Result<Vec<SitePolygonWithHoles>, VoronoiError> {
     let mut result = vec![];
     for face in &dcel.faces {
-        if !face.alive { continue; }
-        let mut this_poly = vec![];
-        let start_edge = face.outer_component;
-        let mut current_edge = start_edge;
-        loop {
-            this_poly.push(dcel.get_origin(current_edge));
-            current_edge = dcel.halfedges[current_edge].next;
-            if current_edge == start_edge { break; }
+        if !face.alive || !is_bounded_cycle(dcel, face.outer_component)? { continue; }
+        if let Some(site) = face.site() {
+            let holes = face.inner_components.iter().map(|&edge| cycle_polygon(dcel, edge)).collect::<Result<_, _>>()?;
+            result.push((site, face_polygon(dcel, face)?, holes));
         }
-        result.push(this_poly);
     }
+    result.sort_by_key(|&(site, _, _)| site);
+    Ok(result)
+}
 
-    // remove the outer face
-    result.sort_by(|a, b| a.len().cmp(&b.len()));
-    result.pop();
+fn face_polygon(dcel: &DCEL, face: &Face) -> Result<Vec<Point>, VoronoiError> {
+    cycle_polygon(dcel, face.outer_component)
+}
 
-    return result;
+fn cycle_polygon(dcel: &DCEL, start_edge: usize) -> Result<Vec<Point>, VoronoiError> {
+    Ok(cycle_edges(dcel, start_edge)?.into_iter().map(|edge| dcel.get_origin(edge)).collect())
+}
+
+// does the cycle avoid running out to infinity?
+fn is_bounded_cycle(dcel: &DCEL, start_edge: usize) -> Result<bool, VoronoiError> {
+    Ok(cycle_edges(dcel, start_edge)?.iter().all(|&edge| dcel.halfedges[edge].origin != NIL))
+}
+
+// the halfedges following start_edge around its face
+fn cycle_edges(dcel: &DCEL, start_edge: usize) -> Result<Vec<usize>, VoronoiError> {
+    walk_cycle(dcel, start_edge, |edge| dcel.halfedges[edge].next)
+}
+
+// the next halfedge clockwise around the origin of `edge`, or NIL
+fn rotate_around_origin(dcel: &DCEL, edge: usize) -> usize {
+    let twin = dcel.halfedges[edge].twin;
+    dcel.halfedges.get(twin).map_or(NIL, |twin| twin.next)
+}
+
+// The halfedges from start_edge on, taking a step from each to the next,
+// until the walk comes back to start_edge. A walk that runs off the DCEL,
+// or takes more steps than there are halfedges, will never come back.
+fn walk_cycle<F: Fn(usize) -> usize>(dcel: &DCEL, start_edge: usize, step: F) -> Result<Vec<usize>, VoronoiError> {
+    let mut result = vec![];
+    let mut current_edge = start_edge;
+    loop {
+        if current_edge >= dcel.halfedges.len() || result.len() == dcel.halfedges.len() {
+            return Err(VoronoiError::BrokenCycle { halfedge: start_edge });
+        }
+        result.push(current_edge);
+        current_edge = step(current_edge);
+        if current_edge == start_edge { return Ok(result); }
+    }
+}
+
+// the new index of each entry when the dead entries are dropped, or NIL
+fn new_indices<I: Iterator<Item = bool>>(alive: I) -> Vec<usize> {
+    let mut next_index = 0;
+    alive.map(|alive| {
+        if !alive { return NIL; }
+        next_index += 1;
+        next_index - 1
+    }).collect()
+}
+
+// positive for counterclockwise polygons
+pub fn signed_area(poly: &[Point]) -> f64 {
+    let mut area = 0.;
+    for (index, &pt) in poly.iter().enumerate() {
+        area += pt.cross(poly[(index + 1) % poly.len()]);
+    }
+    area / 2.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voronoi::{voronoi, voronoi_with_options, voronoi_unbounded, VoronoiOptions};
+    use validate::{assert_valid, Validated};
+    use error::VoronoiError;
+    use rand::{Rng, SeedableRng, StdRng};
+    use bounding_box::BoundingBox;
+
+    fn random_points(num_pts: usize) -> Vec<Point> {
+        let mut rng: StdRng = SeedableRng::from_seed(&[99][..]);
+        (0..num_pts).map(|_| Point::new(rng.next_f64() * 800., rng.next_f64() * 800.)).collect()
+    }
+
+    #[test]
+    fn compacted_indices_are_valid() {
+        let vor_diagram = voronoi(random_points(300), 800.).validated();
+        assert!(vor_diagram.vertices.iter().all(|vertex| vertex.alive));
+        assert!(vor_diagram.faces.iter().all(|face| face.alive));
+        for (index, vertex) in vor_diagram.vertices.iter().enumerate() {
+            assert_eq!(vor_diagram.halfedges[vertex.incident_edge].origin, index);
+        }
+        for (index, halfedge) in vor_diagram.halfedges.iter().enumerate() {
+            assert!(halfedge.alive && halfedge.origin < vor_diagram.vertices.len());
+            assert_eq!(vor_diagram.halfedges[halfedge.twin].twin, index);
+            assert_eq!(vor_diagram.halfedges[halfedge.next].prev, index);
+            assert!(halfedge.face < vor_diagram.faces.len());
+        }
+        for (index, face) in vor_diagram.faces.iter().enumerate() {
+            assert_eq!(vor_diagram.halfedges[face.outer_component].face, index);
+        }
+    }
+
+    #[test]
+    fn compaction_keeps_the_diagram() {
+        let pts = random_points(100);
+        let options = VoronoiOptions { compact: false, ..VoronoiOptions::default() };
+        let (mut vor_diagram, _) = voronoi_with_options(pts, 800., &options).validated().unwrap();
+        let polygons = make_site_polygons(&vor_diagram);
+        let segments = make_line_segments(&vor_diagram);
+        let num_live = vor_diagram.halfedges.iter().filter(|halfedge| halfedge.alive).count();
+
+        let halfedge_map = vor_diagram.compact();
+        assert_eq!(vor_diagram.halfedges.len(), num_live);
+        assert_eq!(halfedge_map.iter().filter(|index| index.is_some()).count(), num_live);
+        assert_eq!(make_site_polygons(&vor_diagram), polygons);
+        assert_eq!(make_line_segments(&vor_diagram), segments);
+    }
+
+    #[test]
+    fn faces_added_once() {
+        let mut vor_diagram = voronoi(vec![Point::new(1.0, 2.0), Point::new(5.0, 6.0)], 10.).validated();
+        assert!(add_faces(&mut vor_diagram).is_err());
+    }
+
+    // two twins whose next pointers both lead to the second, and so never back to the first
+    fn tangled_twins() -> DCEL {
+        let mut dcel = DCEL::new();
+        let (first, second) = dcel.add_twins();
+        dcel.vertices.push(Vertex { coordinates: Point::new(0.0, 0.0), incident_edge: first, alive: true });
+        dcel.vertices.push(Vertex { coordinates: Point::new(1.0, 0.0), incident_edge: second, alive: true });
+        dcel.halfedges[first].origin = 0;
+        dcel.halfedges[second].origin = 1;
+        dcel.halfedges[first].next = second;
+        dcel.halfedges[second].next = second;
+        dcel
+    }
+
+    #[test]
+    fn broken_cycles_reported() {
+        let broken = Err(VoronoiError::BrokenCycle { halfedge: 0 });
+        assert_eq!(tangled_twins().set_prev(), broken);
+        assert_eq!(add_faces(&mut tangled_twins()), broken);
+        assert_eq!(tangled_twins().remove_vertex(0), broken);
+
+        let dcel = tangled_twins();
+        assert_eq!(dcel.cycle(HalfEdgeId(0)).count(), 2);
+        assert_eq!(dcel.vertex_halfedges(VertexId(0)).count(), 2);
+    }
+
+    #[test]
+    fn broken_face_polygons_reported() {
+        let mut vor_diagram = voronoi(random_points(20), 800.).validated();
+        let face = vor_diagram.faces.iter().position(|face| face.site().is_some()).unwrap();
+        let start = vor_diagram.faces[face].outer_component;
+        let next = vor_diagram.halfedges[start].next;
+        vor_diagram.halfedges[next].next = next;
+
+        assert_eq!(try_make_polygons(&vor_diagram), Err(VoronoiError::BrokenCycle { halfedge: start }));
+        assert!(try_make_site_polygons_with_holes(&vor_diagram).is_err());
+        assert!(vor_diagram.try_site_neighbors().is_err());
+        assert!(vor_diagram.cycle(HalfEdgeId(start)).count() <= vor_diagram.halfedges.len());
+    }
+
+    fn line_vertices(dcel: &DCEL, line_edges: &[usize]) -> Vec<Point> {
+        let mut vertices: Vec<Point> = line_edges.iter().map(|&edge| dcel.get_origin(edge)).collect();
+        vertices.push(dcel.get_origin(dcel.halfedges[*line_edges.last().unwrap()].twin));
+        vertices
+    }
+
+    fn assert_valid_lines(mut dcel: DCEL) {
+        dcel.set_prev().unwrap();
+        add_faces(&mut dcel).unwrap();
+        assert_valid(&dcel);
+    }
+
+    #[test]
+    fn line_through_vertex_splits_there() {
+        let mut dcel = DCEL::new();
+        add_line([Point::new(0.0, 0.0), Point::new(10.0, 0.0)], &mut dcel).unwrap();
+        add_line([Point::new(5.0, -5.0), Point::new(5.0, 5.0)], &mut dcel).unwrap();
+        assert_eq!(dcel.vertices.len(), 5);
+
+        // the diagonal passes through the crossing of the first two lines
+        let diagonal = add_line([Point::new(0.0, -5.0), Point::new(10.0, 5.0)], &mut dcel).unwrap();
+        assert_eq!(line_vertices(&dcel, &diagonal), vec![Point::new(0.0, -5.0), Point::new(5.0, 0.0), Point::new(10.0, 5.0)]);
+        assert_eq!(dcel.vertices.len(), 7);
+        let center = dcel.halfedges[diagonal[1]].origin;
+        assert_eq!(dcel.get_edges_around_vertex(center).unwrap().len(), 6);
+        assert_valid_lines(dcel);
+    }
+
+    #[test]
+    fn line_ending_on_edge_splits_it() {
+        let mut dcel = DCEL::new();
+        add_line([Point::new(0.0, 0.0), Point::new(10.0, 0.0)], &mut dcel).unwrap();
+        let line = add_line([Point::new(4.0, 0.0), Point::new(4.0, 6.0)], &mut dcel).unwrap();
+        assert_eq!(line.len(), 1);
+        assert_eq!(dcel.vertices.len(), 4);
+        assert_eq!(dcel.get_edges_around_vertex(dcel.halfedges[line[0]].origin).unwrap().len(), 3);
+        assert_valid_lines(dcel);
+    }
+
+    #[test]
+    fn line_along_edges_keeps_them() {
+        let mut dcel = DCEL::new();
+        let first = add_line([Point::new(0.0, 0.0), Point::new(10.0, 0.0)], &mut dcel).unwrap();
+        add_line([Point::new(5.0, -5.0), Point::new(5.0, 5.0)], &mut dcel).unwrap();
+        let num_halfedges = dcel.halfedges.len();
+
+        // overlapping at both ends, and lying within an edge
+        let longer = add_line([Point::new(-5.0, 0.0), Point::new(15.0, 0.0)], &mut dcel).unwrap();
+        assert_eq!(line_vertices(&dcel, &longer), vec![Point::new(-5.0, 0.0), Point::new(0.0, 0.0), Point::new(5.0, 0.0), Point::new(10.0, 0.0), Point::new(15.0, 0.0)]);
+        assert_eq!(longer[1], first[0]);
+        assert_eq!(dcel.halfedges.len(), num_halfedges + 4);
+
+        let inner = add_line([Point::new(6.0, 0.0), Point::new(8.0, 0.0)], &mut dcel).unwrap();
+        assert_eq!(line_vertices(&dcel, &inner), vec![Point::new(6.0, 0.0), Point::new(8.0, 0.0)]);
+        assert_eq!(dcel.halfedges.len(), num_halfedges + 8);
+        assert_valid_lines(dcel);
+    }
+
+    fn neighbor_sites(neighbors: &[(Neighbor, Segment)]) -> Vec<usize> {
+        let mut sites: Vec<usize> = neighbors.iter().filter_map(|&(neighbor, _)| match neighbor {
+            Neighbor::Site(site) => Some(site),
+            Neighbor::Boundary => None,
+        }).collect();
+        sites.sort();
+        sites.dedup();
+        sites
+    }
+
+    fn length(seg: Segment) -> f64 {
+        (seg[1] - seg[0]).dot(seg[1] - seg[0]).sqrt()
+    }
+
+    #[test]
+    fn two_site_neighbors() {
+        let dcel = voronoi(vec![Point::new(300.0, 380.0), Point::new(500.0, 420.0)], 800.).validated();
+        let neighbors = dcel.site_neighbors();
+        assert_eq!(neighbors.len(), 2);
+        let (site, ref edges) = neighbors[0];
+        assert_eq!(site, 0);
+        let shared: Vec<&(Neighbor, Segment)> = edges.iter().filter(|&&(neighbor, _)| neighbor == Neighbor::Site(1)).collect();
+        assert_eq!(shared.len(), 1);
+        let seg = shared[0].1;
+        assert!(seg[0].y() == 0.0 || seg[0].y() == 800.0);
+        assert!(seg[1].y() == 0.0 || seg[1].y() == 800.0);
+        assert!(edges.iter().any(|&(neighbor, _)| neighbor == Neighbor::Boundary));
+    }
+
+    #[test]
+    fn shared_edges_match() {
+        let pts = vec![Point::new(120.0, 230.0), Point::new(610.0, 90.0), Point::new(400.0, 400.0),
+                       Point::new(250.0, 650.0), Point::new(700.0, 520.0), Point::new(330.0, 310.0)];
+        let neighbors = voronoi(pts, 800.).validated().site_neighbors();
+        let mut boundary_length = 0.;
+        for (site, edges) in &neighbors {
+            for &(neighbor, seg) in edges {
+                match neighbor {
+                    Neighbor::Site(other) => {
+                        let other_edges = &neighbors.iter().find(|&&(s, _)| s == other).unwrap().1;
+                        assert!(other_edges.contains(&(Neighbor::Site(*site), [seg[1], seg[0]])));
+                    }
+                    Neighbor::Boundary => boundary_length += length(seg),
+                }
+            }
+        }
+        assert!((boundary_length - 3200.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn grid_neighbors() {
+        // diagonal sites meet only at a point, so they are not neighbors
+        let mut pts = vec![];
+        for row in 0..3 {
+            for col in 0..3 {
+                pts.push(Point::new(10.0 * col as f64 + 5.0, 10.0 * row as f64 + 5.0));
+            }
+        }
+        let neighbors = voronoi(pts, BoundingBox::new(Point::new(0.0, 0.0), Point::new(30.0, 30.0))).validated().site_neighbors();
+        assert_eq!(neighbor_sites(&neighbors[4].1), vec![1, 3, 5, 7]);
+        assert_eq!(neighbor_sites(&neighbors[0].1), vec![1, 3]);
+        assert!(!neighbors[4].1.iter().any(|&(neighbor, _)| neighbor == Neighbor::Boundary));
+    }
+
+    fn grid_diagram() -> DCEL {
+        let mut pts = vec![];
+        for row in 0..3 {
+            for col in 0..3 {
+                pts.push(Point::new(10.0 * col as f64 + 5.0, 10.0 * row as f64 + 5.0));
+            }
+        }
+        voronoi(pts, BoundingBox::new(Point::new(0.0, 0.0), Point::new(30.0, 30.0))).validated()
+    }
+
+    fn site_face(dcel: &DCEL, site: usize) -> FaceId {
+        dcel.face_ids().find(|&face| dcel.face_site(face) == Some(site)).unwrap()
+    }
+
+    #[test]
+    fn walk_around_face() {
+        let dcel = grid_diagram();
+        let center = site_face(&dcel, 4);
+        let corners: Vec<Point> = dcel.face_halfedges(center)
+            .map(|edge| dcel.position(dcel.origin(edge).unwrap()))
+            .collect();
+        assert_eq!(corners.len(), 4);
+        assert!(signed_area(&corners) > 0.);
+        assert!(dcel.face_halfedges(center).all(|edge| dcel.face(edge) == Some(center)));
+
+        let mut adjacent: Vec<Option<usize>> = dcel.adjacent_faces(center).map(|face| dcel.face_site(face)).collect();
+        adjacent.sort();
+        assert_eq!(adjacent, vec![Some(1), Some(3), Some(5), Some(7)]);
+
+        // a corner cell also borders the outside of the box
+        let mut adjacent: Vec<Option<usize>> = dcel.adjacent_faces(site_face(&dcel, 0)).map(|face| dcel.face_site(face)).collect();
+        adjacent.sort();
+        assert_eq!(adjacent, vec![None, Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn walk_around_vertex() {
+        let dcel = grid_diagram();
+        let mut num_halfedges = 0;
+        for vertex in dcel.vertex_ids() {
+            for edge in dcel.vertex_halfedges(vertex) {
+                assert_eq!(dcel.origin(edge), Some(vertex));
+                num_halfedges += 1;
+            }
+        }
+        assert_eq!(num_halfedges, dcel.halfedge_ids().count());
+
+        // the four cells of the inner corners meet at a vertex of degree four
+        let inner_corner = dcel.vertex_ids().find(|&vertex| dcel.position(vertex) == Point::new(10.0, 10.0)).unwrap();
+        let mut sites: Vec<Option<usize>> = dcel.vertex_halfedges(inner_corner).map(|edge| dcel.face_site(dcel.face(edge).unwrap())).collect();
+        sites.sort();
+        assert_eq!(sites, vec![Some(0), Some(1), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn edge_endpoints() {
+        let dcel = grid_diagram();
+        for edge in dcel.halfedge_ids() {
+            let (start, end) = dcel.endpoints(edge);
+            assert_eq!(start, dcel.origin(edge));
+            assert_eq!(end, dcel.origin(dcel.next(edge)));
+            assert_eq!(dcel.prev(dcel.next(edge)), Some(edge));
+            assert_eq!(dcel.endpoints(dcel.twin(edge)), (end, start));
+        }
+    }
+
+    #[test]
+    fn unbounded_edges_have_open_ends() {
+        let (dcel, rays, _) = voronoi_unbounded(vec![Point::new(0.0, 0.0), Point::new(4.0, 0.0), Point::new(1.0, 3.0)], &VoronoiOptions::default()).validated().unwrap();
+        for ray in rays {
+            let (start, end) = dcel.endpoints(HalfEdgeId(ray.halfedge));
+            assert!(start.is_some());
+            assert_eq!(end, None);
+        }
+        let bounded_faces = dcel.face_ids().filter(|&face| dcel.cycle(dcel.outer_component(face)).all(|edge| dcel.origin(edge).is_some()));
+        assert_eq!(bounded_faces.count(), 0);
+    }
 }
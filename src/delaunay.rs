@@ -0,0 +1,163 @@
+use fnv::FnvHashMap;
+use point::Point;
+use voronoi::{VoronoiOptions, prepare_sites, sweep};
+use error::VoronoiError;
+
+/// A Delaunay triangulation, as the dual of a Voronoi diagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Triangulation {
+    /// Triangles, as counterclockwise triples of input point indices
+    pub triangles: Vec<[usize; 3]>,
+    /// For each triangle, the index of the triangle across the edge
+    /// opposite each of its corners, or None on the convex hull
+    pub neighbors: Vec<[Option<usize>; 3]>,
+}
+
+impl Triangulation {
+    /// Construct a triangulation from counterclockwise triangles,
+    /// finding the neighbors of each triangle.
+    pub fn new(triangles: Vec<[usize; 3]>) -> Self {
+        // the triangle to the left of each directed edge
+        let mut edge_triangles = FnvHashMap::default();
+        for (index, triangle) in triangles.iter().enumerate() {
+            for corner in 0..3 {
+                edge_triangles.insert((triangle[(corner + 1) % 3], triangle[(corner + 2) % 3]), index);
+            }
+        }
+
+        let neighbors = triangles.iter().map(|triangle| {
+            let mut this_neighbors = [None; 3];
+            for (corner, neighbor) in this_neighbors.iter_mut().enumerate() {
+                let reversed_edge = (triangle[(corner + 2) % 3], triangle[(corner + 1) % 3]);
+                *neighbor = edge_triangles.get(&reversed_edge).cloned();
+            }
+            this_neighbors
+        }).collect();
+
+        Triangulation { triangles, neighbors }
+    }
+}
+
+/// Computes the Delaunay triangulation of a set of points.
+///
+/// The triangles are read off the circle events of the Voronoi sweep:
+/// each Voronoi vertex is the circumcenter of a Delaunay triangle.
+/// Where four or more sites are cocircular, their polygon is split into
+/// triangles arbitrarily. Coincident points are treated as in
+/// `voronoi_with_options`, and the second element of the result maps
+/// input indices to the sites used in the triangles.
+pub fn delaunay_triangulation(points: Vec<Point>, options: &VoronoiOptions) -> Result<(Triangulation, Vec<usize>), VoronoiError> {
+    let (sites, site_map) = prepare_sites(points, options)?;
+    let (_, _, triangles) = sweep(&sites)?;
+
+    // the sweep finds the corners in clockwise order
+    let triangles = triangles.into_iter().map(|[a, b, c]| [a, c, b]).collect();
+    Ok((Triangulation::new(triangles), site_map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use predicates::{orient2d, incircle};
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn grid_points(cols: usize, rows: usize) -> Vec<Point> {
+        let mut pts = vec![];
+        for row in 0..rows {
+            for col in 0..cols {
+                pts.push(Point::new(10.0 * col as f64 + 5.0, 10.0 * row as f64 + 5.0));
+            }
+        }
+        pts
+    }
+
+    fn random_points(num_pts: usize) -> Vec<Point> {
+        let mut rng: StdRng = SeedableRng::from_seed(&[12345][..]);
+        (0..num_pts).map(|_| Point::new(rng.next_f64() * 800., rng.next_f64() * 800.)).collect()
+    }
+
+    // counterclockwise triangles, with empty circumcircles and symmetric neighbors
+    fn assert_delaunay(pts: &[Point], triangulation: &Triangulation) {
+        for (index, triangle) in triangulation.triangles.iter().enumerate() {
+            let [a, b, c] = *triangle;
+            assert!(orient2d(pts[a], pts[b], pts[c]) > 0.);
+            for &pt in pts {
+                assert!(incircle(pts[a], pts[b], pts[c], pt) <= 0.);
+            }
+            for (corner, neighbor) in triangulation.neighbors[index].iter().enumerate() {
+                if let Some(neighbor) = *neighbor {
+                    let back = triangulation.neighbors[neighbor].iter().position(|&n| n == Some(index));
+                    assert!(back.is_some());
+                    let other = triangulation.triangles[neighbor][back.unwrap()];
+                    assert!(!triangle.contains(&other));
+                    assert!(triangulation.triangles[neighbor].contains(&triangle[(corner + 1) % 3]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_triangle() {
+        let pts = vec![Point::new(0.0, 0.0), Point::new(4.0, 0.0), Point::new(1.0, 3.0)];
+        let (triangulation, _) = delaunay_triangulation(pts.clone(), &VoronoiOptions::default()).unwrap();
+        assert_eq!(triangulation.triangles.len(), 1);
+        assert_eq!(triangulation.neighbors, vec![[None, None, None]]);
+        assert_delaunay(&pts, &triangulation);
+    }
+
+    #[test]
+    fn random_triangulation() {
+        let pts = random_points(200);
+        let (triangulation, _) = delaunay_triangulation(pts.clone(), &VoronoiOptions::default()).unwrap();
+        assert_delaunay(&pts, &triangulation);
+
+        // every interior edge is shared, every hull edge is not
+        let num_hull_edges: usize = triangulation.neighbors.iter()
+            .map(|neighbors| neighbors.iter().filter(|n| n.is_none()).count())
+            .sum();
+        assert_eq!(triangulation.triangles.len(), 2 * pts.len() - 2 - num_hull_edges);
+    }
+
+    #[test]
+    fn cocircular_grid() {
+        let pts = grid_points(4, 3);
+        let (triangulation, _) = delaunay_triangulation(pts.clone(), &VoronoiOptions::default()).unwrap();
+        assert_eq!(triangulation.triangles.len(), 2 * 12 - 2 - 10);
+        assert_delaunay(&pts, &triangulation);
+    }
+
+    #[test]
+    fn nearly_collinear_points() {
+        // within rounding of a horizontal line, four of them on the hull
+        let pts = vec![Point::new(53.112079767309716, 47.32552003402272), Point::new(69.84442821073355, 47.325520034022816),
+                       Point::new(42.610912530667, 47.325520034022745), Point::new(42.3474864481564, 47.32552003402278),
+                       Point::new(31.516998914555572, 47.3255200340228)];
+        let (triangulation, _) = delaunay_triangulation(pts.clone(), &VoronoiOptions::default()).unwrap();
+        assert_eq!(triangulation.triangles.len(), 4);
+        assert_delaunay(&pts, &triangulation);
+    }
+
+    #[test]
+    fn collinear_points_have_no_triangles() {
+        let pts = vec![Point::new(10.0, 1.0), Point::new(20.0, 2.0), Point::new(30.0, 3.0)];
+        let (triangulation, _) = delaunay_triangulation(pts, &VoronoiOptions::default()).unwrap();
+        assert!(triangulation.triangles.is_empty());
+    }
+
+    #[test]
+    fn non_finite_point_rejected() {
+        let pts = vec![Point::new(0.0, 0.0), Point::new(f64::INFINITY, 0.0), Point::new(1.0, 3.0)];
+        let result = delaunay_triangulation(pts, &VoronoiOptions::default());
+        assert_eq!(result.err(), Some(VoronoiError::NonFiniteCoordinate { index: 1 }));
+    }
+
+    #[test]
+    fn duplicates_use_first_index() {
+        let pts = vec![Point::new(0.0, 0.0), Point::new(4.0, 0.0), Point::new(4.0, 0.0), Point::new(1.0, 3.0)];
+        let (triangulation, site_map) = delaunay_triangulation(pts, &VoronoiOptions::default()).unwrap();
+        let mut corners = triangulation.triangles[0];
+        corners.sort();
+        assert_eq!(corners, [0, 1, 3]);
+        assert_eq!(site_map[2], 1);
+    }
+}
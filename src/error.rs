@@ -0,0 +1,99 @@
+use std::fmt;
+use std::error::Error;
+
+/// The ways in which a Voronoi computation can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoronoiError {
+    /// Two input sites have the same coordinates, and duplicates were set to be rejected.
+    DuplicateSite {
+        /// Index of the first input site at this location
+        first: usize,
+        /// Index of the later input site at the same location
+        duplicate: usize,
+    },
+    /// The bounding box has no area, or its corners are not finite.
+    InvalidBoundingBox,
+    /// The clip polygon has no area or non-finite coordinates, crosses itself
+    /// or its holes, or is not convex where a convex polygon is required.
+    InvalidClipPolygon,
+    /// An input site has a coordinate that is NaN or infinite.
+    NonFiniteCoordinate {
+        /// Index of the offending input site
+        index: usize,
+    },
+    /// An input site of a weighted diagram has a weight that is NaN or infinite.
+    NonFiniteWeight {
+        /// Index of the offending input site
+        index: usize,
+    },
+    /// An input site of a multiplicatively weighted diagram has a weight
+    /// that is zero or negative.
+    NonPositiveWeight {
+        /// Index of the offending input site
+        index: usize,
+    },
+    /// Two input sites of a diagram of segments have a point in common,
+    /// without being duplicates.
+    IntersectingSites {
+        /// Index of the earlier of the two input sites
+        first: usize,
+        /// Index of the later of the two input sites
+        second: usize,
+    },
+    /// The tolerance to which curved edges are approximated is not positive and finite.
+    InvalidTolerance,
+    /// The input is too extreme to compute with, for instance because a
+    /// vertex of the diagram lies beyond the range of finite floats.
+    DegenerateInput,
+    /// Following the halfedges from this one, around its face or around its
+    /// origin, never led back to it. The DCEL is malformed.
+    BrokenCycle {
+        /// Index of the halfedge the traversal started from
+        halfedge: usize,
+    },
+    /// The sweep or the DCEL reached a state that should be impossible.
+    /// This is a bug; the message names the check that failed.
+    InternalInconsistency(&'static str),
+}
+
+impl fmt::Display for VoronoiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VoronoiError::DuplicateSite { first, duplicate } => {
+                write!(f, "input site {} coincides with input site {}", duplicate, first)
+            }
+            VoronoiError::InvalidBoundingBox => {
+                write!(f, "bounding box must have positive, finite width and height")
+            }
+            VoronoiError::InvalidClipPolygon => {
+                write!(f, "clip polygon is degenerate, self-intersecting or not convex")
+            }
+            VoronoiError::NonFiniteCoordinate { index } => {
+                write!(f, "input site {} has a non-finite coordinate", index)
+            }
+            VoronoiError::NonFiniteWeight { index } => {
+                write!(f, "input site {} has a non-finite weight", index)
+            }
+            VoronoiError::NonPositiveWeight { index } => {
+                write!(f, "input site {} has a weight that is not positive", index)
+            }
+            VoronoiError::IntersectingSites { first, second } => {
+                write!(f, "input sites {} and {} touch or cross", first, second)
+            }
+            VoronoiError::InvalidTolerance => {
+                write!(f, "tolerance must be positive and finite")
+            }
+            VoronoiError::DegenerateInput => {
+                write!(f, "input is too degenerate to compute a diagram")
+            }
+            VoronoiError::BrokenCycle { halfedge } => {
+                write!(f, "the halfedges from halfedge {} do not form a closed cycle", halfedge)
+            }
+            VoronoiError::InternalInconsistency(check) => {
+                write!(f, "internal inconsistency: {}", check)
+            }
+        }
+    }
+}
+
+impl Error for VoronoiError {}
@@ -3,27 +3,39 @@ use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 
 use fnv::FnvHashSet;
+use ordered_float::OrderedFloat;
 
 use point::Point;
+use predicates::{compare_circle_bottom, compare_circle_bottoms, CIRCLE_ACCURACY};
+
+type TripleSite = (Point, Point, Point);
+
+// Difference in height, relative to the coordinates, within which the order
+// of two events is found exactly: well beyond the error in their heights
+const TIE_TOLERANCE: f64 = 10.0 * CIRCLE_ACCURACY;
 
 #[derive(Clone)]
 pub enum Event {
-    Site(Point),
-    Circle(Point /* center */, f64 /* radius */, usize /* index of disappearing arc */, usize /* id */),
+    Site(Point, usize /* index of site */),
+    // the sites are kept where the circle passes through them, to order the event exactly
+    Circle(Point /* center */, f64 /* height of sweep line */, Option<TripleSite> /* sites */, usize /* index of disappearing arc */, usize /* id */),
+    // the sweep line may have reached the power cell of a waiting site
+    Appearance(Point /* site */, f64 /* height of sweep line */, usize /* index of site */),
 }
 
 impl fmt::Debug for Event {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Event::Site(pt) => { write!(f, "Site at {:?}", pt) },
-            Event::Circle(center, radius, leaf, _) => { write!(f, "Circle for leaf {}, center {:?}, radius {:?}", leaf, center, radius) },
+            Event::Site(pt, index) => { write!(f, "Site {} at {:?}", index, pt) },
+            Event::Circle(center, height, _, leaf, _) => { write!(f, "Circle for leaf {}, center {:?}, at height {:?}", leaf, center, height) },
+            Event::Appearance(pt, height, index) => { write!(f, "Appearance of site {} at {:?}, at height {:?}", index, pt, height) },
         }
     }
 }
 
 impl PartialEq for Event {
     fn eq(&self, other: &Event) -> bool {
-        self.get_y().eq(&other.get_y())
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -31,23 +43,80 @@ impl Eq for Event {}
 
 impl PartialOrd for Event {
     fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
-        let y = self.get_y();
-        let other_y = other.get_y();
-        y.partial_cmp(&other_y)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Event {
     fn cmp(&self, other: &Event) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Greater)
+        // sites at the same height must be swept from left to right
+        if let (&Event::Site(pt, _), &Event::Site(other_pt, _)) = (self, other) {
+            return pt.cmp(&other_pt);
+        }
+        // near ties between events of points and circles through them are
+        // settled exactly; circles come before sites at the same height
+        let (height, other_height) = (self.get_y(), other.get_y());
+        if let (Some(key), Some(other_key)) = (self.exact_key(), other.exact_key()) {
+            let scale = key.scale().max(other_key.scale()).max(height.abs()).max(other_height.abs());
+            if (height - other_height).abs() <= TIE_TOLERANCE * scale {
+                return key.cmp(&other_key);
+            }
+        }
+        // a total order, so that a NaN cannot leave the heap inconsistent
+        OrderedFloat(height).cmp(&OrderedFloat(other_height)).then_with(|| self.tie_rank().cmp(&other.tie_rank()))
+    }
+}
+
+// What an event's height is worked out from: the height of a site, or the
+// lowest point of a circle through three sites
+enum ExactKey {
+    Site(f64),
+    Circle(TripleSite),
+}
+
+impl ExactKey {
+    // the magnitude of the coordinates the height is found from
+    fn scale(&self) -> f64 {
+        match *self {
+            ExactKey::Site(height) => height.abs(),
+            ExactKey::Circle((a, b, c)) => [a, b, c].iter().fold(0., |most: f64, pt| most.max(pt.x().abs()).max(pt.y().abs())),
+        }
+    }
+
+    fn cmp(&self, other: &ExactKey) -> Ordering {
+        match (self, other) {
+            (&ExactKey::Site(height), &ExactKey::Site(other_height)) => OrderedFloat(height).cmp(&OrderedFloat(other_height)),
+            (&ExactKey::Circle((a, b, c)), &ExactKey::Site(height)) => compare_circle_bottom(a, b, c, height).then(Ordering::Greater),
+            (&ExactKey::Site(height), &ExactKey::Circle((a, b, c))) => compare_circle_bottom(a, b, c, height).reverse().then(Ordering::Less),
+            (&ExactKey::Circle(first), &ExactKey::Circle(second)) => compare_circle_bottoms(first, second),
+        }
     }
 }
 
 impl Event {
     pub fn get_y(&self) -> f64 {
         match *self {
-            Event::Site(ref pt) => pt.y(),
-            Event::Circle(center, radius, _, _) => center.y() + radius,
+            Event::Site(ref pt, _) => pt.y(),
+            Event::Circle(_, height, _, _, _) => height,
+            Event::Appearance(_, height, _) => height,
+        }
+    }
+
+    // at the same height circles come first, and a waiting site appears
+    // only once the sites there are on the beachline
+    fn tie_rank(&self) -> u8 {
+        match *self {
+            Event::Circle(..) => 2,
+            Event::Site(..) => 1,
+            Event::Appearance(..) => 0,
+        }
+    }
+
+    fn exact_key(&self) -> Option<ExactKey> {
+        match *self {
+            Event::Site(pt, _) => Some(ExactKey::Site(pt.y())),
+            Event::Circle(_, _, sites, _, _) => sites.map(ExactKey::Circle),
+            Event::Appearance(..) => None,
         }
     }
 }
@@ -65,7 +134,7 @@ impl fmt::Debug for EventQueue {
 
         for (index, event) in self.events.iter().enumerate() {
             queue_disp.push_str(format!("{}: {:?}", index, event).as_str());
-            queue_disp.push_str("\n");
+            queue_disp.push('\n');
         }
 
         write!(f, "\n{}", queue_disp)
@@ -107,7 +176,63 @@ impl EventQueue {
         None
     }
 
+    pub fn peek(&mut self) -> Option<&Event> {
+        // removed events are dropped on the way
+        while let Some(&Event::Circle(.., id)) = self.events.peek() {
+            if !self.removed_event_ids.remove(&id) { break; }
+            self.events.pop();
+        }
+        self.events.peek()
+    }
+
     pub fn remove(&mut self, event_id: usize) {
         self.removed_event_ids.insert(event_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle_at(y: f64) -> Event {
+        Event::Circle(Point::new(0.0, y), y, None, 0, 0)
+    }
+
+    fn pop_heights(queue: &mut EventQueue) -> Vec<f64> {
+        let mut heights = vec![];
+        while let Some(event) = queue.pop() {
+            heights.push(event.get_y());
+        }
+        heights
+    }
+
+    #[test]
+    fn events_pop_from_the_top() {
+        let mut queue = EventQueue::new();
+        for &y in &[3.0, 9.0, 1.0, 4.0] {
+            queue.push(circle_at(y));
+        }
+        queue.push(Event::Site(Point::new(0.0, 5.0), 0));
+        assert_eq!(pop_heights(&mut queue), vec![9.0, 5.0, 4.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn nan_does_not_scramble_queue() {
+        let mut queue = EventQueue::new();
+        for &y in &[3.0, f64::NAN, 9.0, 1.0, f64::NAN, 4.0, 7.0] {
+            queue.push(circle_at(y));
+        }
+        let heights = pop_heights(&mut queue);
+        assert!(heights[0].is_nan() && heights[1].is_nan());
+        assert_eq!(&heights[2..], &[9.0, 7.0, 4.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn infinities_order_at_the_ends() {
+        let mut queue = EventQueue::new();
+        for &y in &[3.0, f64::NEG_INFINITY, 9.0, f64::INFINITY, 1.0] {
+            queue.push(circle_at(y));
+        }
+        assert_eq!(pop_heights(&mut queue), vec![f64::INFINITY, 9.0, 3.0, 1.0, f64::NEG_INFINITY]);
+    }
+}
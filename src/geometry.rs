@@ -1,35 +1,85 @@
+use std::cmp::Ordering;
+
 use ordered_float::OrderedFloat;
 use point::Point;
 use beachline::BreakPoint;
+use predicates::{orient2d, circumcircle, compare_breakpoint_x};
 
 type TripleSite = (Point, Point, Point);
+// the weights of three sites, zero for a Voronoi diagram
+pub type TripleWeight = (f64, f64, f64);
 
 pub type Segment = [Point; 2];
 
-pub fn segment_intersection(seg1: Segment, seg2: Segment) -> Option<Point> {
-    let a = seg1[0];
-    let c = seg2[0];
-    let r = seg1[1] - a;
-    let s = seg2[1] - c;
-
-    let denom = r.cross(s);
-    if denom == 0.0 { return None; }
-
-    let numer_a = (c - a).cross(s);
-    let numer_c = (c - a).cross(r);
+// How the weights of the sites change the distance to them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Weighting {
+    // the weight is added to the squared distance, zero for a Voronoi diagram
+    Power,
+    // the weight is subtracted from the distance
+    Additive,
+    // the sites are unweighted points and sides of segments
+    Segments,
+}
 
-    let t = numer_a / denom;
-    let u = numer_c / denom;
+// Relative slack with which a point counts as within a power cell
+const CONTACT_TOLERANCE: f64 = 1e-9;
+
+// Is the point strictly inside the polygon? Exact, by winding number,
+// provided the point does not lie on the boundary.
+pub fn polygon_contains(poly: &[Point], pt: Point) -> bool {
+    let mut winding = 0;
+    for (index, &start) in poly.iter().enumerate() {
+        let end = poly[(index + 1) % poly.len()];
+        if start.y() <= pt.y() {
+            if end.y() > pt.y() && orient2d(start, end, pt) > 0.0 { winding += 1; }
+        } else if end.y() <= pt.y() && orient2d(start, end, pt) < 0.0 {
+            winding -= 1;
+        }
+    }
+    winding != 0
+}
 
-    if t < 0.0 || t > 1.0 || u < 0.0 || u > 1.0 { return None; }
+// The part of a convex polygon on the side of a line through a point toward
+// which the normal points
+pub fn clip_polygon(polygon: &[Point], through: Point, normal: Point) -> Vec<Point> {
+    let mut clipped = vec![];
+    for (index, &pt) in polygon.iter().enumerate() {
+        let next = polygon[(index + 1) % polygon.len()];
+        let (here, there) = ((pt - through).dot(normal), (next - through).dot(normal));
+        if here >= 0. { clipped.push(pt); }
+        if (here < 0.) != (there < 0.) { clipped.push(pt + (next - pt) * (here / (here - there))); }
+    }
+    clipped
+}
 
-    return Some(a + r * t);
+// Do the closed segments have any point in common?
+pub fn segments_touch(seg1: Segment, seg2: Segment) -> bool {
+    let [a, b] = seg1;
+    let [c, d] = seg2;
+    let o1 = orient2d(a, b, c);
+    let o2 = orient2d(a, b, d);
+    let o3 = orient2d(c, d, a);
+    let o4 = orient2d(c, d, b);
+    if o1 * o2 < 0.0 && o3 * o4 < 0.0 { return true; }
+
+    // an endpoint lying on the other segment
+    let on_segment = |p: Point, q: Point, r: Point| {
+        r.x() >= p.x().min(q.x()) && r.x() <= p.x().max(q.x())
+            && r.y() >= p.y().min(q.y()) && r.y() <= p.y().max(q.y())
+    };
+    (o1 == 0.0 && on_segment(a, b, c)) || (o2 == 0.0 && on_segment(a, b, d))
+        || (o3 == 0.0 && on_segment(c, d, a)) || (o4 == 0.0 && on_segment(c, d, b))
 }
 
-pub fn circle_bottom(triple_site: TripleSite) -> Option<OrderedFloat<f64>> {
-    let circle_center = circle_center(triple_site);
-    if let None = circle_center { return None; }
-    let circle_center = circle_center.unwrap();
+// The height of the sweep line when it reaches the bottom of the circle
+// around the center of the sites, where their arcs meet.
+pub fn circle_bottom(triple_site: TripleSite, weights: TripleWeight) -> Option<OrderedFloat<f64>> {
+    if weights == (0., 0., 0.) {
+        let (p1, p2, p3) = triple_site;
+        return circumcircle(p1, p2, p3).map(|(_, bottom)| OrderedFloat(bottom));
+    }
+    let circle_center = circle_center(triple_site, weights)?;
 
     let (_, _, p3) = triple_site;
     let x3 = p3.x();
@@ -37,134 +87,463 @@ pub fn circle_bottom(triple_site: TripleSite) -> Option<OrderedFloat<f64>> {
     let x_cen = circle_center.x();
     let y_cen = circle_center.y();
 
-    let r = ((x3 - x_cen) * (x3 - x_cen) + (y3 - y_cen) * (y3 - y_cen)).sqrt();
+    let r = ((x3 - x_cen) * (x3 - x_cen) + (y3 - y_cen) * (y3 - y_cen) + weights.2).sqrt();
 
-    return Some(OrderedFloat::<f64>(y_cen - r));
+    Some(OrderedFloat::<f64>(y_cen - r))
 }
 
-pub fn circle_center(triple_site: TripleSite) -> Option<Point> {
+// The point at the same power distance from all three sites: the circumcenter
+// when the weights are zero, and the radical center of the circles otherwise.
+pub fn circle_center(triple_site: TripleSite, weights: TripleWeight) -> Option<Point> {
     let (p1, p2, p3) = triple_site;
-    let x1 = p1.x();
-    let x2 = p2.x();
-    let x3 = p3.x();
-    let y1 = p1.y();
-    let y2 = p2.y();
-    let y3 = p3.y();
-
-    let c1 = x3 * x3 + y3 * y3 - x1 * x1 - y1 * y1;
-    let c2 = x3 * x3 + y3 * y3 - x2 * x2 - y2 * y2;
-    let a1 = -2. * (x1 - x3);
-    let a2 = -2. * (x2 - x3);
-    let b1 = -2. * (y1 - y3);
-    let b2 = -2. * (y2 - y3);
+    let (w1, w2, w3) = weights;
+    if weights == (0., 0., 0.) { return circumcircle(p1, p2, p3).map(|(center, _)| center); }
 
-    let numer = c1 * a2 - c2 * a1;
-    let denom = b1 * a2 - b2 * a1;
+    // twice the signed area of the triangle; zero exactly when the sites are collinear
+    let area = orient2d(p3, p1, p2);
+    if area == 0.0 { return None; }
 
-    if denom == 0.0 { return None; }
-    let y_cen = numer / denom;
+    // solve for the center relative to p3
+    let b = p1 - p3;
+    let c = p2 - p3;
+    let b_sq = b.dot(b) + (w1 - w3);
+    let c_sq = c.dot(c) + (w2 - w3);
+    let denom = 2. * area;
 
+    let x_cen = (c.y() * b_sq - b.y() * c_sq) / denom;
+    let y_cen = (b.x() * c_sq - c.x() * b_sq) / denom;
 
-    let x_cen = if a2 != 0.0 {
-        (c2 - b2 * y_cen) / a2
-    } else {
-        (c1 - b1 * y_cen) / a1
-    };
-
-    return Some(Point::new(x_cen, y_cen));
+    Some(p3 + Point::new(x_cen, y_cen))
 }
 
 // see http://www.kmschaal.de/Diplomarbeit_KevinSchaal.pdf, pg 27
+// The breakpoints converge exactly when the sites make a right turn.
 pub fn breakpoints_converge(triple_site: TripleSite) -> bool {
     let (a, b, c) = triple_site;
-    let ax = a.x();
-    let ay = a.y();
-    let bx = b.x();
-    let by = b.y();
-    let cx = c.x();
-    let cy = c.y();
+    orient2d(a, b, c) < 0.0
+}
 
-    (ay - by) * (bx - cx) > (by - cy) * (ax - bx)
+// The distance from a weighted site to a point, by which the nearest site is found
+pub fn weighted_distance(weighting: Weighting, site: Point, weight: f64, pt: Point) -> f64 {
+    match weighting {
+        Weighting::Power | Weighting::Segments => (pt - site).dot(pt - site) + weight,
+        Weighting::Additive => (pt - site).dot(pt - site).sqrt() - weight,
+    }
 }
 
-pub fn get_breakpoint_x(bp: &BreakPoint, yl: f64) -> f64 {
+pub fn get_breakpoint_x(bp: &BreakPoint, yl: f64, weighting: Weighting) -> f64 {
+    match weighting {
+        Weighting::Additive => return additive_breakpoint_x(bp, yl),
+        Weighting::Segments => return segment_breakpoint_x(bp, yl),
+        Weighting::Power => {}
+    }
     let ax = bp.left_site.x();
     let bx = bp.right_site.x();
     let ay = bp.left_site.y();
     let by = bp.right_site.y();
+    let aw = bp.left_weight;
+    let bw = bp.right_weight;
 
     // shift frames
     let bx_s = bx - ax;
     let ay_s = ay - yl;
     let by_s = by - yl;
 
-    let discrim = ay_s * by_s * ((ay_s - by_s) * (ay_s - by_s) + bx_s * bx_s);
-    let numer = ay_s * bx_s - discrim.sqrt();
+    // the weights shift the breakpoint along the power bisector; an arc that
+    // has just reached the beachline may leave the discriminant a rounding below zero
+    let discrim = ay_s * by_s * ((ay_s - by_s) * (ay_s - by_s) + bx_s * bx_s) - (by_s - ay_s) * (by_s * aw - ay_s * bw);
+    let numer = ay_s * bx_s - discrim.max(0.).sqrt();
     let denom = ay_s - by_s;
 
     let mut x_bp = if denom != 0.0 {
         numer / denom
+    } else if aw != bw {
+        bx_s / 2. + (bw - aw) / (2. * bx_s)
     } else {
         bx_s / 2.
     };
     x_bp += ax; // shift back to original frame
 
-    return x_bp;
+    x_bp
 }
 
-// TODO: handle py == yl case
-pub fn get_breakpoint_y(bp: &BreakPoint, yl: f64) -> f64 {
-    let px = bp.left_site.x();
-    let py = bp.left_site.y();
+// Where a point lies beside a breakpoint when the sweep line is at its
+// height: Less if to its left. Between unweighted sites this is exact.
+pub fn breakpoint_side(bp: &BreakPoint, pt: Point, weighting: Weighting) -> Ordering {
+    if weighting == Weighting::Power && bp.left_weight == 0. && bp.right_weight == 0. {
+        return compare_breakpoint_x(bp.left_site, bp.right_site, pt);
+    }
+    if pt.x() < get_breakpoint_x(bp, pt.y(), weighting) { Ordering::Less } else { Ordering::Greater }
+}
 
-    let bp_x = get_breakpoint_x(bp, yl);
+// The arc of a site whose distance is reduced by its weight is the parabola of
+// the site over a sweep line lowered by the weight: y = (x - ax)^2 / 2h + ay - h / 2,
+// where h is the height of the lowered sweep line below the site.
+fn additive_breakpoint_x(bp: &BreakPoint, yl: f64) -> f64 {
+    let ax = bp.left_site.x();
+    let bx_s = bp.right_site.x() - ax;
+    let ah = bp.left_site.y() + bp.left_weight - yl;
+    let bh = bp.right_site.y() + bp.right_weight - yl;
+    let lowest = (bp.left_site.y() - ah / 2.) - (bp.right_site.y() - bh / 2.);
+
+    // an arc that has just reached the beachline may leave the discriminant a rounding below zero
+    let discrim = ah * bh * (bx_s * bx_s - 2. * (bh - ah) * lowest);
+    let denom = ah - bh;
+    let x_bp = if denom != 0.0 {
+        (ah * bx_s - discrim.max(0.).sqrt()) / denom
+    } else if lowest != 0.0 {
+        bx_s / 2. - bh * lowest / bx_s
+    } else {
+        bx_s / 2.
+    };
+    x_bp + ax
+}
 
-    let numer = (px - bp_x) * (px - bp_x);
-    let denom = 2. * (py - yl);
+// The arc of the side of a segment to the right of the way from `start` to
+// `end` is the line of the locations as far from the line of the segment as
+// from the sweep line: y = slope x + offset. The segment must not be horizontal.
+pub fn side_arc(start: Point, end: Point, yl: f64) -> (f64, f64) {
+    let along = end - start;
+    let length = along.dot(along).sqrt();
+    let normal = Point::new(along.y() / length, -along.x() / length);
+    let rise = 1. - normal.y();
+    (normal.x() / rise, (yl - normal.dot(start)) / rise)
+}
 
-    return numer / denom + (py + yl) / 2.;
+// The arcs of a segment diagram are those of points and of the sides of
+// segments, as side_arc gives them; a site with an end apart from it is a side.
+fn segment_breakpoint_x(bp: &BreakPoint, yl: f64) -> f64 {
+    let (left, right) = ((bp.left_site, bp.left_end), (bp.right_site, bp.right_end));
+    match (left.0 == left.1, right.0 == right.1) {
+        (true, true) => get_breakpoint_x(bp, yl, Weighting::Power),
+        (true, false) => point_side_breakpoint_x(left.0, right, yl, true),
+        (false, true) => point_side_breakpoint_x(right.0, left, yl, false),
+        // the two sides of a segment meet where it crosses the sweep line
+        (false, false) if left.0 == right.1 && left.1 == right.0 => {
+            let along = left.1 - left.0;
+            left.0.x() + (yl - left.0.y()) * along.x() / along.y()
+        }
+        (false, false) => {
+            let ((left_slope, left_offset), (right_slope, right_offset)) = (side_arc(left.0, left.1, yl), side_arc(right.0, right.1, yl));
+            if left_slope == right_slope { return f64::NAN; }
+            (right_offset - left_offset) / (left_slope - right_slope)
+        }
+    }
+}
+
+// Where the arc of a point meets that of a side of a segment, the point on
+// the left if `point_left`. The arc of an end of the segment meets it on the
+// line square to the segment at the end.
+fn point_side_breakpoint_x(pt: Point, side: (Point, Point), yl: f64, point_left: bool) -> f64 {
+    let (slope, offset) = side_arc(side.0, side.1, yl);
+    if pt == side.0 || pt == side.1 {
+        // along . (x, slope x + offset) = along . pt
+        let along = side.1 - side.0;
+        return (along.dot(pt) - along.y() * offset) / (along.x() + along.y() * slope);
+    }
+    let height = pt.y() - yl;
+    if height <= 0. { return pt.x(); }
+    // (x - px)^2 / 2h + (py + yl) / 2 = slope x + offset, in u = x - px; the
+    // parabola lies below the line between the two crossings
+    let line_above = slope * pt.x() + offset;
+    let discrim = height * height * slope * slope + height * (2. * line_above - pt.y() - yl);
+    let root = discrim.max(0.).sqrt();
+    pt.x() + height * slope + if point_left { root } else { -root }
+}
+
+// The circles touching the circles around three sites whose radii are the weights,
+// as (center, radius); the radius is negative where the circle lies inside them.
+// Each is a point at the same additively weighted distance, the radius, from
+// the three sites. There are at most two; for collinear sites, they are mirror
+// images in the line, and there are none if the weights are also equal.
+pub fn apollonius_circles(triple_site: TripleSite, weights: TripleWeight) -> Vec<(Point, f64)> {
+    let (p1, p2, p3) = triple_site;
+    let (w1, w2, w3) = weights;
+
+    // relative to p3, the center z satisfies b . z = kb - radius * mb, and likewise for c
+    let b = p1 - p3;
+    let c = p2 - p3;
+    let (kb, kc) = ((b.dot(b) - w1 * w1 + w3 * w3) / 2., (c.dot(c) - w2 * w2 + w3 * w3) / 2.);
+    let (mb, mc) = (w1 - w3, w2 - w3);
+    let area = orient2d(p3, p1, p2);
+    let circles = if area == 0.0 {
+        collinear_circles((b, kb, mb), (c, kc, mc), w3)
+    } else {
+        general_circles(area, (b, kb, mb), (c, kc, mc), w3)
+    };
+    circles.into_iter()
+        .filter(|&(_, radius)| radius.is_finite())
+        .map(|(center, radius)| (p3 + center, radius))
+        .filter(|&(center, radius)| [(p1, w1), (p2, w2), (p3, w3)].iter().all(|&(pt, weight)| {
+            let reach = radius + weight;
+            reach >= -CONTACT_TOLERANCE * (center - pt).dot(center - pt).sqrt()
+        }))
+        .collect()
+}
+
+// The touching circles relative to the third site, for sites making a triangle
+// of the given orientation; each other site is given as (offset, k, m)
+fn general_circles(area: f64, (b, kb, mb): (Point, f64, f64), (c, kc, mc): (Point, f64, f64), w3: f64) -> Vec<(Point, f64)> {
+    let fixed = Point::new(c.y() * kb - b.y() * kc, b.x() * kc - c.x() * kb) * (1. / area);
+    let per_radius = Point::new(b.y() * mc - c.y() * mb, c.x() * mb - b.x() * mc) * (1. / area);
+
+    // and |z| = radius + w3
+    let quad = per_radius.dot(per_radius) - 1.;
+    let half_lin = fixed.dot(per_radius) - w3;
+    let constant = fixed.dot(fixed) - w3 * w3;
+    let radii = if quad == 0.0 {
+        vec![-constant / (2. * half_lin)]
+    } else {
+        let discrim = half_lin * half_lin - quad * constant;
+        if discrim < 0.0 { return vec![]; }
+        let q = -(half_lin + discrim.sqrt().copysign(half_lin));
+        vec![q / quad, constant / q]
+    };
+    radii.into_iter().map(|radius| (fixed + per_radius * radius, radius)).collect()
+}
+
+// The touching circles relative to the third site, for collinear sites. Along
+// the line, both equations fix the same coordinate, which fixes the radius, and
+// the distance across the line follows from |z| = radius + w3.
+fn collinear_circles((b, kb, mb): (Point, f64, f64), (c, kc, mc): (Point, f64, f64), w3: f64) -> Vec<(Point, f64)> {
+    let longer = if b.dot(b) >= c.dot(c) { b } else { c };
+    let along = longer * (1. / longer.dot(longer).sqrt());
+    let (beta, gamma) = (b.dot(along), c.dot(along));
+    let slope = beta * mc - gamma * mb;
+    if slope == 0.0 { return vec![]; }
+    let radius = (beta * kc - gamma * kb) / slope;
+    let offset = if beta.abs() >= gamma.abs() { (kb - radius * mb) / beta } else { (kc - radius * mc) / gamma };
+    let across_sq = (radius + w3) * (radius + w3) - offset * offset;
+    if across_sq.is_nan() || across_sq < 0.0 { return vec![]; }
+    let across = Point::new(-along.y(), along.x()) * across_sq.sqrt();
+    vec![(along * offset + across, radius), (along * offset - across, radius)]
+}
+
+// Points along the branch of the hyperbola between two sites, where their
+// additively weighted distances are equal, from `start` to `end`, which lie on it.
+// Returns the points between them, in order, such that the path through them is
+// within `tolerance` of the branch. A branch whose sites are no further apart
+// than the difference of their weights is degenerate, and none are returned.
+pub fn hyperbola_points(a: (Point, f64), b: (Point, f64), start: Point, end: Point, tolerance: f64) -> Vec<Point> {
+    let mut points = vec![];
+    if let Some(branch) = Branch::new(a, b) {
+        let (start_angle, end_angle) = (branch.angle_of(start), branch.angle_of(end));
+        branch.refine((start_angle, start), (end_angle, end), tolerance, 0, &mut points);
+    }
+    points
+}
+
+// The point of the branch of the hyperbola between two sites that is `reach`
+// from the first site, on the half of the branch towards which `direction` points.
+pub fn hyperbola_far_point(a: (Point, f64), b: (Point, f64), direction: Point, reach: f64) -> Option<Point> {
+    let branch = Branch::new(a, b)?;
+    // the distance from the first site grows as the angle turns away from the second
+    let cos = (branch.gap / branch.separation + (branch.separation * branch.separation - branch.gap * branch.gap) / (2. * reach * branch.separation)).min(1.);
+    let angle = cos.acos();
+    let angle = if branch.unit.cross(direction) >= 0. { angle } else { -angle };
+    Some(branch.point_at(angle))
+}
+
+// Subdivisions of a hyperbola beyond this depth would be below rounding
+const HYPERBOLA_DEPTH: usize = 40;
+
+// A hyperbola branch in polar form about the first site: a point at angle phi
+// from the direction of the second site is at distance
+// (separation^2 - gap^2) / 2 (separation cos phi - gap)
+struct Branch {
+    focus: Point,
+    unit: Point,
+    separation: f64,
+    gap: f64,
+}
+
+impl Branch {
+    fn new(a: (Point, f64), b: (Point, f64)) -> Option<Self> {
+        let offset = b.0 - a.0;
+        let separation = offset.dot(offset).sqrt();
+        let gap = a.1 - b.1;
+        if separation <= gap.abs() { return None; }
+        Some(Branch { focus: a.0, unit: offset * (1. / separation), separation, gap })
+    }
+
+    fn angle_of(&self, pt: Point) -> f64 {
+        let offset = pt - self.focus;
+        self.unit.cross(offset).atan2(self.unit.dot(offset))
+    }
+
+    fn point_at(&self, angle: f64) -> Point {
+        let distance = (self.separation * self.separation - self.gap * self.gap) / (2. * (self.separation * angle.cos() - self.gap));
+        let (sin, cos) = angle.sin_cos();
+        self.focus + Point::new(self.unit.x() * cos - self.unit.y() * sin, self.unit.x() * sin + self.unit.y() * cos) * distance
+    }
+
+    // Adds the points needed between two points of the branch, given with their angles
+    fn refine(&self, from: (f64, Point), to: (f64, Point), tolerance: f64, depth: usize, points: &mut Vec<Point>) {
+        let angle = (from.0 + to.0) / 2.;
+        let middle = self.point_at(angle);
+        let chord = to.1 - from.1;
+        let length = chord.dot(chord).sqrt();
+        let deviation = if length > 0. { chord.cross(middle - from.1).abs() / length } else { (middle - from.1).dot(middle - from.1).sqrt() };
+        // the middle of the arc is not quite its furthest point from the chord
+        if depth >= HYPERBOLA_DEPTH || deviation.is_nan() || deviation <= tolerance / 2. { return; }
+        self.refine(from, (angle, middle), tolerance, depth + 1, points);
+        points.push(middle);
+        self.refine((angle, middle), to, tolerance, depth + 1, points);
+    }
+}
+
+// Where the arc of a weighted site first reaches the arcs of its competitors,
+// as found by `power_contact`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contact {
+    // height of the sweep line at the moment of contact
+    pub height: f64,
+    pub point: Point,
+    // indices into the competitors of the one or two sites met there
+    pub competitors: Vec<usize>,
+}
+
+// The point at which the arc of a site, whose squared distance is increased by
+// `offset`, first reaches the arcs of the competing sites, given as (site,
+// offset) pairs. Where a point p meets the beachline, the sweep line is at
+// p.y - sqrt(|p - site|^2 + offset), so the contact is where that height is
+// greatest within the site's power cell among the competitors. The height is
+// concave, with no maximum inside the cell when the offset is positive, so the
+// contact lies on an edge or at a corner of the cell. Returns None if the cell
+// is empty or has no area.
+pub fn power_contact(site: Point, offset: f64, competitors: &[(Point, f64)]) -> Option<Contact> {
+    // the cell is where normal . z <= bound for every competitor, with z relative to the site
+    let constraints: Vec<(Point, f64)> = competitors.iter().map(|&(pt, weight)| {
+        let normal = pt - site;
+        (normal, (normal.dot(normal) + weight - offset) / 2.)
+    }).collect();
+    if is_flat(&constraints) { return None; }
+    let feasible = |z: Point, on: &[usize]| constraints.iter().enumerate().all(|(index, &(normal, bound))| {
+        let slack = CONTACT_TOLERANCE * (bound.abs() + normal.dot(normal).sqrt() * z.dot(z).sqrt());
+        on.contains(&index) || normal.dot(z) <= bound + slack
+    });
+
+    let mut best: Option<(f64, Point, Vec<usize>)> = None;
+    let mut consider = |z: Point, on: Vec<usize>| {
+        if !z.x().is_finite() || !z.y().is_finite() || !feasible(z, &on) { return; }
+        let height = z.y() - (z.dot(z) + offset).sqrt();
+        if best.as_ref().map(|&(best_height, _, _)| height > best_height).unwrap_or(true) {
+            best = Some((height, z, on));
+        }
+    };
+    for (index, &(normal, bound)) in constraints.iter().enumerate() {
+        // a vertical edge rises forever, and its height only tends to that of the site
+        if normal.y() == 0. { continue; }
+        let foot = normal * (bound / normal.dot(normal));
+        let along = Point::new(-normal.y(), normal.x()) * (1. / normal.dot(normal).sqrt());
+        let spread = (foot.dot(foot) + offset).sqrt();
+        let distance = normal.x() * spread / normal.y().abs();
+        consider(foot + along * distance, vec![index]);
+    }
+    for (first, &(normal_a, bound_a)) in constraints.iter().enumerate() {
+        for (second, &(normal_b, bound_b)) in constraints.iter().enumerate().skip(first + 1) {
+            let det = normal_a.cross(normal_b);
+            if det == 0. { continue; }
+            let corner = Point::new(
+                (bound_a * normal_b.y() - bound_b * normal_a.y()) / det,
+                (normal_a.x() * bound_b - normal_b.x() * bound_a) / det);
+            consider(corner, vec![first, second]);
+        }
+    }
+    best.map(|(height, z, competitors)| Contact { height: site.y() + height, point: site + z, competitors })
+}
+
+// Has the cell where normal . z <= bound for each constraint no interior?
+// By Helly's theorem, it has none if and only if two or three of the
+// constraints leave none: two facing each other across no gap, or three
+// whose normals surround the origin and whose bounds close a triangle.
+fn is_flat(constraints: &[(Point, f64)]) -> bool {
+    // each normal as a unit vector, and the distance of its line from the site
+    let lines: Vec<(Point, f64)> = constraints.iter().map(|&(normal, bound)| {
+        let length = normal.dot(normal).sqrt();
+        (normal * (1. / length), bound / length)
+    }).collect();
+    let slack = |involved: &[(Point, f64)]| CONTACT_TOLERANCE * involved.iter().map(|&(pt, distance)| distance.abs() + pt.dot(pt).sqrt()).sum::<f64>();
+    for (first, &(u, c)) in lines.iter().enumerate() {
+        for (second, &(v, d)) in lines.iter().enumerate().skip(first + 1) {
+            let facing = u.dot(v) < 0. && u.cross(v).abs() <= CONTACT_TOLERANCE;
+            if facing && c + d <= slack(&[constraints[first], constraints[second]]) { return true; }
+            for (third, &(w, e)) in lines.iter().enumerate().skip(second + 1) {
+                // weights with which the normals sum to zero
+                let (a, b, g) = (v.cross(w), w.cross(u), u.cross(v));
+                let sign = if a > 0. && b > 0. && g > 0. { 1. } else if a < 0. && b < 0. && g < 0. { -1. } else { continue };
+                let closed = sign * (a * c + b * d + g * e);
+                if closed <= slack(&[constraints[first], constraints[second], constraints[third]]) { return true; }
+            }
+        }
+    }
+    false
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const NO_WEIGHTS: TripleWeight = (0., 0., 0.);
+
     #[test]
     fn simple_circle_center() {
         let circle_triple = (Point::new(-1.0, 0.0), Point::new(0.0, 1.0), Point::new(1.0, 0.0));
-        assert_eq!(circle_center(circle_triple).unwrap(), Point::new(0.0, 0.0));
+        assert_eq!(circle_center(circle_triple, NO_WEIGHTS).unwrap(), Point::new(0.0, 0.0));
     }
 
     #[test]
     fn simple_circle_bottom() {
         let circle_triple = (Point::new(-1.0, 0.0), Point::new(0.0, 1.0), Point::new(1.0, 0.0));
-        assert_eq!(circle_bottom(circle_triple).unwrap(), OrderedFloat(-1.0));
+        assert_eq!(circle_bottom(circle_triple, NO_WEIGHTS).unwrap(), OrderedFloat(-1.0));
     }
 
     #[test]
     fn degenerate_circle() {
         let circle_triple = (Point::new(-1.0, 0.0), Point::new(1.0, 0.0), Point::new(0.0, 0.0));
-        assert_eq!(circle_bottom(circle_triple), None);
+        assert_eq!(circle_bottom(circle_triple, NO_WEIGHTS), None);
+    }
+
+    #[test]
+    fn winding_contains() {
+        let square = [Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 2.0), Point::new(0.0, 2.0)];
+        assert!(polygon_contains(&square, Point::new(1.0, 1.0)));
+        assert!(!polygon_contains(&square, Point::new(3.0, 1.0)));
+        let clockwise: Vec<Point> = square.iter().rev().cloned().collect();
+        assert!(polygon_contains(&clockwise, Point::new(1.0, 1.0)));
+    }
+
+    #[test]
+    fn touching_segments() {
+        let seg = [Point::new(0.0, 0.0), Point::new(2.0, 0.0)];
+        assert!(segments_touch(seg, [Point::new(1.0, -1.0), Point::new(1.0, 1.0)]));
+        assert!(segments_touch(seg, [Point::new(1.0, 0.0), Point::new(1.0, 1.0)]));
+        assert!(segments_touch(seg, [Point::new(1.0, 0.0), Point::new(3.0, 0.0)]));
+        assert!(!segments_touch(seg, [Point::new(3.0, 0.0), Point::new(4.0, 0.0)]));
+        assert!(!segments_touch(seg, [Point::new(1.0, 0.5), Point::new(1.0, 1.0)]));
     }
 
     #[test]
-    fn simple_segments_intersect() {
-        let line1 = [Point::new(-1.0, 0.0), Point::new(1.0, 0.0)];
-        let line2 = [Point::new(0.0, -1.0), Point::new(0.0, 1.0)];
-        assert_eq!(segment_intersection(line1, line2), Some(Point::new(0.0, 0.0)));
+    fn collinear_circle_with_inexact_arithmetic() {
+        let circle_triple = (Point::new(0.3678484251536762, 6.179966849702221),
+                             Point::new(1.3157201229112638, 6.416934774141618),
+                             Point::new(4.159335216184027, 7.127838547459809));
+        assert_eq!(circle_center(circle_triple, NO_WEIGHTS), None);
     }
 
     #[test]
-    fn tee_segments_intersect() {
-        let line1 = [Point::new(-1.0, 0.0), Point::new(1.0, 0.0)];
-        let line2 = [Point::new(0.0, 0.0), Point::new(0.0, 1.0)];
-        assert_eq!(segment_intersection(line1, line2), Some(Point::new(0.0, 0.0)));
+    fn near_collinear_breakpoints_converge() {
+        let converging = (Point::new(0.5000000000000001, 0.5), Point::new(12.0, 12.0), Point::new(24.0, 24.0));
+        assert!(breakpoints_converge(converging));
+        let diverging = (Point::new(0.5, 0.5000000000000001), Point::new(12.0, 12.0), Point::new(24.0, 24.0));
+        assert!(!breakpoints_converge(diverging));
     }
 
     #[test]
-    fn simple_segments_nonintersect() {
-        let line1 = [Point::new(-1.0, 10.0), Point::new(1.0, 10.0)];
-        let line2 = [Point::new(0.0, -1.0), Point::new(0.0, 1.0)];
-        assert_eq!(segment_intersection(line1, line2), None);
+    fn collinear_breakpoints_do_not_converge() {
+        let collinear = (Point::new(0.43782840126630695, 2.3941809460788566),
+                         Point::new(-0.17759347401849057, 0.547915320224464),
+                         Point::new(-4.485546601012073, -12.375944060756284));
+        assert!(!breakpoints_converge(collinear));
     }
 }
@@ -14,14 +14,37 @@ extern crate ordered_float;
 extern crate fnv;
 
 mod geometry;
+mod predicates;
 mod point;
+mod bounding_box;
 mod dcel;
 mod beachline;
 mod event;
 mod voronoi;
+mod clip;
+mod delaunay;
 mod lloyd;
+mod error;
+mod validate;
+mod power;
+mod apollonius;
+mod multiplicative;
+mod metric;
+mod segments;
+#[cfg(test)]
+mod test_utils;
 
-pub use voronoi::voronoi;
+pub use voronoi::{voronoi, try_voronoi, voronoi_with_options, voronoi_in_convex_polygon, voronoi_in_polygon, voronoi_unbounded, VoronoiOptions, DuplicateSites};
 pub use point::Point;
-pub use dcel::{DCEL, make_line_segments, make_polygons};
+pub use bounding_box::BoundingBox;
+pub use predicates::{orient2d, incircle};
+pub use dcel::{DCEL, Neighbor, Ray, VertexId, HalfEdgeId, FaceId, Cycle, FaceHalfEdges, VertexHalfEdges, make_line_segments, make_polygons, make_site_polygons, make_site_polygons_with_holes, try_make_polygons, try_make_site_polygons_with_holes};
+pub use delaunay::{delaunay_triangulation, Triangulation};
 pub use lloyd::{lloyd_relaxation, polygon_centroid};
+pub use power::power_diagram;
+pub use apollonius::apollonius_diagram;
+pub use multiplicative::multiplicative_diagram;
+pub use metric::{voronoi_with_metric, Metric};
+pub use segments::{segment_voronoi, Site};
+pub use error::VoronoiError;
+pub use validate::Violation;
@@ -1,6 +1,7 @@
 use point::Point;
 use voronoi::voronoi;
-use dcel::make_polygons;
+use dcel::make_site_polygons;
+use bounding_box::BoundingBox;
 
 /// Computes the centroid of a polygon.
 pub fn polygon_centroid(pts: &Vec<Point>) -> Point {
@@ -13,9 +14,35 @@ pub fn polygon_centroid(pts: &Vec<Point>) -> Point {
 
 /// Produces the Lloyd Relaxation of a set of points.
 ///
-/// Each point is moved to the centroid of its Voronoi cell.
-pub fn lloyd_relaxation(pts: Vec<Point>, box_size: f64) -> Vec<Point> {
-    let voronoi = voronoi(pts, box_size);
-    let faces = make_polygons(&voronoi);
-    faces.iter().map(polygon_centroid).collect::<Vec<Point>>()
+/// Each point is moved to the centroid of its Voronoi cell. Points without a
+/// cell, as a later duplicate of another point or a point outside the box,
+/// stay where they are, so the result has one point for each input point.
+pub fn lloyd_relaxation<B: Into<BoundingBox>>(pts: Vec<Point>, bbox: B) -> Vec<Point> {
+    let mut relaxed = pts.clone();
+    let voronoi = voronoi(pts, bbox);
+    for (site, face) in make_site_polygons(&voronoi) {
+        relaxed[site] = polygon_centroid(&face);
+    }
+    relaxed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_keep_their_places() {
+        let pts = vec![Point::new(2.0, 2.0), Point::new(8.0, 3.0), Point::new(2.0, 2.0), Point::new(5.0, 8.0), Point::new(15.0, 5.0)];
+        let relaxed = lloyd_relaxation(pts.clone(), 10.);
+        assert_eq!(relaxed.len(), pts.len());
+
+        let faces = make_site_polygons(&voronoi(pts.clone(), 10.));
+        assert_eq!(faces.iter().map(|&(site, _)| site).collect::<Vec<usize>>(), vec![0, 1, 3]);
+        for (site, face) in faces {
+            assert_eq!(relaxed[site], polygon_centroid(&face));
+        }
+        // the duplicate and the point outside the box have no cell
+        assert_eq!(relaxed[2], pts[2]);
+        assert_eq!(relaxed[4], pts[4]);
+    }
 }
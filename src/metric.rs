@@ -0,0 +1,487 @@
+use ordered_float::OrderedFloat;
+use point::Point;
+use dcel::{DCEL, Ends, add_path, add_faces, end_vertex, close_box};
+use voronoi::{VoronoiOptions, voronoi_with_options, prepare_sites};
+use error::VoronoiError;
+use bounding_box::{BoundingBox, BoxGrid};
+use geometry::clip_polygon;
+
+// Distance, relative to the diagonal of the box, within which the ends of
+// two edges are taken to be the same vertex
+const VERTEX_TOLERANCE: f64 = 1e-9;
+// Difference in distance, relative to the diagonal of the box, within which
+// two sites are taken to be equally near
+const TIE_TOLERANCE: f64 = 1e-12;
+// Distance, relative to the diagonal of the box, to the side of a bisector
+// at which to tell which site is nearer where several are equally near on it
+const NUDGE_TOLERANCE: f64 = 1e-6;
+
+/// The distance between points under which `voronoi_with_metric` builds cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// The straight line distance, as for `voronoi_with_options`.
+    Euclidean,
+    /// The L1 distance, the sum of the distances along each axis.
+    Manhattan,
+    /// The L-infinity distance, the greatest of the distances along each axis.
+    Chebyshev,
+}
+
+impl Metric {
+    // Maps a point to the frame in which the distance is the Manhattan
+    // distance, up to a factor. The Chebyshev distance is the Manhattan
+    // distance turned by 45 degrees.
+    fn into_frame(self, pt: Point) -> Point {
+        match self {
+            Metric::Chebyshev => Point::new(pt.x() + pt.y(), pt.x() - pt.y()),
+            _ => pt,
+        }
+    }
+
+    fn out_of_frame(self, pt: Point) -> Point {
+        match self {
+            Metric::Chebyshev => Point::new((pt.x() + pt.y()) / 2., (pt.x() - pt.y()) / 2.),
+            _ => pt,
+        }
+    }
+}
+
+/// Computes the Voronoi diagram of a set of points under a metric, clipped
+/// to a bounding box.
+///
+/// Under the Manhattan and Chebyshev metrics the edges between cells are paths
+/// of straight edges, at most three to a pair of sites. Two sites on a diagonal
+/// of each other (Manhattan), or on a line parallel to an axis (Chebyshev), are
+/// equally near over whole areas; these are split between them along the
+/// straight line through their midpoint square to the line joining them. Each
+/// cell is a single face, the diagram can be walked and turned into polygons
+/// as for `voronoi_with_options`, and the second element of the result maps
+/// input indices to sites in the same way.
+///
+/// Only sites near each other are compared, so for sites spread over the box
+/// the time grows about in proportion to their number, though it is still
+/// many times that of the sweep used for the Euclidean metric.
+///
+/// Fails as `voronoi_with_options` does.
+pub fn voronoi_with_metric<B: Into<BoundingBox>>(points: Vec<Point>, bbox: B, metric: Metric, options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    if metric == Metric::Euclidean {
+        return voronoi_with_options(points, bbox, options);
+    }
+    let bbox = bbox.into();
+    if !bbox.is_valid() {
+        return Err(VoronoiError::InvalidBoundingBox);
+    }
+    let (sites, site_map) = prepare_sites(points, options)?;
+    let mut dcel = metric_in_box(&sites, &bbox, metric)?;
+    if options.compact { dcel.compact(); }
+    Ok((dcel, site_map))
+}
+
+// A straight stretch of a bisector, as (origin, direction, start, end), where
+// start and end are parameters along the direction and may be infinite
+type Segment = (Point, Point, f64, f64);
+// A parameter along a segment, with the side of the box there, if any
+type End = (f64, Option<usize>);
+
+// Builds the diagram of the sites, as (input index, point), with distinct
+// points. Each edge of the diagram is a stretch of the bisector of two
+// sites along which no other site is nearer, and which lies in the box.
+// Only sites near enough to share an edge are paired, and only sites near
+// both are checked against each stretch, which the grid finds.
+fn metric_in_box(sites: &[(usize, Point)], bbox: &BoundingBox, metric: Metric) -> Result<DCEL, VoronoiError> {
+    let mut dcel = DCEL::new();
+    let snap = VERTEX_TOLERANCE * bbox.diagonal();
+    let tie = TIE_TOLERANCE * bbox.diagonal();
+    let nudge = NUDGE_TOLERANCE * bbox.diagonal();
+    let mut ends = Ends::with_corners(&mut dcel, bbox, snap);
+    let framed: Vec<Point> = sites.iter().map(|&(_, pt)| metric.into_frame(pt)).collect();
+    // the sites turned by 45 degrees, in the cells of a grid
+    let turned: Vec<Point> = framed.iter().map(|&pt| turn(pt)).collect();
+    let grid = BoxGrid::new(&turned.iter().map(|&pt| BoundingBox::new(pt, pt)).collect::<Vec<_>>());
+    let corners: Vec<Point> = bbox.corners().iter().map(|&corner| metric.into_frame(corner)).collect();
+    let reach: Vec<f64> = (0..sites.len()).map(|site| cell_reach(&turned, &grid, site, &corners, tie) + nudge).collect();
+
+    for first in 0..sites.len() {
+        for second in near_sites(&turned, &grid, turned[first], 2. * reach[first]) {
+            // the two can share an edge only where both are within reach,
+            // and a site nearer than them there is within twice that of them
+            let limit = reach[first].min(reach[second]);
+            if second <= first || distances(framed[second] - framed[first]).0 > 2. * limit { continue; }
+            let mut others = near_sites(&turned, &grid, turned[first], 2. * limit);
+            others.retain(|&other| other != first && other != second && distances(framed[other] - framed[second]).0 <= 2. * limit);
+            for (frame_origin, frame_direction, start, end) in bisector_segments(framed[first], framed[second]) {
+                // the parameters are the same in the frame and in the plane
+                let (origin, direction) = (metric.out_of_frame(frame_origin), metric.out_of_frame(frame_direction));
+                let (start, end) = match clip_to_box(origin, direction, start, end, bbox) {
+                    Some(stretch) => stretch,
+                    None => continue,
+                };
+                // no other site may be nearer than either of the two; the
+                // distances to both are the same, but which is nearer than
+                // the other breaks ties differently
+                let mut excluded = beyond_intervals((frame_origin, frame_direction, start.0, end.0), framed[first], limit);
+                for &other in &others {
+                    for &site in &[first, second] {
+                        excluded.extend(nearer_intervals((frame_origin, frame_direction, start.0, end.0), framed[site], framed[other], tie, nudge));
+                    }
+                }
+                // each site lies wholly on one side of each segment of the bisector
+                let (left, right) = if direction.cross(sites[first].1 - origin) > 0. { (first, second) } else { (second, first) };
+                for (from, to) in free_stretches(start, end, excluded) {
+                    let from_vertex = end_vertex(&mut dcel, &mut ends, origin + direction * from.0, from.1, bbox);
+                    let to_vertex = end_vertex(&mut dcel, &mut ends, origin + direction * to.0, to.1, bbox);
+                    if from_vertex == to_vertex { continue; }
+                    add_path(&mut dcel, &[from_vertex, to_vertex], Some(sites[left].0), Some(sites[right].0))?;
+                }
+            }
+        }
+    }
+
+    close_box(&mut dcel, &ends, bbox, |pt| nearest_site(sites, &framed, metric.into_frame(pt)))?;
+    add_faces(&mut dcel)?;
+    Ok(dcel)
+}
+
+// The bisector of two distinct points in the frame, as segments in order
+// along it. Sites further apart along x than along y are split by rays
+// parallel to the y axis, joined by a diagonal segment between them, and
+// the other way around; sites on a diagonal by the line square to it.
+fn bisector_segments(first: Point, second: Point) -> Vec<Segment> {
+    let offset = second - first;
+    if offset.x().abs() == offset.y().abs() {
+        return vec![((first + second) * 0.5, Point::new(-offset.y(), offset.x()), f64::NEG_INFINITY, f64::INFINITY)];
+    }
+    // swap the axes so that the sites are further apart along x
+    let swap = offset.y().abs() > offset.x().abs();
+    let flip = |pt: Point| if swap { Point::new(pt.y(), pt.x()) } else { pt };
+    let (first, second) = (flip(first), flip(second));
+    // the point of the bisector at a height, where the difference in distance
+    // along y is made up along x
+    let toward = (second.x() - first.x()).signum();
+    let across = |y: f64| Point::new((first.x() + second.x() + toward * ((y - second.y()).abs() - (y - first.y()).abs())) / 2., y);
+    let (bottom, top) = (across(first.y().min(second.y())), across(first.y().max(second.y())));
+    let up = Point::new(0., 1.);
+    let segments = if bottom == top {
+        vec![(bottom, up, f64::NEG_INFINITY, f64::INFINITY)]
+    } else {
+        vec![(bottom, up, f64::NEG_INFINITY, 0.), (bottom, top - bottom, 0., 1.), (top, up, 0., f64::INFINITY)]
+    };
+    segments.into_iter().map(|(origin, direction, start, end)| (flip(origin), flip(direction), start, end)).collect()
+}
+
+// The stretch of a segment in the box, or None if it misses the box or
+// runs along a side of it
+fn clip_to_box(origin: Point, direction: Point, start: f64, end: f64, bbox: &BoundingBox) -> Option<(End, End)> {
+    let (mut start, mut end) = ((start, None), (end, None));
+    let slabs = [
+        (origin.x(), direction.x(), bbox.min.x(), 3, bbox.max.x(), 1),
+        (origin.y(), direction.y(), bbox.min.y(), 0, bbox.max.y(), 2),
+    ];
+    for &(position, speed, low, low_side, high, high_side) in &slabs {
+        if speed == 0. {
+            if position <= low || position >= high { return None; }
+            continue;
+        }
+        let (enter, leave) = if speed > 0. {
+            (((low - position) / speed, low_side), ((high - position) / speed, high_side))
+        } else {
+            (((high - position) / speed, high_side), ((low - position) / speed, low_side))
+        };
+        if enter.0 > start.0 { start = (enter.0, Some(enter.1)); }
+        if leave.0 < end.0 { end = (leave.0, Some(leave.1)); }
+    }
+    if start.0 < end.0 { Some((start, end)) } else { None }
+}
+
+// The distance along an offset in the frame, followed by the Chebyshev
+// distance in the frame, which breaks ties
+fn distances(offset: Point) -> (f64, f64) {
+    let (x, y) = (offset.x().abs(), offset.y().abs());
+    (x + y, x.max(y))
+}
+
+// The intervals of a finite segment of the bisector of a site in the frame
+// along which another site is nearer, on the side of the site
+fn nearer_intervals(segment: Segment, site: Point, other: Point, tie: f64, nudge: f64) -> Vec<(f64, f64)> {
+    let (origin, direction, start, end) = segment;
+    // the distances are linear between the parameters at which the offset
+    // to either site crosses an axis or a diagonal
+    let mut breaks = vec![start, end];
+    for &pt in &[site, other] {
+        let offset = origin - pt;
+        let crossings = [
+            (offset.x(), direction.x()),
+            (offset.y(), direction.y()),
+            (offset.x() + offset.y(), direction.x() + direction.y()),
+            (offset.x() - offset.y(), direction.x() - direction.y()),
+        ];
+        for &(position, speed) in &crossings {
+            let param = -position / speed;
+            if param > start && param < end { breaks.push(param); }
+        }
+    }
+    breaks.sort_by_key(|&param| OrderedFloat(param));
+
+    let difference = |param: f64| {
+        let pt = origin + direction * param;
+        let (near, near_tie) = distances(pt - other);
+        let (own, own_tie) = distances(pt - site);
+        (near - own, near_tie - own_tie)
+    };
+    // square to the segment, toward the site
+    let length = direction.dot(direction).sqrt();
+    let mut toward = Point::new(-direction.y(), direction.x()) * (1. / length);
+    if toward.dot(site - origin) < 0. { toward = toward * -1.; }
+
+    let mut nearer = vec![];
+    for pair in breaks.windows(2) {
+        let (at_start, at_end) = (difference(pair[0]), difference(pair[1]));
+        if at_start.0.abs() > tie || at_end.0.abs() > tie {
+            nearer.extend(negative_part(pair[0], pair[1], at_start.0, at_end.0, tie));
+            continue;
+        }
+        // where the sites are equally near along a stretch, so too may be
+        // a third, but the cell of the site lies to one side of the stretch,
+        // and there the third site may be nearer or further
+        let step = nudge.min((pair[1] - pair[0]) * length / 4.);
+        let beside = origin + direction * ((pair[0] + pair[1]) / 2.) + toward * step;
+        let off = distances(beside - other).0 - distances(beside - site).0;
+        if off < -step / 2. {
+            nearer.push((pair[0], pair[1]));
+        } else if off <= step / 2. {
+            nearer.extend(negative_part(pair[0], pair[1], at_start.1, at_end.1, tie));
+        }
+    }
+    nearer
+}
+
+// The intervals of a finite segment in the frame along which it is further
+// than a distance from a site
+fn beyond_intervals(segment: Segment, site: Point, limit: f64) -> Vec<(f64, f64)> {
+    let (origin, direction, start, end) = segment;
+    // the distance is linear between the parameters at which the offset
+    // to the site crosses an axis
+    let offset = origin - site;
+    let mut breaks = vec![start, end];
+    for &(position, speed) in &[(offset.x(), direction.x()), (offset.y(), direction.y())] {
+        let param = -position / speed;
+        if param > start && param < end { breaks.push(param); }
+    }
+    breaks.sort_by_key(|&param| OrderedFloat(param));
+    let spare = |param: f64| limit - distances(origin + direction * param - site).0;
+    breaks.windows(2).filter_map(|pair| negative_part(pair[0], pair[1], spare(pair[0]), spare(pair[1]), 0.)).collect()
+}
+
+// Where a linear function with the given values at start and end is
+// negative, taking values within `tie` of zero to be zero
+fn negative_part(start: f64, end: f64, at_start: f64, at_end: f64, tie: f64) -> Option<(f64, f64)> {
+    let zeroed = |value: f64| if value.abs() <= tie { 0. } else { value };
+    let (at_start, at_end) = (zeroed(at_start), zeroed(at_end));
+    let root = || start + (end - start) * at_start / (at_start - at_end);
+    match (at_start < 0., at_end < 0.) {
+        (true, true) => Some((start, end)),
+        (true, false) => Some((start, root())),
+        (false, true) => Some((root(), end)),
+        (false, false) => None,
+    }
+}
+
+// The stretches from start to end outside the excluded intervals, in order
+fn free_stretches(start: End, end: End, mut excluded: Vec<(f64, f64)>) -> Vec<(End, End)> {
+    excluded.sort_by_key(|&(from, _)| OrderedFloat(from));
+    let mut stretches = vec![];
+    let mut reached = start;
+    for (from, to) in excluded {
+        if from >= end.0 { break; }
+        if from > reached.0 { stretches.push((reached, (from, None))); }
+        if to > reached.0 { reached = (to, None); }
+    }
+    if end.0 > reached.0 { stretches.push((reached, end)); }
+    stretches
+}
+
+// the input index of the site nearest a point in the frame, the first of them on a tie
+fn nearest_site(sites: &[(usize, Point)], framed: &[Point], pt: Point) -> Option<usize> {
+    (0..sites.len())
+        .min_by_key(|&site| {
+            let (distance, tie) = distances(pt - framed[site]);
+            (OrderedFloat(distance), OrderedFloat(tie))
+        })
+        .map(|site| sites[site].0)
+}
+
+// Turns a point in the frame by 45 degrees, so that the distance in the
+// frame is the greatest distance along either axis
+fn turn(pt: Point) -> Point {
+    Point::new(pt.x() + pt.y(), pt.x() - pt.y())
+}
+
+// The square with its center at a point, reaching a distance along each axis
+fn around(center: Point, distance: f64) -> BoundingBox {
+    BoundingBox::new(center - Point::new(distance, distance), center + Point::new(distance, distance))
+}
+
+// The greatest distance along either axis
+fn square_distance(offset: Point) -> f64 {
+    offset.x().abs().max(offset.y().abs())
+}
+
+// The sites within a distance of a point, both turned, in order
+fn near_sites(turned: &[Point], grid: &BoxGrid, center: Point, distance: f64) -> Vec<usize> {
+    let mut near = grid.near(&around(center, distance));
+    near.retain(|&site| square_distance(turned[site] - center) <= distance);
+    near
+}
+
+// A bound on the distance in the frame from a site to any location of its
+// cell in the box, given by its corners in the frame. A location at some
+// distance from the site, in a quadrant of it when turned, is nearer another
+// site if that site lies inside the square of that side with a corner at the
+// site in the same quadrant.
+fn cell_reach(turned: &[Point], grid: &BoxGrid, site: usize, corners: &[Point], tie: f64) -> f64 {
+    let center = turned[site];
+    let area: Vec<Point> = corners.iter().map(|&corner| turn(corner)).collect();
+    let quadrants = [Point::new(1., 1.), Point::new(-1., 1.), Point::new(-1., -1.), Point::new(1., -1.)];
+    // how far the box reaches in each quadrant
+    let bounds: Vec<f64> = quadrants.iter().map(|&quadrant| {
+        let part = clip_polygon(&clip_polygon(&area, center, Point::new(quadrant.x(), 0.)), center, Point::new(0., quadrant.y()));
+        part.iter().map(|&pt| square_distance(pt - center)).fold(0., f64::max)
+    }).collect();
+    // look further until the nearest site inside each quadrant, if nearer
+    // than the box reaches, has been seen
+    let mut distance = grid.side();
+    loop {
+        let mut nearest = bounds.clone();
+        for other in near_sites(turned, grid, center, distance) {
+            let offset = turned[other] - center;
+            for (quadrant, nearest) in quadrants.iter().zip(nearest.iter_mut()) {
+                // a site barely off the edges is not taken to be inside
+                if offset.x() * quadrant.x() > 2. * tie && offset.y() * quadrant.y() > 2. * tie {
+                    *nearest = nearest.min(square_distance(offset));
+                }
+            }
+        }
+        let all_seen = grid.covers(&around(center, distance));
+        if all_seen || nearest.iter().all(|&reach| reach <= distance) {
+            return nearest.iter().cloned().fold(0., f64::max);
+        }
+        distance *= 2.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcel::{make_site_polygons, make_site_polygons_with_holes, make_polygons};
+    use test_utils::{assert_fills_box, assert_grid_owned};
+    use validate::Validated;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // the distance under the metric, followed by the one that breaks ties
+    fn distance(pt: Point, site: Point, metric: Metric) -> (f64, f64) {
+        let (x, y) = ((pt.x() - site.x()).abs(), (pt.y() - site.y()).abs());
+        if metric == Metric::Manhattan { (x + y, x.max(y)) } else { (x.max(y), x + y) }
+    }
+
+    fn assert_same_polygon(poly: &[Point], expected: &[Point]) {
+        assert_eq!(poly.len(), expected.len(), "{:?} is not {:?}", poly, expected);
+        assert!(expected.iter().all(|&corner| poly.iter().any(|&pt| (pt - corner).dot(pt - corner).sqrt() < 1e-9)), "{:?} is not {:?}", poly, expected);
+    }
+
+    // each site has one face, the faces tile the box, and each location on a
+    // grid off the bisectors lies in the face of the site nearest it
+    fn assert_metric_cells(sites: &[Point], size: f64, metric: Metric) {
+        let dcel = voronoi_with_metric(sites.to_vec(), size, metric, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_eq!(faces.len(), sites.len());
+        assert_fills_box(&faces, size);
+
+        assert_grid_owned(&faces, size, |pt| {
+            let order = |first: &(f64, f64), second: &(f64, f64)| first.0.total_cmp(&second.0).then(first.1.total_cmp(&second.1));
+            let site = (0..sites.len()).min_by(|&a, &b| order(&distance(pt, sites[a], metric), &distance(pt, sites[b], metric))).unwrap();
+            let nearest = distance(pt, sites[site], metric);
+            let next = (0..sites.len()).filter(|&other| other != site).map(|other| distance(pt, sites[other], metric)).min_by(order).unwrap();
+            if next.0 - nearest.0 < 1e-6 && (next.0 != nearest.0 || next.1 - nearest.1 < 1e-6) { None } else { Some(site) }
+        });
+    }
+
+    #[test]
+    fn euclidean_gives_voronoi_diagram() {
+        let points = vec![Point::new(1.0, 2.0), Point::new(7.0, 3.0), Point::new(4.0, 8.0), Point::new(5.5, 5.0)];
+        let polys = make_polygons(&voronoi_with_metric(points.clone(), 10., Metric::Euclidean, &VoronoiOptions::default()).unwrap().validated().0);
+        assert_eq!(polys, make_polygons(&::voronoi::voronoi(points, 10.)));
+    }
+
+    #[test]
+    fn manhattan_bisector_bends() {
+        let dcel = voronoi_with_metric(vec![Point::new(1.0, 2.0), Point::new(9.0, 4.0)], 10., Metric::Manhattan, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons(&dcel);
+        assert_eq!(faces.len(), 2);
+        let expected = [Point::new(0., 0.), Point::new(6., 0.), Point::new(6., 2.), Point::new(4., 4.), Point::new(4., 10.), Point::new(0., 10.)];
+        assert_same_polygon(&faces[0].1, &expected);
+    }
+
+    #[test]
+    fn chebyshev_bisector_bends() {
+        // a stretch square to the sites where both are nearest along y, and
+        // diagonals beside it, where one is nearest along x
+        let dcel = voronoi_with_metric(vec![Point::new(5.0, 2.0), Point::new(6.0, 8.0)], 10., Metric::Chebyshev, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons(&dcel);
+        assert_eq!(faces.len(), 2);
+        let expected = [Point::new(0., 0.), Point::new(10., 0.), Point::new(10., 3.), Point::new(8., 5.), Point::new(3., 5.), Point::new(0., 8.)];
+        assert_same_polygon(&faces[0].1, &expected);
+    }
+
+    #[test]
+    fn diagonal_ties_split_square_to_sites() {
+        let dcel = voronoi_with_metric(vec![Point::new(3.0, 3.0), Point::new(7.0, 7.0)], 10., Metric::Manhattan, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons(&dcel);
+        assert_same_polygon(&faces[0].1, &[Point::new(0., 0.), Point::new(10., 0.), Point::new(0., 10.)]);
+
+        let dcel = voronoi_with_metric(vec![Point::new(3.0, 5.0), Point::new(7.0, 5.0)], 10., Metric::Chebyshev, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons(&dcel);
+        assert_same_polygon(&faces[0].1, &[Point::new(0., 0.), Point::new(5., 0.), Point::new(5., 10.), Point::new(0., 10.)]);
+    }
+
+    #[test]
+    fn three_sites_tied_along_stretch() {
+        // all three sites are equally near along x = 25 below y = 15, where
+        // the first two split their tie, and the last is nearer only to the left
+        let sites = vec![Point::new(60.0, 20.0), Point::new(50.0, 30.0), Point::new(20.0, 50.0)];
+        assert_metric_cells(&sites, 100., Metric::Manhattan);
+        let sites = vec![Point::new(90.0, 80.0), Point::new(10.0, 90.0), Point::new(40.0, 60.0)];
+        assert_metric_cells(&sites, 100., Metric::Manhattan);
+    }
+
+    #[test]
+    fn random_sites() {
+        for seed in 0..4 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let sites: Vec<Point> = (0..30).map(|_| Point::new(rng.next_f64() * 100., rng.next_f64() * 100.)).collect();
+            assert_metric_cells(&sites, 100., Metric::Manhattan);
+            assert_metric_cells(&sites, 100., Metric::Chebyshev);
+        }
+    }
+
+    #[test]
+    fn many_sites() {
+        // enough sites that most pairs are too far apart to be compared
+        let mut rng = StdRng::from_seed(&[3][..]);
+        let sites: Vec<Point> = (0..120).map(|_| Point::new(rng.next_f64() * 100., rng.next_f64() * 100.)).collect();
+        assert_metric_cells(&sites, 100., Metric::Manhattan);
+        assert_metric_cells(&sites, 100., Metric::Chebyshev);
+    }
+
+    #[test]
+    fn grid_sites() {
+        // many pairs lie on diagonals or lines parallel to the axes
+        for seed in 0..4 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let mut sites: Vec<Point> = (0..30).map(|_| Point::new((rng.next_f64() * 20.).floor() * 5., (rng.next_f64() * 20.).floor() * 5.)).collect();
+            sites.sort_by_key(|pt| (OrderedFloat(pt.x()), OrderedFloat(pt.y())));
+            sites.dedup();
+            assert_metric_cells(&sites, 100., Metric::Manhattan);
+            assert_metric_cells(&sites, 100., Metric::Chebyshev);
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use std::f64::consts::PI;
+use ordered_float::OrderedFloat;
+use point::Point;
+use dcel::{DCEL, Ends, add_vertex, add_path, add_faces, end_vertex, close_box};
+use voronoi::{VoronoiOptions, prepare_weighted_sites};
+use error::VoronoiError;
+use bounding_box::{BoundingBox, BoxGrid, clamp_to_box, side_offset};
+use geometry::clip_polygon;
+
+// Distance, relative to the diagonal of the box, within which the ends of
+// two edges are taken to be the same vertex. Where circles touch, the point
+// is known only to about the square root of the precision.
+const VERTEX_TOLERANCE: f64 = 1e-7;
+// Newton steps taken to place each end of an edge
+const VERTEX_STEPS: usize = 4;
+// Distance, relative to the diagonal of the box, added to the bound on how
+// far the cell of a site reaches from it
+const REACH_TOLERANCE: f64 = 1e-6;
+// Equal sectors of the directions around a site, in bounding cells
+const SECTORS: usize = 8;
+
+/// Computes the multiplicatively weighted Voronoi diagram of a set of weighted
+/// points, clipped to a bounding box.
+///
+/// The distance from a location to a site is the distance to its point divided
+/// by its weight, as for facilities that serve further the greater their
+/// capacity, and the cell of a site is where its distance is least. The cells are
+/// bounded by circles, around the lighter of each pair of sites, or by straight
+/// lines between sites of equal weight; the circular arcs become paths of straight
+/// edges within `tolerance` of them. A cell may be in several pieces, each a face of
+/// the site, and a face may have holes (see `make_site_polygons_with_holes`).
+/// Returns the Doubly Connected Edge List, together with the indices of the input
+/// sites whose cells are empty, in increasing order; these have no face. Sites
+/// whose cells miss the bounding box have no face either, but are not listed.
+///
+/// Only sites whose cells may meet are paired, and only sites near both are
+/// compared with them, so for sites of similar weights spread over the box the
+/// time grows somewhat faster than their number. A site much heavier than those
+/// around it reaches far, and is compared with them all.
+///
+/// Coincident points are treated as by `power_diagram`. Fails as it does, with
+/// `VoronoiError::NonPositiveWeight` if a weight is zero or negative, and with
+/// `VoronoiError::InvalidTolerance` unless the tolerance is positive and finite.
+pub fn multiplicative_diagram<B: Into<BoundingBox>>(sites: Vec<(Point, f64)>, bbox: B, tolerance: f64, options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let bbox = bbox.into();
+    if !bbox.is_valid() {
+        return Err(VoronoiError::InvalidBoundingBox);
+    }
+    if tolerance <= 0. || !tolerance.is_finite() {
+        return Err(VoronoiError::InvalidTolerance);
+    }
+    let (sites, weights, hidden) = prepare_weighted_sites(sites, options)?;
+    if let Some(index) = weights.iter().position(|&weight| weight <= 0.) {
+        return Err(VoronoiError::NonPositiveWeight { index });
+    }
+    let sites: Vec<(usize, Point, f64)> = sites.into_iter().map(|(index, pt)| (index, pt, weights[index])).collect();
+
+    let mut dcel = multiplicative_in_box(&sites, &bbox, tolerance)?;
+    if options.compact { dcel.compact(); }
+    Ok((dcel, hidden))
+}
+
+// The curve on which two sites are at the same distance, traced so that the
+// cell of the first site it is made for lies to its left
+#[derive(Clone, Copy, Debug)]
+enum Bisector {
+    // center and radius, traced counterclockwise around the lighter site
+    Circle(Point, f64),
+    // a point on the line and a unit vector along it
+    Line(Point, Point),
+}
+
+impl Bisector {
+    fn point_at(&self, param: f64) -> Point {
+        match *self {
+            Bisector::Circle(center, radius) => center + Point::new(param.cos(), param.sin()) * radius,
+            Bisector::Line(origin, direction) => origin + direction * param,
+        }
+    }
+}
+
+// What ends a stretch of a bisector: a side of the box, numbered
+// counterclockwise from the bottom, each running from the corner of the
+// same index, a third site, by its place in the sites, as near as the two,
+// or the bound on how far the cells reach, beyond which the sites not
+// compared are nearer
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Bound {
+    Side(usize),
+    Site(usize),
+    Reach,
+}
+
+// A stretch of a bisector between two parameters, with what ends it at each end
+#[derive(Clone, Copy, Debug)]
+struct Piece {
+    start: f64,
+    end: f64,
+    start_bound: Bound,
+    end_bound: Bound,
+}
+
+impl Bound {
+    fn side(self) -> Option<usize> {
+        match self {
+            Bound::Side(side) => Some(side),
+            Bound::Site(_) | Bound::Reach => None,
+        }
+    }
+}
+
+// How much of a bisector borders both cells within the box: stretches of
+// it, or the whole circle, traced from the angle at which it comes nearest
+// to leaving either cell or the box
+enum Border {
+    Pieces(Vec<Piece>),
+    Ring(f64),
+}
+
+// An interval of parameters excluded from a bisector, as (start, end, bound),
+// where bound is the side of the box or the site that excludes it
+type Excluded = (f64, f64, Bound);
+
+// Builds the diagram of the sites, as (input index, point, weight), with
+// distinct points. Each edge of the diagram is a stretch of the bisector
+// of two sites along which no other site is nearer, and which lies in the
+// box; the box is split where the edges meet it.
+fn multiplicative_in_box(sites: &[(usize, Point, f64)], bbox: &BoundingBox, tolerance: f64) -> Result<DCEL, VoronoiError> {
+    let mut dcel = DCEL::new();
+    let snap = VERTEX_TOLERANCE * bbox.diagonal();
+    let mut ends = Ends::with_corners(&mut dcel, bbox, snap);
+    let grid = BoxGrid::new(&sites.iter().map(|&(_, pt, _)| around(pt, 0.)).collect::<Vec<_>>());
+    let reach: Vec<f64> = (0..sites.len()).map(|place| cell_reach(sites, &grid, place, bbox) + REACH_TOLERANCE * bbox.diagonal()).collect();
+    // the disks within which the cells lie
+    let disks = BoxGrid::new(&sites.iter().zip(&reach).map(|(&(_, pt, _), &reach)| around(pt, reach)).collect::<Vec<_>>());
+    let meeting = |place: usize| {
+        let mut near = disks.near(&around(sites[place].1, reach[place]));
+        near.retain(|&other| distance(sites[place].1, sites[other].1) <= reach[place] + reach[other]);
+        near
+    };
+
+    for first in 0..sites.len() {
+        for second in meeting(first) {
+            if second <= first { continue; }
+            // trace each circle around the lighter site
+            let (left, right) = if sites[second].2 < sites[first].2 { (second, first) } else { (first, second) };
+            let bisector = match make_bisector(sites[left], sites[right], bbox) {
+                Some(bisector) => bisector,
+                None => continue,
+            };
+            // the edge lies within the smaller disk, and a location on it
+            // that neither cell takes lies in the disk of the site that
+            // does, which meets the disks of both
+            let (within, beside) = if reach[left] < reach[right] { (left, right) } else { (right, left) };
+            let others: Vec<usize> = meeting(within).into_iter()
+                .filter(|&other| other != left && other != right && distance(sites[beside].1, sites[other].1) <= reach[beside] + reach[other])
+                .collect();
+            let pieces = match bisector_pieces(bisector, left, (sites[within].1, reach[within]), &others, sites, bbox) {
+                Border::Pieces(pieces) => pieces,
+                Border::Ring(origin) => {
+                    // the last point is the first, which is shared with
+                    // whatever the circle touches there
+                    let mut params = arc_params(bisector, origin, origin + 2. * PI, tolerance);
+                    params.pop();
+                    let mut ring = vec![end_vertex(&mut dcel, &mut ends, bisector.point_at(origin), None, bbox)];
+                    for &param in &params[1..] {
+                        ring.push(add_vertex(&mut dcel, clamp_to_box(bisector.point_at(param), bbox)));
+                    }
+                    let closed: Vec<usize> = ring.iter().chain(ring.first()).cloned().collect();
+                    add_path(&mut dcel, &closed, Some(sites[left].0), Some(sites[right].0))?;
+                    continue;
+                }
+            };
+            for piece in pieces {
+                let mut end_at = |param: f64, bound: Bound| {
+                    let pt = refine_vertex(bisector.point_at(param), sites[left], sites[right], bound, sites, bbox);
+                    end_vertex(&mut dcel, &mut ends, pt, bound.side(), bbox)
+                };
+                let (start, end) = (end_at(piece.start, piece.start_bound), end_at(piece.end, piece.end_bound));
+                // a piece whose ends meet is either too short to keep or a
+                // loop through a point where circles touch
+                if start == end {
+                    let middle = bisector.point_at((piece.start + piece.end) / 2.);
+                    let offset = middle - dcel.vertices[start].coordinates;
+                    if offset.dot(offset).sqrt() <= snap { continue; }
+                }
+                let params = arc_params(bisector, piece.start, piece.end, tolerance);
+                let mut path = vec![start];
+                for &param in &params[1..params.len() - 1] {
+                    path.push(add_vertex(&mut dcel, clamp_to_box(bisector.point_at(param), bbox)));
+                }
+                path.push(end);
+                add_path(&mut dcel, &path, Some(sites[left].0), Some(sites[right].0))?;
+            }
+        }
+    }
+
+    close_box(&mut dcel, &ends, bbox, |pt| nearest_site(sites, pt))?;
+    add_faces(&mut dcel)?;
+    Ok(dcel)
+}
+
+// The bisector of two sites, or None if it misses the box or runs along
+// a side of it, so that it bounds no cell within the box.
+fn make_bisector(left: (usize, Point, f64), right: (usize, Point, f64), bbox: &BoundingBox) -> Option<Bisector> {
+    let (_, left_pt, left_weight) = left;
+    let (_, right_pt, right_weight) = right;
+    let offset = right_pt - left_pt;
+    if left_weight == right_weight {
+        let along = Point::new(-offset.y(), offset.x());
+        let direction = along * (1. / along.dot(along).sqrt());
+        let origin = (left_pt + right_pt) * 0.5;
+        let on_side = (direction.x() == 0. && (origin.x() == bbox.min.x() || origin.x() == bbox.max.x()))
+            || (direction.y() == 0. && (origin.y() == bbox.min.y() || origin.y() == bbox.max.y()));
+        return if on_side { None } else { Some(Bisector::Line(origin, direction)) };
+    }
+    // the Apollonius circle of points whose distances to the sites are in
+    // the ratio of the weights
+    let spread = right_weight * right_weight - left_weight * left_weight;
+    let center = left_pt - offset * (left_weight * left_weight / spread);
+    let radius = left_weight * right_weight * offset.dot(offset).sqrt() / spread;
+    if !center.x().is_finite() || !center.y().is_finite() || !radius.is_finite() { return None; }
+    Some(Bisector::Circle(center, radius))
+}
+
+// The stretches of the bisector of two sites that border both cells within
+// the box, in order along it. The stretches lie within a disk, as (center,
+// radius), and the other sites given are all those nearer than the two
+// anywhere in it.
+fn bisector_pieces(bisector: Bisector, left: usize, (within, limit): (Point, f64), others: &[usize], sites: &[(usize, Point, f64)], bbox: &BoundingBox) -> Border {
+    let (_, left_pt, left_weight) = sites[left];
+    let mut excluded: Vec<Excluded> = vec![];
+    match bisector {
+        Bisector::Circle(center, radius) => {
+            // each constraint, that a + v . (cos, sin) be at least zero,
+            // excludes an arc of the circle
+            let mut constraints = vec![
+                (center.y() - bbox.min.y(), Point::new(0., radius), Bound::Side(0)),
+                (bbox.max.x() - center.x(), Point::new(-radius, 0.), Bound::Side(1)),
+                (bbox.max.y() - center.y(), Point::new(0., -radius), Bound::Side(2)),
+                (center.x() - bbox.min.x(), Point::new(radius, 0.), Bound::Side(3)),
+            ];
+            // nor may it leave the disk: r^2 >= |p - c|^2
+            let to_within = center - within;
+            constraints.push((limit * limit - to_within.dot(to_within) - radius * radius, to_within * (-2. * radius), Bound::Reach));
+            // no other site may be nearer: w_left^2 |p - s|^2 >= w^2 |p - s_left|^2
+            let to_left = center - left_pt;
+            for &index in others {
+                let (_, pt, weight) = sites[index];
+                let to_other = center - pt;
+                let (own, other) = (left_weight * left_weight, weight * weight);
+                let constant = own * (to_other.dot(to_other) + radius * radius) - other * (to_left.dot(to_left) + radius * radius);
+                constraints.push((constant, (to_other * own - to_left * other) * (2. * radius), Bound::Site(index)));
+            }
+            let mut nearest = (f64::INFINITY, 0.);
+            for (constant, varying, bound) in constraints {
+                let reach = varying.dot(varying).sqrt();
+                let middle = (-varying.y()).atan2(-varying.x());
+                if constant >= reach {
+                    if constant / reach < nearest.0 { nearest = (constant / reach, middle); }
+                    continue;
+                }
+                if constant < -reach { return Border::Pieces(vec![]); }
+                let half_width = (constant / reach).acos();
+                if half_width > 0. { excluded.push((middle - half_width, middle + half_width, bound)); }
+            }
+            if excluded.is_empty() { return Border::Ring(nearest.1); }
+
+            // start from the beginning of an excluded arc, and unwrap the others after it
+            let origin = excluded[0].0;
+            let mut unwrapped = vec![];
+            for (start, end, bound) in excluded {
+                let turns = ((start - origin) / (2. * PI)).floor();
+                let (start, end) = (start - turns * 2. * PI, end - turns * 2. * PI);
+                unwrapped.push((start, end, bound));
+                if end > origin + 2. * PI { unwrapped.push((origin, end - 2. * PI, bound)); }
+            }
+            let origin_bound = unwrapped[0].2;
+            Border::Pieces(free_pieces((origin, origin_bound), (origin + 2. * PI, origin_bound), unwrapped))
+        }
+        Bisector::Line(origin, direction) => {
+            // the stretch of the line within the box
+            let mut start = (f64::NEG_INFINITY, Bound::Side(0));
+            let mut end = (f64::INFINITY, Bound::Side(0));
+            let slabs = [
+                (origin.x(), direction.x(), bbox.min.x(), 3, bbox.max.x(), 1),
+                (origin.y(), direction.y(), bbox.min.y(), 0, bbox.max.y(), 2),
+            ];
+            for &(position, speed, low, low_side, high, high_side) in &slabs {
+                if speed == 0. {
+                    if position < low || position > high { return Border::Pieces(vec![]); }
+                    continue;
+                }
+                let (enter, leave) = if speed > 0. {
+                    (((low - position) / speed, low_side), ((high - position) / speed, high_side))
+                } else {
+                    (((high - position) / speed, high_side), ((low - position) / speed, low_side))
+                };
+                if enter.0 > start.0 { start = (enter.0, Bound::Side(enter.1)); }
+                if leave.0 < end.0 { end = (leave.0, Bound::Side(leave.1)); }
+            }
+            if start.0 >= end.0 { return Border::Pieces(vec![]); }
+
+            // nor may it leave the disk, and no other site may be nearer,
+            // where a quadratic in the parameter is at least zero
+            let to_within = origin - within;
+            let leaving = negative_intervals(-1., -2. * to_within.dot(direction), limit * limit - to_within.dot(to_within));
+            excluded.extend(leaving.into_iter().map(|(from, to)| (from, to, Bound::Reach)));
+            let to_left = origin - left_pt;
+            for &index in others {
+                let (_, pt, weight) = sites[index];
+                let to_other = origin - pt;
+                let (own, other) = (left_weight * left_weight, weight * weight);
+                let square = own - other;
+                let linear = 2. * (own * to_other.dot(direction) - other * to_left.dot(direction));
+                let constant = own * to_other.dot(to_other) - other * to_left.dot(to_left);
+                excluded.extend(negative_intervals(square, linear, constant).into_iter().map(|(from, to)| (from, to, Bound::Site(index))));
+            }
+            Border::Pieces(free_pieces(start, end, excluded))
+        }
+    }
+}
+
+// The intervals where the quadratic is negative
+fn negative_intervals(square: f64, linear: f64, constant: f64) -> Vec<(f64, f64)> {
+    let everywhere = vec![(f64::NEG_INFINITY, f64::INFINITY)];
+    if square == 0. {
+        if linear == 0. { return if constant < 0. { everywhere } else { vec![] }; }
+        let root = -constant / linear;
+        return vec![if linear > 0. { (f64::NEG_INFINITY, root) } else { (root, f64::INFINITY) }];
+    }
+    let discrim = linear * linear - 4. * square * constant;
+    if discrim <= 0. { return if square < 0. { everywhere } else { vec![] }; }
+    let q = -(linear + discrim.sqrt().copysign(linear)) / 2.;
+    let (first, second) = (q / square, constant / q);
+    let (low, high) = (first.min(second), first.max(second));
+    if square > 0. {
+        vec![(low, high)]
+    } else {
+        vec![(f64::NEG_INFINITY, low), (high, f64::INFINITY)]
+    }
+}
+
+// The stretches from start to end not excluded, in order. start and end
+// are (parameter, what bounds the bisector there).
+fn free_pieces(start: (f64, Bound), end: (f64, Bound), mut excluded: Vec<Excluded>) -> Vec<Piece> {
+    excluded.sort_by_key(|&(from, _, _)| OrderedFloat(from));
+    let mut pieces = vec![];
+    let (mut reached, mut reached_bound) = start;
+    for (from, to, bound) in excluded {
+        let (stop, stop_bound) = if from < end.0 { (from, bound) } else { end };
+        if stop > reached {
+            pieces.push(Piece { start: reached, end: stop, start_bound: reached_bound, end_bound: stop_bound });
+        }
+        if from >= end.0 { return pieces; }
+        if to > reached { reached = to; reached_bound = bound; }
+    }
+    if end.0 > reached {
+        pieces.push(Piece { start: reached, end: end.0, start_bound: reached_bound, end_bound: end.1 });
+    }
+    pieces
+}
+
+// The point at the end of a stretch of the bisector of two sites, refined
+// by Newton's method from a point near it. The angles along a large circle
+// place it only roughly, and the edges that meet there must meet at one
+// vertex. The point is equally near the two sites, and either on a side of
+// the box or equally near a third site. Keeps the start if the method fails.
+fn refine_vertex(start: Point, left: (usize, Point, f64), right: (usize, Point, f64), bound: Bound, sites: &[(usize, Point, f64)], bbox: &BoundingBox) -> Point {
+    // the difference of the distances, scaled by the weights, and its gradient
+    let difference = |pt: Point, (_, first, first_weight): (usize, Point, f64), (_, second, second_weight): (usize, Point, f64)| {
+        let (to_first, to_second) = (pt - first, pt - second);
+        let (first_distance, second_distance) = (to_first.dot(to_first).sqrt(), to_second.dot(to_second).sqrt());
+        let value = second_weight * first_distance - first_weight * second_distance;
+        let gradient = to_first * (second_weight / first_distance) - to_second * (first_weight / second_distance);
+        (value, gradient)
+    };
+    let mut pt = start;
+    for _ in 0..VERTEX_STEPS {
+        let (value, gradient) = difference(pt, left, right);
+        let (other_value, other_gradient) = match bound {
+            Bound::Side(side) => (side_offset(pt, side, bbox), side_normal(side)),
+            Bound::Site(index) => difference(pt, left, sites[index]),
+            Bound::Reach => return start,
+        };
+        let det = gradient.cross(other_gradient);
+        let step = Point::new(value * other_gradient.y() - other_value * gradient.y(), other_value * gradient.x() - value * other_gradient.x()) * (1. / det);
+        if !step.x().is_finite() || !step.y().is_finite() { return start; }
+        pt = pt - step;
+    }
+    pt
+}
+
+// Parameters from start to end, including both, such that the chords
+// between the points at them are within `tolerance` of the bisector. An arc
+// has at least two chords, so that arcs between the same ends stay apart,
+// and a quarter of a turn at most.
+fn arc_params(bisector: Bisector, start: f64, end: f64, tolerance: f64) -> Vec<f64> {
+    let count = match bisector {
+        Bisector::Circle(_, radius) => {
+            let step = if tolerance < radius { 2. * (1. - tolerance / radius).acos() } else { PI };
+            ((end - start) / step.min(PI / 2.)).ceil().max(2.) as usize
+        }
+        Bisector::Line(..) => 1,
+    };
+    (0..count + 1).map(|index| start + (end - start) * (index as f64 / count as f64)).collect()
+}
+
+// The gradient of side_offset, pointing into the box
+fn side_normal(side: usize) -> Point {
+    match side {
+        0 => Point::new(0., 1.),
+        1 => Point::new(-1., 0.),
+        2 => Point::new(0., -1.),
+        _ => Point::new(1., 0.),
+    }
+}
+
+// The square around a point reaching a distance from it along each axis
+fn around(center: Point, distance: f64) -> BoundingBox {
+    BoundingBox::new(center - Point::new(distance, distance), center + Point::new(distance, distance))
+}
+
+fn distance(from: Point, to: Point) -> f64 {
+    (to - from).dot(to - from).sqrt()
+}
+
+// A bound on the distance from a site to any location of its cell in the
+// box. A location at a distance r from the site, in some eighth of a turn
+// around it, would be nearer a site at least k times as heavy at a
+// distance d from it, at an angle to each direction in the eighth with a
+// cosine of at least c, if r > d / (c + sqrt(c^2 + k^2 - 1)). That bound is
+// never below d / (1 + k), and lighter sites bound no cell.
+fn cell_reach(sites: &[(usize, Point, f64)], grid: &BoxGrid, place: usize, bbox: &BoundingBox) -> f64 {
+    let (_, center, weight) = sites[place];
+    let sectors: Vec<(Point, Point)> = (0..SECTORS).map(|sector| {
+        let (from, to) = (sector as f64 * 2. * PI / SECTORS as f64, (sector + 1) as f64 * 2. * PI / SECTORS as f64);
+        (Point::new(from.cos(), from.sin()), Point::new(to.cos(), to.sin()))
+    }).collect();
+    // how far the box reaches in each sector
+    let area = bbox.corners();
+    let bounds: Vec<f64> = sectors.iter().map(|&(from, to)| {
+        let part = clip_polygon(&clip_polygon(&area, center, Point::new(-from.y(), from.x())), center, Point::new(to.y(), -to.x()));
+        part.iter().map(|&pt| distance(center, pt)).fold(0., f64::max)
+    }).collect();
+    let heaviest = sites.iter().map(|&(_, _, weight)| weight).fold(0., f64::max) / weight;
+    // look further until the sites that give the least bound in each sector,
+    // if less than the box reaches, have been seen
+    let mut seen = grid.side();
+    loop {
+        let mut nearest = bounds.clone();
+        for other in grid.near(&around(center, seen)).into_iter().filter(|&other| other != place) {
+            let (_, pt, other_weight) = sites[other];
+            if other_weight < weight { continue; }
+            let ratio = other_weight / weight;
+            let apart = distance(center, pt);
+            let toward = (pt - center) * (1. / apart);
+            for (&(from, to), nearest) in sectors.iter().zip(nearest.iter_mut()) {
+                // the cosine is least at an edge of the eighth, unless it
+                // holds the opposite direction
+                let opposite = from.cross(toward) <= 0. && toward.cross(to) <= 0.;
+                let cosine = if opposite { -1. } else { from.dot(toward).min(to.dot(toward)) };
+                // sites of equal weight bound only the eighths they face
+                let scale = cosine + (cosine * cosine + ratio * ratio - 1.).sqrt();
+                if scale > 0. { *nearest = nearest.min(apart / scale); }
+            }
+        }
+        if grid.covers(&around(center, seen)) || nearest.iter().all(|&reach| reach <= seen / (1. + heaviest)) {
+            return nearest.iter().cloned().fold(0., f64::max);
+        }
+        seen *= 2.;
+    }
+}
+
+// the input index of the site nearest a point, the first of them on a tie
+fn nearest_site(sites: &[(usize, Point, f64)], pt: Point) -> Option<usize> {
+    sites.iter()
+        .min_by_key(|&&(_, site, weight)| OrderedFloat((pt - site).dot(pt - site).sqrt() / weight))
+        .map(|&(index, _, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcel::{make_site_polygons_with_holes, make_polygons};
+    use test_utils::{face_contains, assert_fills_box, assert_grid_owned, clearly_nearest};
+    use validate::Validated;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn distance(pt: Point, site: (Point, f64)) -> f64 {
+        (pt - site.0).dot(pt - site.0).sqrt() / site.1
+    }
+
+    // the faces tile the box, each location on a grid lies in a face of the
+    // site nearest it, unless it is within the tolerance of a boundary, and no
+    // location is nearest a site listed as empty
+    fn assert_multiplicative_cells(sites: &[(Point, f64)], size: f64, tolerance: f64) {
+        let (dcel, empty) = multiplicative_diagram(sites.to_vec(), size, tolerance, &VoronoiOptions::default()).unwrap().validated();
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_fills_box(&faces, size);
+
+        let min_weight = sites.iter().map(|&(_, weight)| weight).fold(f64::INFINITY, f64::min);
+        assert_grid_owned(&faces, size, |pt| {
+            let distance = |index: usize| distance(pt, sites[index]);
+            let nearest = clearly_nearest(sites.len(), distance, 0.).unwrap();
+            assert!(!empty.contains(&nearest), "empty site {} owns {:?}", nearest, pt);
+            // the difference in distance changes by at most 2 / min_weight per unit moved
+            clearly_nearest(sites.len(), distance, 4. * tolerance / min_weight)
+        });
+    }
+
+    #[test]
+    fn equal_weights_give_voronoi_diagram() {
+        let points = vec![Point::new(1.0, 2.0), Point::new(7.0, 3.0), Point::new(4.0, 8.0), Point::new(5.5, 5.0)];
+        let sites: Vec<(Point, f64)> = points.iter().map(|&pt| (pt, 2.0)).collect();
+        let (dcel, empty) = multiplicative_diagram(sites, 10., 1e-3, &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        let mut polys: Vec<Vec<Point>> = make_polygons(&dcel);
+        let mut expected = make_polygons(&::voronoi::voronoi(points, 10.));
+        assert_eq!(polys.len(), expected.len());
+        // the faces come out in another order, each from another vertex
+        let key = |poly: &Vec<Point>| {
+            let least = poly.iter().cloned().min_by_key(|pt| (OrderedFloat(pt.x()), OrderedFloat(pt.y()))).unwrap();
+            (OrderedFloat(least.x()), OrderedFloat(least.y()))
+        };
+        polys.sort_by_key(|poly| key(poly));
+        expected.sort_by_key(|poly| key(poly));
+        for (poly, expected) in polys.iter().zip(&expected) {
+            assert_eq!(poly.len(), expected.len());
+            assert!(expected.iter().all(|&corner| poly.iter().any(|&pt| (pt - corner).dot(pt - corner).sqrt() < 1e-6)));
+        }
+    }
+
+    #[test]
+    fn lighter_site_is_hole_in_heavier() {
+        let sites = vec![(Point::new(50.0, 50.0), 1.0), (Point::new(60.0, 50.0), 2.0)];
+        let tolerance = 1e-2;
+        let (dcel, empty) = multiplicative_diagram(sites.clone(), 100., tolerance, &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_eq!(faces.len(), 2);
+        assert_eq!((faces[0].0, faces[0].2.len()), (0, 0));
+        assert_eq!((faces[1].0, faces[1].2.len()), (1, 1));
+        // the cell of the lighter site is a disk, the hole in the other, whose
+        // vertices lie on its circle, and whose edges are within the tolerance of it
+        let (center, radius) = (Point::new(50.0 - 10.0 / 3., 50.0), 20.0 / 3.);
+        assert!(faces[0].1.len() > 8);
+        for &pt in &faces[0].1 {
+            assert!(((pt - center).dot(pt - center).sqrt() - radius).abs() < 1e-9);
+        }
+        let chord = faces[0].1[1] - faces[0].1[0];
+        assert!(radius - (radius * radius - chord.dot(chord) / 4.).sqrt() <= tolerance);
+        assert_multiplicative_cells(&sites, 100., tolerance);
+    }
+
+    #[test]
+    fn heavier_site_cell_is_disconnected() {
+        // the heavy site reaches around the two light ones, to the bottom of the box
+        let sites = vec![(Point::new(33.5, 60.0), 1.0), (Point::new(66.5, 60.0), 1.0), (Point::new(50.0, 90.0), 2.0)];
+        let (dcel, empty) = multiplicative_diagram(sites.clone(), BoundingBox::new(Point::new(10., 0.), Point::new(90., 100.)), 1e-2, &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_eq!(faces.iter().map(|face| face.0).collect::<Vec<_>>(), vec![0, 1, 2, 2]);
+        assert!(faces.iter().all(|face| face.2.is_empty()));
+        assert!(faces.iter().any(|face| face.0 == 2 && face_contains(face, Point::new(50.0, 10.0))));
+        assert!(faces.iter().any(|face| face.0 == 2 && face_contains(face, Point::new(50.0, 90.0))));
+    }
+
+    #[test]
+    fn collinear_sites_touching() {
+        // each pair's circle passes through the same point on the side of the box
+        let sites = vec![(Point::new(60.0, 70.0), 3.0), (Point::new(20.0, 70.0), 1.0), (Point::new(40.0, 70.0), 2.0)];
+        assert_multiplicative_cells(&sites, 100., 0.1);
+    }
+
+    #[test]
+    fn coincident_sites_keep_heaviest() {
+        let sites = vec![(Point::new(3.0, 3.0), 1.0), (Point::new(7.0, 7.0), 1.0), (Point::new(3.0, 3.0), 2.0)];
+        let (dcel, empty) = multiplicative_diagram(sites, 10., 1e-3, &VoronoiOptions::default()).unwrap().validated();
+        assert_eq!(empty, vec![0]);
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_eq!(faces.iter().map(|face| face.0).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn invalid_input_rejected() {
+        let options = VoronoiOptions::default();
+        for &weight in &[0., -1.] {
+            let sites = vec![(Point::new(3.0, 3.0), 1.0), (Point::new(7.0, 7.0), weight)];
+            assert_eq!(multiplicative_diagram(sites, 10., 1e-3, &options).err(), Some(VoronoiError::NonPositiveWeight { index: 1 }));
+        }
+        let sites = vec![(Point::new(3.0, 3.0), f64::NAN)];
+        assert_eq!(multiplicative_diagram(sites, 10., 1e-3, &options).err(), Some(VoronoiError::NonFiniteWeight { index: 0 }));
+        for &tolerance in &[0., -1., f64::NAN, f64::INFINITY] {
+            let sites = vec![(Point::new(3.0, 3.0), 1.0)];
+            assert_eq!(multiplicative_diagram(sites, 10., tolerance, &options).err(), Some(VoronoiError::InvalidTolerance));
+        }
+    }
+
+    #[test]
+    fn random_weights() {
+        for seed in 0..20 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let sites: Vec<(Point, f64)> = (0..20)
+                .map(|_| (Point::new(rng.next_f64() * 100., rng.next_f64() * 100.), 1. + rng.next_f64() * 2.))
+                .collect();
+            assert_multiplicative_cells(&sites, 100., 0.01);
+        }
+    }
+
+    #[test]
+    fn many_sites() {
+        // enough sites that most pairs are too far apart to be compared, some
+        // outside the box and many of equal weight
+        let mut rng = StdRng::from_seed(&[3][..]);
+        let sites: Vec<(Point, f64)> = (0..150)
+            .map(|_| (Point::new(rng.next_f64() * 110. - 5., rng.next_f64() * 110. - 5.), 1. + (rng.next_f64() * 4.).floor() / 8.))
+            .collect();
+        assert_multiplicative_cells(&sites, 100., 0.01);
+    }
+}
@@ -87,11 +87,11 @@ impl PartialOrd for Point {
 
 impl Ord for Point {
     fn cmp(&self, other: &Point) -> Ordering {
-        if self.y > other.y { return Ordering::Greater; }
+        if self.y > other.y { Ordering::Greater }
         else if self.y == other.y {
-            if self.x < other.x { return Ordering::Greater; }
-            else if self.x == other.x { return Ordering::Equal; }
-            else { return Ordering::Less; }
-        } else { return Ordering::Less; }
+            if self.x < other.x { Ordering::Greater }
+            else if self.x == other.x { Ordering::Equal }
+            else { Ordering::Less }
+        } else { Ordering::Less }
     }
 }
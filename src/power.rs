@@ -0,0 +1,229 @@
+use point::Point;
+use dcel::DCEL;
+use voronoi::{VoronoiOptions, prepare_weighted_sites, power_in_region};
+use error::VoronoiError;
+use bounding_box::BoundingBox;
+
+/// Computes the power diagram of a set of weighted points, clipped to a bounding box.
+///
+/// Each site is a point with a weight. The power distance from a location to a
+/// site is the squared distance to the point less the weight, and the cell of a
+/// site is where its power distance is least: with equal weights this is the
+/// Voronoi diagram. A light site may have a cell that does not contain its point,
+/// or no cell at all. Returns the Doubly Connected Edge List, together with the
+/// indices of the input sites whose cells are empty, in increasing order; these
+/// have no face. Sites whose cells miss the bounding box have no face either,
+/// but are not listed.
+///
+/// Of coincident points, the one with the greatest weight (the first of them,
+/// on a tie) takes the cell, and the others have empty cells, unless the options
+/// reject duplicates. Fails as `voronoi_with_options` does, and with
+/// `VoronoiError::NonFiniteWeight` if a weight is NaN or infinite.
+pub fn power_diagram<B: Into<BoundingBox>>(sites: Vec<(Point, f64)>, bbox: B, options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let bbox = bbox.into();
+    if !bbox.is_valid() {
+        return Err(VoronoiError::InvalidBoundingBox);
+    }
+    let (sites, weights, hidden) = prepare_weighted_sites(sites, options)?;
+
+    // the sweep adds to the squared distances rather than subtracting, so
+    // the weights are measured down from the greatest
+    let greatest = sites.iter().map(|&(index, _)| weights[index]).fold(f64::NEG_INFINITY, f64::max);
+    let offsets: Vec<f64> = weights.iter().map(|&weight| greatest - weight).collect();
+    if offsets.iter().any(|offset| !offset.is_finite()) {
+        return Err(VoronoiError::DegenerateInput);
+    }
+
+    let (dcel, mut empty) = power_in_region(&sites, &offsets, &bbox.corners(), options)?;
+    empty.extend(hidden);
+    empty.sort();
+    Ok((dcel, empty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voronoi::DuplicateSites;
+    use dcel::{make_site_polygons, make_site_polygons_with_holes, make_polygons, signed_area};
+    use test_utils::{assert_fills_box, assert_grid_owned, clearly_nearest};
+    use validate::Validated;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn power_distance(pt: Point, site: (Point, f64)) -> f64 {
+        (pt - site.0).dot(pt - site.0) - site.1
+    }
+
+    // the faces tile the box, each location on a grid lies in the face of the
+    // site of least power distance there, and no site listed as empty is least
+    // anywhere on the grid
+    fn assert_power_cells(sites: &[(Point, f64)], size: f64) {
+        let (dcel, empty) = power_diagram(sites.to_vec(), size, &VoronoiOptions::default()).unwrap().validated();
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_fills_box(&faces, size);
+        for face in &faces {
+            assert!(signed_area(&face.1) > 0.);
+            assert!(!empty.contains(&face.0));
+        }
+
+        let margin = 1e-9 * size * size;
+        assert_grid_owned(&faces, size, |pt| {
+            let distance = |index: usize| power_distance(pt, sites[index]);
+            let least = (0..sites.len()).map(distance).fold(f64::INFINITY, f64::min);
+            for &index in &empty {
+                assert!(distance(index) > least - margin, "empty site {} owns {:?}", index, pt);
+            }
+            clearly_nearest(sites.len(), distance, margin)
+        });
+    }
+
+    #[test]
+    fn equal_weights_give_voronoi_diagram() {
+        let points = vec![Point::new(1.0, 2.0), Point::new(7.0, 3.0), Point::new(4.0, 8.0), Point::new(5.5, 5.0)];
+        let sites: Vec<(Point, f64)> = points.iter().map(|&pt| (pt, 3.0)).collect();
+        let (dcel, empty) = power_diagram(sites, 10., &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        assert_eq!(make_polygons(&dcel), make_polygons(&::voronoi::voronoi(points, 10.)));
+    }
+
+    #[test]
+    fn weight_moves_bisector() {
+        // x^2 - 20 = (x - 4)^2, so the boundary is at x = 4.5
+        let sites = vec![(Point::new(0.0, 5.0), 20.0), (Point::new(4.0, 5.0), 0.0)];
+        let (dcel, empty) = power_diagram(sites.clone(), 10., &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        let faces = make_site_polygons(&dcel);
+        assert_eq!(faces.len(), 2);
+        assert!((signed_area(&faces[0].1) - 45.0).abs() < 1e-9);
+        assert_power_cells(&sites, 10.);
+    }
+
+    #[test]
+    fn cell_away_from_its_site() {
+        // the light site at (6, 6) has a cell that begins at x = 8
+        let sites = vec![(Point::new(2.0, 6.0), 0.0), (Point::new(6.0, 6.0), -32.0), (Point::new(5.0, 1.0), 0.0)];
+        let (dcel, empty) = power_diagram(sites.clone(), 10., &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        let faces = make_site_polygons(&dcel);
+        let light = faces.iter().find(|&&(site, _)| site == 1).unwrap();
+        assert!(light.1.iter().all(|pt| pt.x() >= 8.0 - 1e-9));
+        assert_power_cells(&sites, 10.);
+    }
+
+    #[test]
+    fn light_site_has_empty_cell() {
+        let mut sites = vec![];
+        for col in 0..3 {
+            for row in 0..3 {
+                sites.push((Point::new(2.0 + 3.0 * col as f64, 2.0 + 3.0 * row as f64), 4.0));
+            }
+        }
+        sites.push((Point::new(5.5, 5.0), -10.0));
+        let (dcel, empty) = power_diagram(sites.clone(), 10., &VoronoiOptions::default()).unwrap().validated();
+        assert_eq!(empty, vec![9]);
+        assert_eq!(make_site_polygons(&dcel).len(), 9);
+        assert_power_cells(&sites, 10.);
+    }
+
+    #[test]
+    fn empty_cells_in_top_row() {
+        // the middle site of the row is hidden by the heavier sites beside it
+        let sites = vec![(Point::new(2.0, 9.0), 9.0), (Point::new(5.0, 9.0), 0.0), (Point::new(8.0, 9.0), 9.0), (Point::new(5.0, 2.0), 0.0)];
+        let (_, empty) = power_diagram(sites.clone(), 10., &VoronoiOptions::default()).unwrap().validated();
+        assert_eq!(empty, vec![1]);
+        assert_power_cells(&sites, 10.);
+
+        // sites of the row whose strips lie out of order with their points
+        let sites = vec![(Point::new(0.0, 9.0), 0.0), (Point::new(1.0, 9.0), -4.0), (Point::new(2.0, 9.0), -9.0), (Point::new(5.0, 3.0), 0.0)];
+        assert_power_cells(&sites, 10.);
+    }
+
+    #[test]
+    fn coincident_sites_keep_heaviest() {
+        let sites = vec![(Point::new(3.0, 3.0), 1.0), (Point::new(7.0, 7.0), 0.0), (Point::new(3.0, 3.0), 2.0)];
+        let (dcel, empty) = power_diagram(sites.clone(), 10., &VoronoiOptions::default()).unwrap().validated();
+        assert_eq!(empty, vec![0]);
+        let faces = make_site_polygons(&dcel);
+        assert_eq!(faces.iter().map(|&(site, _)| site).collect::<Vec<_>>(), vec![1, 2]);
+
+        let reject = VoronoiOptions { duplicates: DuplicateSites::Reject, ..VoronoiOptions::default() };
+        assert_eq!(power_diagram(sites, 10., &reject).err(), Some(VoronoiError::DuplicateSite { first: 0, duplicate: 2 }));
+    }
+
+    #[test]
+    fn non_finite_weight_rejected() {
+        let sites = vec![(Point::new(3.0, 3.0), 1.0), (Point::new(7.0, 7.0), f64::NAN)];
+        assert_eq!(power_diagram(sites, 10., &VoronoiOptions::default()).err(), Some(VoronoiError::NonFiniteWeight { index: 1 }));
+    }
+
+    #[test]
+    fn single_weighted_site() {
+        let (dcel, empty) = power_diagram(vec![(Point::new(3.0, 3.0), -5.0)], 10., &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        assert_eq!(make_site_polygons(&dcel).len(), 1);
+    }
+
+    #[test]
+    fn appears_at_nearer_breakpoint() {
+        // the arc of the heaviest site lies between two arcs of the top site,
+        // and the last site reaches the breakpoint on its left
+        let sites = vec![(Point::new(92.33, 30.28), 162.48), (Point::new(92.36, 22.31), 352.09), (Point::new(87.79, 21.98), 271.37)];
+        let (dcel, empty) = power_diagram(sites.clone(), 100., &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        assert_eq!(make_site_polygons(&dcel).len(), 3);
+        assert_power_cells(&sites, 100.);
+    }
+
+    #[test]
+    fn site_on_shared_edge_has_empty_cell() {
+        // the light site lies on the edge between the other two, where its
+        // power is that of both, so its cell has no area
+        let sites = vec![(Point::new(80.0, 30.0), 200.0), (Point::new(60.0, 10.0), 200.0), (Point::new(70.0, 20.0), 0.0)];
+        let (_, empty) = power_diagram(sites.clone(), 100., &VoronoiOptions::default()).unwrap().validated();
+        assert_eq!(empty, vec![2]);
+        assert_power_cells(&sites, 100.);
+    }
+
+    #[test]
+    fn sites_appear_at_vertex() {
+        // the cells of sites 0, 2, 3 and 4 meet at (50, 55), which is where
+        // site 0 reaches the beachline
+        let sites = vec![(Point::new(50.0, 20.0), 100.0), (Point::new(10.0, 40.0), 200.0), (Point::new(80.0, 80.0), 400.0),
+                         (Point::new(20.0, 80.0), 400.0), (Point::new(40.0, 90.0), 200.0), (Point::new(70.0, 0.0), 800.0)];
+        let (dcel, empty) = power_diagram(sites.clone(), 100., &VoronoiOptions::default()).unwrap().validated();
+        assert!(empty.is_empty());
+        assert_eq!(make_site_polygons(&dcel).len(), 6);
+        assert_power_cells(&sites, 100.);
+
+        // sites 1 and 4 reach the beachline together, where the cells of
+        // sites 0 and 3 meet them at (40, 45)
+        let sites = vec![(Point::new(40.0, 60.0), 100.0), (Point::new(30.0, 30.0), 200.0), (Point::new(50.0, 80.0), 100.0), (Point::new(10.0, 50.0), 800.0),
+                         (Point::new(40.0, 30.0), 100.0), (Point::new(30.0, 60.0), 0.0), (Point::new(10.0, 30.0), 100.0), (Point::new(80.0, 50.0), 800.0)];
+        assert_power_cells(&sites, 100.);
+    }
+
+    #[test]
+    fn random_weights() {
+        for seed in 0..10 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let sites: Vec<(Point, f64)> = (0..30)
+                .map(|_| (Point::new(rng.next_f64() * 100., rng.next_f64() * 100.), rng.next_f64() * 400.))
+                .collect();
+            assert_power_cells(&sites, 100.);
+        }
+    }
+
+    #[test]
+    fn grid_sites_and_weights() {
+        // many cells meet at single points, and many are flat or empty
+        for seed in 0..40 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let sites: Vec<(Point, f64)> = (0..3 + seed % 8)
+                .map(|_| {
+                    let pt = Point::new((rng.next_f64() * 10.).floor() * 10., (rng.next_f64() * 10.).floor() * 10.);
+                    (pt, [0., 100., 200., 400., 800.][(rng.next_f64() * 5.) as usize])
+                })
+                .collect();
+            assert_power_cells(&sites, 100.);
+        }
+    }
+}
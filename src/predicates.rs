@@ -0,0 +1,444 @@
+// Adaptive precision geometric predicates, after
+// J. R. Shewchuk, "Adaptive Precision Floating-Point Arithmetic and
+// Fast Robust Geometric Predicates" (1997).
+//
+// Each predicate first evaluates its determinant in plain floating point
+// and checks the result against a forward error bound. Only if the sign
+// cannot be trusted is the determinant recomputed exactly, using
+// floating-point expansions (sums of non-overlapping doubles).
+
+use std::cmp::Ordering;
+
+use point::Point;
+
+const EPSILON: f64 = 1.1102230246251565e-16; // 2^-53
+const CCW_ERRBOUND_A: f64 = (3.0 + 16.0 * EPSILON) * EPSILON;
+const ICC_ERRBOUND_A: f64 = (10.0 + 96.0 * EPSILON) * EPSILON;
+// Generous bounds on the relative error of the floating-point terms of
+// `circumcircle` and `compare_breakpoint_x`
+const CIRCLE_ERRBOUND: f64 = 8.0 * EPSILON;
+const BREAKPOINT_ERRBOUND: f64 = 16.0 * EPSILON;
+/// The relative error allowed in the circles found by `circumcircle`.
+pub const CIRCLE_ACCURACY: f64 = 1e-10;
+
+/// Positive if a, b, c are in counterclockwise order, negative if they are
+/// in clockwise order, zero if they are collinear.
+///
+/// The sign is exact; the magnitude approximates twice the signed area
+/// of the triangle.
+pub fn orient2d(a: Point, b: Point, c: Point) -> f64 {
+    let detleft = (a.x() - c.x()) * (b.y() - c.y());
+    let detright = (a.y() - c.y()) * (b.x() - c.x());
+    let det = detleft - detright;
+
+    let detsum = if detleft > 0.0 {
+        if detright <= 0.0 { return det; }
+        detleft + detright
+    } else if detleft < 0.0 {
+        if detright >= 0.0 { return det; }
+        -detleft - detright
+    } else {
+        return det;
+    };
+
+    let errbound = CCW_ERRBOUND_A * detsum;
+    if det >= errbound || -det >= errbound {
+        return det;
+    }
+    orient2d_exact(a, b, c)
+}
+
+/// Positive if d lies inside the circle through a, b, c, negative if it lies
+/// outside, zero if the four points are cocircular. The points a, b, c
+/// must be in counterclockwise order, otherwise the sign is reversed.
+///
+/// The sign is exact.
+pub fn incircle(a: Point, b: Point, c: Point, d: Point) -> f64 {
+    let adx = a.x() - d.x();
+    let bdx = b.x() - d.x();
+    let cdx = c.x() - d.x();
+    let ady = a.y() - d.y();
+    let bdy = b.y() - d.y();
+    let cdy = c.y() - d.y();
+
+    let bdxcdy = bdx * cdy;
+    let cdxbdy = cdx * bdy;
+    let alift = adx * adx + ady * ady;
+
+    let cdxady = cdx * ady;
+    let adxcdy = adx * cdy;
+    let blift = bdx * bdx + bdy * bdy;
+
+    let adxbdy = adx * bdy;
+    let bdxady = bdx * ady;
+    let clift = cdx * cdx + cdy * cdy;
+
+    let det = alift * (bdxcdy - cdxbdy)
+        + blift * (cdxady - adxcdy)
+        + clift * (adxbdy - bdxady);
+
+    let permanent = (bdxcdy.abs() + cdxbdy.abs()) * alift
+        + (cdxady.abs() + adxcdy.abs()) * blift
+        + (adxbdy.abs() + bdxady.abs()) * clift;
+    let errbound = ICC_ERRBOUND_A * permanent;
+    if det > errbound || -det > errbound {
+        return det;
+    }
+    incircle_exact(a, b, c, d)
+}
+
+/// The center of the circle through a, b, c, and the height of its lowest
+/// point, each correct to within a relative error of `CIRCLE_ACCURACY`.
+/// None if the points are collinear.
+pub fn circumcircle(a: Point, b: Point, c: Point) -> Option<(Point, f64)> {
+    let (bx, by, cx, cy) = (a.x() - c.x(), a.y() - c.y(), b.x() - c.x(), b.y() - c.y());
+    let (b_sq, c_sq) = (bx * bx + by * by, cx * cx + cy * cy);
+    let (detleft, detright) = (bx * cy, by * cx);
+    let (mut nx, mut ny, mut d) = (cy * b_sq - by * c_sq, bx * c_sq - cx * b_sq, 2.0 * (detleft - detright));
+    let d_error = CIRCLE_ERRBOUND * 2.0 * (detleft.abs() + detright.abs());
+    let n_error = CIRCLE_ERRBOUND * ((cy.abs() + cx.abs()) * b_sq + (by.abs() + bx.abs()) * c_sq);
+    if d_error >= CIRCLE_ACCURACY * d.abs() || n_error >= CIRCLE_ACCURACY * (nx.abs() + ny.abs()) {
+        // too thin a triangle for floating point
+        let circle = ExactCircle::new(a, b, c)?;
+        nx = estimate(&circle.nx);
+        ny = estimate(&circle.ny);
+        d = estimate(&circle.d);
+    } else if d < 0.0 {
+        nx = -nx;
+        ny = -ny;
+        d = -d;
+    }
+    let center = Point::new(c.x() + nx / d, c.y() + ny / d);
+    // a center far above the points would lose the lowest point to cancellation
+    let reach = (nx * nx + ny * ny).sqrt();
+    let drop = if ny > 0.0 { -nx * nx / (d * (ny + reach)) } else { (ny - reach) / d };
+    Some((center, c.y() + drop))
+}
+
+/// Compares the height of the lowest point of the circle through a, b, c
+/// with a height. The points must not be collinear.
+///
+/// The result is exact.
+pub fn compare_circle_bottom(a: Point, b: Point, c: Point, height: f64) -> Ordering {
+    let circle = match ExactCircle::new(a, b, c) {
+        Some(circle) => circle,
+        None => return Ordering::Equal,
+    };
+    // (base - height) d + ny - sqrt(nx^2 + ny^2), times d
+    let above = expansion_sum(&expansion_product(&diff_expansion(circle.base.y(), height), &circle.d), &circle.ny);
+    sign_minus_root(&above, &circle.squared_reach())
+}
+
+/// Compares the heights of the lowest points of the circles through two
+/// triples of points, neither collinear.
+///
+/// The result is exact.
+pub fn compare_circle_bottoms(first: (Point, Point, Point), second: (Point, Point, Point)) -> Ordering {
+    let (one, other) = match (ExactCircle::new(first.0, first.1, first.2), ExactCircle::new(second.0, second.1, second.2)) {
+        (Some(one), Some(other)) => (one, other),
+        _ => return Ordering::Equal,
+    };
+    // the difference times both denominators is x - sqrt(u) + sqrt(v)
+    let denominators = expansion_product(&one.d, &other.d);
+    let x = expansion_sum(
+        &expansion_product(&diff_expansion(one.base.y(), other.base.y()), &denominators),
+        &expansion_diff(&expansion_product(&one.ny, &other.d), &expansion_product(&other.ny, &one.d)));
+    let u = expansion_product(&expansion_product(&other.d, &other.d), &one.squared_reach());
+    let v = expansion_product(&expansion_product(&one.d, &one.d), &other.squared_reach());
+    let twice_x = scale_expansion(&x, 2.0);
+    let x_squared = expansion_product(&x, &x);
+    if sign(&x) != Ordering::Less {
+        // both x + sqrt(v) and sqrt(u) are not negative, so compare their squares
+        sign_plus_root(&expansion_diff(&expansion_sum(&x_squared, &v), &u), &twice_x, &v)
+    } else {
+        // compare sqrt(v) with sqrt(u) - x, which is positive
+        sign_plus_root(&expansion_diff(&expansion_diff(&v, &u), &x_squared), &twice_x, &u)
+    }
+}
+
+/// Compares the x coordinate of a point with that of the breakpoint between
+/// the arcs of the sites `left` and `right`, in that order along the
+/// beachline, when the sweep line is at the height of the point. Both sites
+/// must be at or above the point.
+///
+/// The result is exact.
+pub fn compare_breakpoint_x(left: Point, right: Point, pt: Point) -> Ordering {
+    // a site on the sweep line has a vertical ray for its arc
+    match (left.y() == pt.y(), right.y() == pt.y()) {
+        (true, true) => return sign(&expansion_sum(&diff_expansion(pt.x(), left.x()), &diff_expansion(pt.x(), right.x()))),
+        (true, false) => return pt.x().partial_cmp(&left.x()).unwrap_or(Ordering::Equal),
+        (false, true) => return pt.x().partial_cmp(&right.x()).unwrap_or(Ordering::Equal),
+        (false, false) => (),
+    }
+    // the arcs cross twice, on either side of the site of the narrower arc; this
+    // breakpoint is the crossing to the left of the right site if that is the
+    // narrower, and to the right of the left site otherwise
+    if left.y() > right.y() && pt.x() >= right.x() { return Ordering::Greater; }
+    if left.y() < right.y() && pt.x() <= left.x() { return Ordering::Less; }
+
+    // positive where the arc of the left site is the lower, so nearer the sweep line
+    let (left_dx, left_drop, right_dx, right_drop) = (pt.x() - left.x(), left.y() - pt.y(), pt.x() - right.x(), right.y() - pt.y());
+    let first = (right_dx * right_dx + right_drop * right_drop) * left_drop;
+    let second = (left_dx * left_dx + left_drop * left_drop) * right_drop;
+    let lower = first - second;
+    let errbound = BREAKPOINT_ERRBOUND * (first.abs() + second.abs());
+    if lower > errbound { return Ordering::Less; }
+    if -lower > errbound { return Ordering::Greater; }
+
+    let (left_drop, right_drop) = (diff_expansion(left.y(), pt.y()), diff_expansion(right.y(), pt.y()));
+    let (left_dx, right_dx) = (diff_expansion(pt.x(), left.x()), diff_expansion(pt.x(), right.x()));
+    let lift = |dx: &[f64], drop: &[f64]| expansion_sum(&expansion_product(dx, dx), &expansion_product(drop, drop));
+    sign(&expansion_diff(&expansion_product(&lift(&right_dx, &right_drop), &left_drop), &expansion_product(&lift(&left_dx, &left_drop), &right_drop))).reverse()
+}
+
+// The circle through three points as exact expansions: its center is at
+// base + (nx, ny) / d, with d positive
+struct ExactCircle {
+    base: Point,
+    nx: Vec<f64>,
+    ny: Vec<f64>,
+    d: Vec<f64>,
+}
+
+impl ExactCircle {
+    fn new(a: Point, b: Point, c: Point) -> Option<Self> {
+        let (bx, by) = (diff_expansion(a.x(), c.x()), diff_expansion(a.y(), c.y()));
+        let (cx, cy) = (diff_expansion(b.x(), c.x()), diff_expansion(b.y(), c.y()));
+        let b_sq = expansion_sum(&expansion_product(&bx, &bx), &expansion_product(&by, &by));
+        let c_sq = expansion_sum(&expansion_product(&cx, &cx), &expansion_product(&cy, &cy));
+        let mut d = scale_expansion(&expansion_diff(&expansion_product(&bx, &cy), &expansion_product(&by, &cx)), 2.0);
+        let mut nx = expansion_diff(&expansion_product(&cy, &b_sq), &expansion_product(&by, &c_sq));
+        let mut ny = expansion_diff(&expansion_product(&bx, &c_sq), &expansion_product(&cx, &b_sq));
+        match sign(&d) {
+            Ordering::Equal => return None,
+            Ordering::Less => {
+                for component in d.iter_mut().chain(nx.iter_mut()).chain(ny.iter_mut()) { *component = -*component; }
+            }
+            Ordering::Greater => (),
+        }
+        Some(ExactCircle { base: c, nx, ny, d })
+    }
+
+    // the squared radius times d^2
+    fn squared_reach(&self) -> Vec<f64> {
+        expansion_sum(&expansion_product(&self.nx, &self.nx), &expansion_product(&self.ny, &self.ny))
+    }
+}
+
+// The sign of p - sqrt(q), for q not negative
+fn sign_minus_root(p: &[f64], q: &[f64]) -> Ordering {
+    match sign(p) {
+        Ordering::Greater => sign(&expansion_diff(&expansion_product(p, p), q)),
+        Ordering::Equal => sign(q).reverse(),
+        Ordering::Less => Ordering::Less,
+    }
+}
+
+// The sign of r + s sqrt(t), for t not negative
+fn sign_plus_root(r: &[f64], s: &[f64], t: &[f64]) -> Ordering {
+    let r_sign = sign(r);
+    let root_sign = if sign(t) == Ordering::Equal { Ordering::Equal } else { sign(s) };
+    if root_sign == Ordering::Equal || r_sign == root_sign { return r_sign; }
+    if r_sign == Ordering::Equal { return root_sign; }
+    // the terms have opposite signs, and the larger decides
+    match sign(&expansion_diff(&expansion_product(r, r), &expansion_product(&expansion_product(s, s), t))) {
+        Ordering::Greater => r_sign,
+        Ordering::Less => root_sign,
+        Ordering::Equal => Ordering::Equal,
+    }
+}
+
+fn orient2d_exact(a: Point, b: Point, c: Point) -> f64 {
+    // (ax by + bx cy + cx ay) - (ay bx + by cx + cy ax), each product exact
+    let mut det = vec![];
+    for &(p, q) in &[(a, b), (b, c), (c, a)] {
+        let (hi, lo) = two_product(p.x(), q.y());
+        det = expansion_sum(&det, &[lo, hi]);
+        let (hi, lo) = two_product(-p.y(), q.x());
+        det = expansion_sum(&det, &[lo, hi]);
+    }
+    estimate(&det)
+}
+
+fn incircle_exact(a: Point, b: Point, c: Point, d: Point) -> f64 {
+    let adx = diff_expansion(a.x(), d.x());
+    let bdx = diff_expansion(b.x(), d.x());
+    let cdx = diff_expansion(c.x(), d.x());
+    let ady = diff_expansion(a.y(), d.y());
+    let bdy = diff_expansion(b.y(), d.y());
+    let cdy = diff_expansion(c.y(), d.y());
+
+    let lift = |dx: &[f64], dy: &[f64]| {
+        expansion_sum(&expansion_product(dx, dx), &expansion_product(dy, dy))
+    };
+    let cross = |px: &[f64], py: &[f64], qx: &[f64], qy: &[f64]| {
+        expansion_diff(&expansion_product(px, qy), &expansion_product(qx, py))
+    };
+
+    let aterm = expansion_product(&lift(&adx, &ady), &cross(&bdx, &bdy, &cdx, &cdy));
+    let bterm = expansion_product(&lift(&bdx, &bdy), &cross(&cdx, &cdy, &adx, &ady));
+    let cterm = expansion_product(&lift(&cdx, &cdy), &cross(&adx, &ady, &bdx, &bdy));
+
+    estimate(&expansion_sum(&expansion_sum(&aterm, &bterm), &cterm))
+}
+
+// a + b = hi + lo exactly
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let hi = a + b;
+    let b_virtual = hi - a;
+    let a_virtual = hi - b_virtual;
+    let lo = (a - a_virtual) + (b - b_virtual);
+    (hi, lo)
+}
+
+// a * b = hi + lo exactly
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let hi = a * b;
+    let lo = a.mul_add(b, -hi);
+    (hi, lo)
+}
+
+// a - b as an expansion
+fn diff_expansion(a: f64, b: f64) -> Vec<f64> {
+    let (hi, lo) = two_sum(a, -b);
+    compress(&[lo, hi])
+}
+
+// Adds a single double to an expansion, eliminating zero components.
+fn grow_expansion(e: &[f64], b: f64) -> Vec<f64> {
+    let mut result = Vec::with_capacity(e.len() + 1);
+    let mut q = b;
+    for &component in e {
+        let (sum, err) = two_sum(q, component);
+        if err != 0.0 { result.push(err); }
+        q = sum;
+    }
+    if q != 0.0 || result.is_empty() { result.push(q); }
+    result
+}
+
+fn expansion_sum(e: &[f64], f: &[f64]) -> Vec<f64> {
+    let mut result = e.to_vec();
+    for &component in f {
+        result = grow_expansion(&result, component);
+    }
+    result
+}
+
+fn expansion_diff(e: &[f64], f: &[f64]) -> Vec<f64> {
+    let negated: Vec<f64> = f.iter().map(|x| -x).collect();
+    expansion_sum(e, &negated)
+}
+
+// Multiplies an expansion by a single double, eliminating zero components.
+fn scale_expansion(e: &[f64], b: f64) -> Vec<f64> {
+    let mut result = vec![];
+    for &component in e {
+        let (hi, lo) = two_product(component, b);
+        result = grow_expansion(&result, lo);
+        result = grow_expansion(&result, hi);
+    }
+    result
+}
+
+fn expansion_product(e: &[f64], f: &[f64]) -> Vec<f64> {
+    let mut result = vec![];
+    for &component in f {
+        result = expansion_sum(&result, &scale_expansion(e, component));
+    }
+    result
+}
+
+fn compress(e: &[f64]) -> Vec<f64> {
+    grow_expansion(&e[..e.len() - 1], e[e.len() - 1])
+}
+
+// The components are non-overlapping and ordered by increasing magnitude,
+// so their floating-point sum has the sign of the exact value.
+fn estimate(e: &[f64]) -> f64 {
+    e.iter().fold(0.0, |sum, component| sum + component)
+}
+
+fn sign(e: &[f64]) -> Ordering {
+    estimate(e).partial_cmp(&0.0).unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_orientation() {
+        let a = Point::new(0.0, 0.0);
+        let b = Point::new(1.0, 0.0);
+        let c = Point::new(0.0, 1.0);
+        assert!(orient2d(a, b, c) > 0.0);
+        assert!(orient2d(a, c, b) < 0.0);
+        assert_eq!(orient2d(a, b, Point::new(2.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn near_collinear_orientation() {
+        let b = Point::new(12.0, 12.0);
+        let c = Point::new(24.0, 24.0);
+        assert!(orient2d(Point::new(0.5, 0.5000000000000001), b, c) > 0.0);
+        assert!(orient2d(Point::new(0.5000000000000001, 0.5), b, c) < 0.0);
+        assert_eq!(orient2d(Point::new(0.5, 0.5), b, c), 0.0);
+    }
+
+    #[test]
+    fn exactly_collinear_orientation() {
+        let a = Point::new(0.3678484251536762, 6.179966849702221);
+        let b = Point::new(1.3157201229112638, 6.416934774141618);
+        let c = Point::new(4.159335216184027, 7.127838547459809);
+        assert_eq!(orient2d(a, b, c), 0.0);
+        assert_eq!(orient2d(c, a, b), 0.0);
+    }
+
+    #[test]
+    fn simple_incircle() {
+        let a = Point::new(-1.0, 0.0);
+        let b = Point::new(0.0, -1.0);
+        let c = Point::new(1.0, 0.0);
+        assert!(incircle(a, b, c, Point::new(0.0, 0.5)) > 0.0);
+        assert!(incircle(a, b, c, Point::new(0.0, 1.5)) < 0.0);
+        assert_eq!(incircle(a, b, c, Point::new(0.0, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn near_cocircular_incircle() {
+        let a = Point::new(0.1, 0.1);
+        let b = Point::new(0.3, 0.1);
+        let c = Point::new(0.3, 0.3);
+        assert_eq!(incircle(a, b, c, Point::new(0.1, 0.3)), 0.0);
+        assert!(incircle(a, b, c, Point::new(0.1, 0.30000000000000004)) < 0.0);
+        assert!(incircle(a, b, c, Point::new(0.1, 0.29999999999999993)) > 0.0);
+    }
+
+    #[test]
+    fn circle_bottoms_compared_exactly() {
+        // the circle through these points has its lowest point at 1 - sqrt(2)
+        let (a, b, c) = (Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 2.0));
+        let (center, bottom) = circumcircle(a, b, c).unwrap();
+        assert_eq!(center, Point::new(1.0, 1.0));
+        assert!((bottom - (1.0 - 2f64.sqrt())).abs() < 1e-15);
+        assert_eq!(compare_circle_bottom(a, b, c, -0.4375), Ordering::Greater);
+        assert_eq!(compare_circle_bottom(a, b, c, -0.40625), Ordering::Less);
+        assert!(circumcircle(a, b, Point::new(5.0, 0.0)).is_none());
+
+        // the same circle, through other points of it, and a wider one
+        assert_eq!(compare_circle_bottoms((a, b, c), (b, c, Point::new(0.0, 2.0))), Ordering::Equal);
+        assert_eq!(compare_circle_bottoms((a, b, c), (b, c, Point::new(-1.0, 2.0))), Ordering::Greater);
+    }
+
+    #[test]
+    fn breakpoints_compared_exactly() {
+        // sites at the same height meet halfway between them
+        let (left, right) = (Point::new(0.125, 0.375), Point::new(0.375, 0.375));
+        assert_eq!(compare_breakpoint_x(left, right, Point::new(0.25, 0.125)), Ordering::Equal);
+        assert_eq!(compare_breakpoint_x(left, right, Point::new(0.25000000000000006, 0.125)), Ordering::Greater);
+        assert_eq!(compare_breakpoint_x(left, right, Point::new(0.24999999999999997, 0.125)), Ordering::Less);
+        // a site on the sweep line meets the beachline straight above it
+        assert_eq!(compare_breakpoint_x(left, Point::new(0.25, 0.125), Point::new(0.25, 0.125)), Ordering::Equal);
+    }
+}
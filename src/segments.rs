@@ -0,0 +1,1063 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use ordered_float::OrderedFloat;
+use point::Point;
+use dcel::{DCEL, Vertex, add_faces, bend_edge, contract_edge, left_site, set_left_site, label_enclosed_faces_with};
+use clip::clip_to_convex_polygon;
+use beachline::{BeachLine, BeachNode, BeachItem, Arc, BreakPoint};
+use event::{Event, EventQueue};
+use voronoi::{VoronoiOptions, DuplicateSites, split_arc, split_arc_horizontal, delete_leaf, remove_circle_event};
+use geometry::{Weighting, segments_touch, side_arc};
+use predicates::{orient2d, circumcircle};
+use error::VoronoiError;
+use bounding_box::{BoundingBox, BoxGrid};
+
+const NIL: usize = !0;
+// A neighbor or site that the beachline must have is missing
+const BEACHLINE_BROKEN: VoronoiError = VoronoiError::InternalInconsistency("beachline is missing an expected node");
+
+// Distance, relative to the diagonal of the box, within which the ends of
+// two edges are taken to be the same vertex
+const VERTEX_TOLERANCE: f64 = 1e-9;
+// Distance, relative to the radius, below which two circle event centers
+// are the same vertex; also the rounding, relative to the size and place of
+// a circle, by which its event may lie above the sweep line
+const COCIRCULAR_TOLERANCE: f64 = 1e-9;
+// Relative distance within which breakpoints meet at the center of a touching circle
+const MEETING_TOLERANCE: f64 = 1e-6;
+// Equal steps of the half turn, at the middles of which the frame of the
+// sweep may be turned
+const SWEEP_TURNS: usize = 16;
+// Part of the length of the shortest segment at a shared end by which the
+// segments there are shortened
+const SHARED_END_SHORTENING: f64 = 1e-7;
+// Sine of the angle between two segments below which they are taken to be
+// parallel, as their unit directions only cancel to rounding
+const PARALLEL_TOLERANCE: f64 = 1e-12;
+
+// the swept diagram, and for each vertex the parts whose circle event made it
+type SweptParts = (DCEL, Vec<Option<[usize; 3]>>);
+
+/// An input site of `segment_voronoi`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Site {
+    /// A single point.
+    Point(Point),
+    /// The closed line segment between two points.
+    Segment(Point, Point),
+}
+
+impl From<Point> for Site {
+    fn from(pt: Point) -> Self {
+        Site::Point(pt)
+    }
+}
+
+impl Site {
+    // The ends of the site, which are the same for a point
+    fn ends(&self) -> [Point; 2] {
+        match *self {
+            Site::Point(pt) => [pt, pt],
+            Site::Segment(start, end) => [start, end],
+        }
+    }
+
+    // The ends in order, so that a segment gives the same either way around
+    fn sorted_ends(&self) -> [Point; 2] {
+        let [start, end] = self.ends();
+        if start <= end { [start, end] } else { [end, start] }
+    }
+}
+
+/// Computes the Voronoi diagram of a set of points and line segments, clipped
+/// to a bounding box.
+///
+/// The cell of a site is where the site, as a closed set, is nearest. Between
+/// a point and the inside of a segment the cells are bounded by parabolas,
+/// which become paths of straight edges within `tolerance` of them; all other
+/// edges are straight. Each face is attributed to the index of the input site
+/// whose cell it is, and a segment's cell takes in the locations nearest either
+/// of its ends. A segment with both ends the same is a point.
+///
+/// Segments may share an end, as in a polyline. Around that end, outside the
+/// angle between them, they are equally near; those locations are split
+/// between them along the line halving the angle. Where more segments share
+/// an end, each such location goes to the segment that leans most toward it.
+///
+/// The sites are swept as in `voronoi_with_options`, each segment as its two
+/// ends and the two sides of its inside, so the time grows as n log n in the
+/// number of sites. The sweep runs in a frame turned so that no segment lies
+/// along the sweep line. Segments that share an end are shortened there by a
+/// ten-millionth of the shortest of them, which moves the edges near the end
+/// by no more than that.
+///
+/// Returns the Doubly Connected Edge List, together with a vector mapping the
+/// index of every input site to the index of the input site that represents
+/// it, as in `voronoi_with_options`; sites are duplicates if they are the same
+/// point or the same segment, either way around. Fails with
+/// `VoronoiError::NonFiniteCoordinate` if a site has a NaN or infinite
+/// coordinate, `VoronoiError::InvalidTolerance` unless the tolerance is positive
+/// and finite, and `VoronoiError::IntersectingSites` if two sites other than
+/// duplicates have any point in common, other than an end two segments share.
+/// Segments that run on from a shared end the same way overlap, and a point
+/// may not lie on the end of a segment.
+pub fn segment_voronoi<B: Into<BoundingBox>>(sites: Vec<Site>, bbox: B, tolerance: f64, options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let bbox = bbox.into();
+    if !bbox.is_valid() {
+        return Err(VoronoiError::InvalidBoundingBox);
+    }
+    if tolerance <= 0. || !tolerance.is_finite() {
+        return Err(VoronoiError::InvalidTolerance);
+    }
+    if let Some(index) = sites.iter().position(|site| site.ends().iter().any(|pt| !pt.x().is_finite() || !pt.y().is_finite())) {
+        return Err(VoronoiError::NonFiniteCoordinate { index });
+    }
+
+    let site_map = find_duplicate_sites(&sites);
+    if options.duplicates == DuplicateSites::Reject {
+        if let Some(duplicate) = (0..sites.len()).find(|&index| site_map[index] != index) {
+            return Err(VoronoiError::DuplicateSite { first: site_map[duplicate], duplicate });
+        }
+    }
+    let kept: Vec<(usize, [Point; 2])> = (0..sites.len())
+        .filter(|&index| site_map[index] == index)
+        .map(|index| (index, sites[index].ends()))
+        .collect();
+    let boxes: Vec<BoundingBox> = kept.iter().map(|&(_, ends)| BoundingBox::enclosing(&ends)).collect();
+    let grid = BoxGrid::new(&boxes);
+    for (place, &(first, first_ends)) in kept.iter().enumerate() {
+        let crossing = grid.near(&boxes[place]).into_iter().find(|&other| other > place && sites_cross(first_ends, kept[other].1));
+        if let Some(other) = crossing {
+            return Err(VoronoiError::IntersectingSites { first, second: kept[other].0 });
+        }
+    }
+
+    let mut dcel = segments_in_box(&kept, &bbox, tolerance)?;
+    if options.compact { dcel.compact(); }
+    Ok((dcel, site_map))
+}
+
+// For each site, the index of the first site that is the same point or
+// the same segment, either way around
+fn find_duplicate_sites(sites: &[Site]) -> Vec<usize> {
+    let keys: Vec<[Point; 2]> = sites.iter().map(Site::sorted_ends).collect();
+    let mut order: Vec<usize> = (0..sites.len()).collect();
+    order.sort_by(|&a, &b| keys[a].cmp(&keys[b]).then(a.cmp(&b)));
+
+    let mut site_map: Vec<usize> = (0..sites.len()).collect();
+    for pair in order.windows(2) {
+        if keys[pair[0]] == keys[pair[1]] {
+            site_map[pair[1]] = site_map[pair[0]];
+        }
+    }
+    site_map
+}
+
+// Whether two sites have a point in common, other than an end that two
+// segments share and that is all they have in common
+fn sites_cross(first: [Point; 2], second: [Point; 2]) -> bool {
+    if !segments_touch(first, second) { return false; }
+    match shared_end(first, second) {
+        Some(end) => {
+            let (first_far, second_far) = (far_end(first, end), far_end(second, end));
+            // a point, or segments running the same way from the end
+            first_far == end || second_far == end
+                || (orient2d(end, first_far, second_far) == 0. && (first_far - end).dot(second_far - end) > 0.)
+        }
+        None => true,
+    }
+}
+
+// An end of one site that is also an end of the other
+fn shared_end(first: [Point; 2], second: [Point; 2]) -> Option<Point> {
+    first.iter().cloned().find(|end| second.contains(end))
+}
+
+// The end of a site other than the given one
+fn far_end(ends: [Point; 2], end: Point) -> Point {
+    if ends[0] == end { ends[1] } else { ends[0] }
+}
+
+// An offset scaled to unit length
+fn unit(pt: Point) -> Point {
+    pt * (1. / pt.dot(pt).sqrt())
+}
+
+// An offset turned a quarter counterclockwise
+fn square(pt: Point) -> Point {
+    Point::new(-pt.y(), pt.x())
+}
+
+// The point of a site, as its ends, nearest a location
+fn nearest_point(ends: [Point; 2], pt: Point) -> Point {
+    let [start, end] = ends;
+    let span = end - start;
+    let along = if start == end { 0. } else { ((pt - start).dot(span) / span.dot(span)).clamp(0., 1.) };
+    start + span * along
+}
+
+// The distance from a location to a site, as its ends
+fn site_distance(ends: [Point; 2], pt: Point) -> f64 {
+    let foot = nearest_point(ends, pt);
+    (pt - foot).dot(pt - foot).sqrt()
+}
+
+// A location in the frame turned by `turn`, given as its cosine and sine, and back
+fn turn_by(pt: Point, turn: Point) -> Point {
+    Point::new(pt.x() * turn.x() - pt.y() * turn.y(), pt.x() * turn.y() + pt.y() * turn.x())
+}
+
+fn turn_back(pt: Point, turn: Point) -> Point {
+    Point::new(pt.x() * turn.x() + pt.y() * turn.y(), pt.y() * turn.x() - pt.x() * turn.y())
+}
+
+// A part of a site that the sweep treats as one: a point, or the side of a
+// segment to the right of the way from its first end to its second
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Part {
+    Point(Point),
+    Side(Point, Point),
+}
+
+impl Part {
+    // The part as the beachline holds it, as a site and the end of its segment
+    fn from_arc((site, end): (Point, Point)) -> Self {
+        if site == end { Part::Point(site) } else { Part::Side(site, end) }
+    }
+
+    fn turned(&self, turn: Point) -> Self {
+        match *self {
+            Part::Point(pt) => Part::Point(turn_by(pt, turn)),
+            Part::Side(start, end) => Part::Side(turn_by(start, turn), turn_by(end, turn)),
+        }
+    }
+
+    // The farthest any of the locations is from the part
+    fn reach(&self, corners: &[Point]) -> f64 {
+        let ends = match *self {
+            Part::Point(pt) => [pt, pt],
+            Part::Side(start, end) => [start, end],
+        };
+        corners.iter().flat_map(|&corner| ends.iter().map(move |&end| (corner - end).dot(corner - end).sqrt()))
+            .fold(0., f64::max)
+    }
+
+    // The height of the arc of the part over the sweep line, at x
+    fn arc_height(&self, x: f64, yl: f64) -> f64 {
+        match *self {
+            Part::Point(pt) => (x - pt.x()) * (x - pt.x()) / (2. * (pt.y() - yl)) + (pt.y() + yl) / 2.,
+            Part::Side(start, end) => {
+                let (slope, offset) = side_arc(start, end, yl);
+                slope * x + offset
+            }
+        }
+    }
+
+    // Whether a location is square to the segment of a side, but for rounding
+    fn faces(&self, pt: Point) -> bool {
+        match *self {
+            Part::Point(_) => true,
+            Part::Side(start, end) => {
+                let along = (pt - start).dot(end - start) / (end - start).dot(end - start);
+                (-MEETING_TOLERANCE..=1. + MEETING_TOLERANCE).contains(&along)
+            }
+        }
+    }
+}
+
+// Whether two parts are the two sides of one segment
+fn opposite(first: Part, second: Part) -> bool {
+    match (first, second) {
+        (Part::Side(start, end), Part::Side(other_start, other_end)) => start == other_end && end == other_start,
+        _ => false,
+    }
+}
+
+// How the sweep takes a part
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Role {
+    // a point site
+    Alone,
+    // the end of a segment that the sweep reaches first, or last, with the
+    // part of the side to the left of the segment; the next part is the other side
+    Start(usize),
+    End(usize),
+    Side,
+}
+
+// The parts of the sites, in the frame of the sweep and as given, with the
+// input index of the site of each
+struct Parts {
+    turned: Vec<Part>,
+    given: Vec<Part>,
+    roles: Vec<Role>,
+    sites: Vec<usize>,
+}
+
+impl Parts {
+    fn push(&mut self, part: Part, role: Role, site: usize, turn: Point) {
+        self.turned.push(part.turned(turn));
+        self.given.push(part);
+        self.roles.push(role);
+        self.sites.push(site);
+    }
+}
+
+// The turn of the frame of the sweep, as its cosine and sine: of the turns
+// to the middles of the steps of the half turn, the one that keeps every
+// segment farthest from lying along the sweep line
+fn sweep_turn(sites: &[(usize, [Point; 2])]) -> Point {
+    let slant = |turn: Point| sites.iter()
+        .filter(|&&(_, [start, end])| start != end)
+        .map(|&(_, [start, end])| turn_by(unit(end - start), turn).y().abs())
+        .fold(f64::INFINITY, f64::min);
+    (0..SWEEP_TURNS)
+        .map(|step| {
+            let angle = (step as f64 + 0.5) * PI / SWEEP_TURNS as f64;
+            Point::new(angle.cos(), angle.sin())
+        })
+        .max_by_key(|&turn| OrderedFloat(slant(turn)))
+        .unwrap_or_else(|| Point::new(1., 0.))
+}
+
+// Splits the sites into the parts the sweep takes. Segments that share an
+// end are all shortened there by the same small part of the shortest of
+// them, so that no two parts meet; the bisector of their new ends still
+// runs through the shared end, halving the angle between them.
+fn site_parts(sites: &[(usize, [Point; 2])], turn: Point) -> Parts {
+    // the number of segments at each end, and the length of the shortest
+    let mut at_end: BTreeMap<Point, (usize, f64)> = BTreeMap::new();
+    for &(_, [start, end]) in sites {
+        if start == end { continue; }
+        let length = (end - start).dot(end - start).sqrt();
+        for &pt in &[start, end] {
+            let entry = at_end.entry(pt).or_insert((0, f64::INFINITY));
+            *entry = (entry.0 + 1, entry.1.min(length));
+        }
+    }
+    let shortened = |end: Point, far: Point| match at_end.get(&end) {
+        Some(&(count, length)) if count > 1 => end + unit(far - end) * (SHARED_END_SHORTENING * length),
+        _ => end,
+    };
+
+    let mut parts = Parts { turned: vec![], given: vec![], roles: vec![], sites: vec![] };
+    for &(index, [first, second]) in sites {
+        if first == second {
+            parts.push(Part::Point(first), Role::Alone, index, turn);
+            continue;
+        }
+        let (first, second) = (shortened(first, second), shortened(second, first));
+        let (start, end) = if turn_by(first, turn) > turn_by(second, turn) { (first, second) } else { (second, first) };
+        let side = parts.given.len() + 2;
+        parts.push(Part::Point(start), Role::Start(side), index, turn);
+        parts.push(Part::Point(end), Role::End(side), index, turn);
+        parts.push(Part::Side(start, end), Role::Side, index, turn);
+        parts.push(Part::Side(end, start), Role::Side, index, turn);
+    }
+    parts
+}
+
+// Builds the diagram of the sites, as (input index, ends), which neither
+// coincide nor touch but at ends that segments share. The parts of the sites
+// are swept in the turned frame, and the vertices of the circle events are
+// then found again from their parts as given, so that they carry no rounding
+// from the turn. Edges between a part and itself, such as the line square to
+// a segment at its end, are removed, as are edges left shorter than rounding.
+fn segments_in_box(sites: &[(usize, [Point; 2])], bbox: &BoundingBox, tolerance: f64) -> Result<DCEL, VoronoiError> {
+    let turn = sweep_turn(sites);
+    let parts = site_parts(sites, turn);
+    let corners: Vec<Point> = bbox.corners().iter().map(|&corner| turn_by(corner, turn)).collect();
+    let (mut dcel, vertex_parts) = sweep_parts(&parts, &corners)?;
+
+    for (vertex, &triple) in vertex_parts.iter().enumerate() {
+        let swept = turn_back(dcel.vertices[vertex].coordinates, turn);
+        dcel.vertices[vertex].coordinates = triple
+            .and_then(|[left, middle, right]| touching_circles([parts.given[left], parts.given[middle], parts.given[right]]).into_iter()
+                .map(|(center, _)| center)
+                .min_by_key(|&center| OrderedFloat((center - swept).dot(center - swept))))
+            .unwrap_or(swept);
+    }
+
+    let snap = VERTEX_TOLERANCE * bbox.diagonal();
+    for edge in 0..dcel.halfedges.len() {
+        if !dcel.halfedges[edge].is_alive() { continue; }
+        let offset = dcel.get_origin(edge) - dcel.get_origin(dcel.halfedges[edge].twin);
+        if offset.dot(offset).sqrt() <= snap { contract_edge(&mut dcel, edge)?; }
+    }
+
+    let curved: Vec<usize> = (0..dcel.halfedges.len())
+        .filter(|&edge| dcel.halfedges[edge].is_alive() && edge < dcel.halfedges[edge].twin)
+        .collect();
+    for edge in curved {
+        let twin = dcel.halfedges[edge].twin;
+        if let (Some(left), Some(right)) = (left_site(&dcel, edge), left_site(&dcel, twin)) {
+            let path = parabola_points(parts.given[left], parts.given[right], dcel.get_origin(edge), dcel.get_origin(twin), tolerance);
+            bend_edge(&mut dcel, edge, &path)?;
+        }
+    }
+
+    for edge in 0..dcel.halfedges.len() {
+        if let Some(part) = left_site(&dcel, edge) { set_left_site(&mut dcel, edge, parts.sites[part]); }
+    }
+    dcel.set_prev()?;
+    for edge in 0..dcel.halfedges.len() {
+        let twin = dcel.halfedges[edge].twin;
+        if dcel.halfedges[edge].is_alive() && left_site(&dcel, edge) == left_site(&dcel, twin) {
+            dcel.remove_edge(edge);
+        }
+    }
+    // the far end of a removed edge is left with none
+    for vertex in &mut dcel.vertices {
+        if vertex.alive && !dcel.halfedges[vertex.incident_edge].is_alive() { vertex.alive = false; }
+    }
+
+    clip_to_convex_polygon(&mut dcel, &bbox.corners())?;
+    add_faces(&mut dcel)?;
+    label_enclosed_faces_with(&mut dcel, |pt| sites.iter().min_by_key(|&&(_, ends)| OrderedFloat(site_distance(ends, pt))).map(|&(index, _)| index))?;
+    Ok(dcel)
+}
+
+// Runs Fortune's algorithm on the parts, in the frame of the sweep, and
+// extends the edges left on the beachline beyond the corners of the box,
+// turned likewise. The halfedges have the parts to their left as sites.
+// Also returns, for each vertex, the parts whose circle event made it.
+//
+// The sweep reaches a segment at its start: the arc of the start is split
+// there as that of a point is, and the sides of the segment come up within
+// it, meeting where the segment crosses the sweep line. The arc of the end
+// comes up between them when the sweep reaches it.
+fn sweep_parts(parts: &Parts, corners: &[Point]) -> Result<SweptParts, VoronoiError> {
+    trace!("Starting segment Voronoi computation");
+    let mut queue = EventQueue::new();
+    let mut beachline = BeachLine::new();
+    beachline.weighting = Weighting::Segments;
+    for (place, &part) in parts.turned.iter().enumerate() {
+        if let Part::Point(pt) = part { queue.push(Event::Site(pt, place)); }
+    }
+    let mut dcel = DCEL::new();
+    let mut vertex_parts = vec![];
+    // the breakpoint between the sides of each segment that crosses the
+    // sweep line, by the part of its left side
+    let mut crossings = vec![NIL; parts.turned.len()];
+    let mut sweep_y = f64::INFINITY;
+
+    while let Some(this_event) = queue.pop() {
+        trace!("Popped event from queue: {:?}", this_event);
+        match this_event {
+            Event::Site(pt, place) => {
+                sweep_y = pt.y();
+                let (first, last) = match parts.roles[place] {
+                    Role::End(side) => {
+                        let leaf = end_segment(pt, place, side, crossings[side], &mut queue, &mut beachline, &mut dcel)?;
+                        (leaf, leaf)
+                    }
+                    role => {
+                        let leaf = add_point_arc(pt, place, &mut queue, &mut beachline, &mut dcel)?;
+                        match role {
+                            Role::Start(side) => {
+                                let (crossing, first, last) = start_segment(leaf, side, parts, &mut beachline, &mut dcel)?;
+                                crossings[side] = crossing;
+                                (first, last)
+                            }
+                            _ => (leaf, leaf),
+                        }
+                    }
+                };
+                let beside = [beachline.get_left_arc(Some(first)), Some(first), Some(last), beachline.get_right_arc(Some(last))];
+                for &arc in beside.iter().flatten() {
+                    check_circle(arc, sweep_y, parts, &mut queue, &mut beachline)?;
+                }
+            }
+            Event::Circle(center, height, _, leaf, _) => {
+                sweep_y = height;
+                close_arc(leaf, center, height, parts, &mut queue, &mut beachline, &mut dcel, &mut vertex_parts)?;
+            }
+            Event::Appearance(..) => {
+                return Err(VoronoiError::InternalInconsistency("segment sites do not wait"));
+            }
+        }
+    }
+    if !beachline.is_empty() {
+        extend_breakpoints(sweep_y, corners, &beachline, &mut dcel, &mut vertex_parts)?;
+    }
+    Ok((dcel, vertex_parts))
+}
+
+// Adds the arc of a point that the sweep line has reached, as for the site
+// of a Voronoi diagram. Returns its leaf.
+fn add_point_arc(pt: Point, place: usize, queue: &mut EventQueue, beachline: &mut BeachLine, dcel: &mut DCEL) -> Result<usize, VoronoiError> {
+    if beachline.is_empty() {
+        beachline.insert_point(pt, place);
+        return Ok(beachline.root);
+    }
+    let arc_above = beachline.get_arc_above(pt)?;
+    remove_circle_event(arc_above, queue, beachline);
+    let (site, end) = beachline.get_site_end(arc_above)?;
+    if site == end && site.y() == pt.y() {
+        let last_arc = beachline.tree_maximum(beachline.root);
+        split_arc_horizontal(last_arc, pt, place, beachline, dcel, &[])
+    } else {
+        split_arc(arc_above, pt, place, beachline, dcel, &[])
+    }
+}
+
+// Brings up the sides of a segment, the parts from `side`, in the middle of
+// the new arc of its start. The edges between the start and the sides run
+// along the line square to the segment there. Returns the breakpoint between
+// the sides, and the leaves of the pieces of the arc of the start on either side.
+fn start_segment(leaf: usize, side: usize, parts: &Parts, beachline: &mut BeachLine, dcel: &mut DCEL) -> Result<(usize, usize, usize), VoronoiError> {
+    let parent = beachline.nodes[leaf].parent;
+    let (start, _) = beachline.get_site_end(leaf)?;
+    let place = beachline.get_site_index(leaf)?;
+    let end = match parts.turned[side] {
+        Part::Side(_, end) => end,
+        Part::Point(_) => return Err(VoronoiError::InternalInconsistency("segment has no side")),
+    };
+
+    let (twin1, twin2) = dcel.add_twins();
+    set_left_site(dcel, twin1, place);
+    set_left_site(dcel, twin2, side + 1);
+
+    let arcs = vec![
+        Arc::new(start, place, None),
+        Arc { end, ..Arc::new(start, side, None) },
+        Arc { end: start, ..Arc::new(end, side + 1, None) },
+        Arc::new(start, place, None),
+    ];
+    let breakpoints = vec![
+        BreakPoint::between(&arcs[0], &arcs[1], twin1),
+        BreakPoint::between(&arcs[1], &arcs[2], NIL),
+        BreakPoint::between(&arcs[2], &arcs[3], twin2),
+    ];
+    let leaves: Vec<usize> = arcs.into_iter().map(|arc| beachline.add_node(BeachNode::make_arc(None, BeachItem::Leaf(arc)))).collect();
+
+    // each breakpoint has the arc to its left, and the rest to its right
+    let mut nodes = vec![];
+    let mut right = leaves[3];
+    for (index, breakpoint) in breakpoints.into_iter().enumerate().rev() {
+        let priority = beachline.next_priority();
+        let node = beachline.add_node(BeachNode { parent: None, left_child: Some(leaves[index]), right_child: Some(right), item: BeachItem::Internal(breakpoint), priority });
+        beachline.nodes[leaves[index]].parent = Some(node);
+        beachline.nodes[right].parent = Some(node);
+        nodes.push(node);
+        right = node;
+    }
+    beachline.nodes[right].parent = parent;
+    beachline.replace_child(parent, leaf, right)?;
+    beachline.free_node(leaf);
+
+    // the lower breakpoints first, as in split_arc
+    for &node in &nodes {
+        beachline.rebalance(node)?;
+    }
+    Ok((nodes[1], leaves[0], leaves[3]))
+}
+
+// Brings up the arc of the end of a segment between its sides, which meet
+// at the breakpoint `crossing`. Returns the leaf of the new arc.
+#[allow(clippy::too_many_arguments)]
+fn end_segment(pt: Point, place: usize, side: usize, crossing: usize, queue: &mut EventQueue, beachline: &mut BeachLine, dcel: &mut DCEL) -> Result<usize, VoronoiError> {
+    if crossing == NIL { return Err(VoronoiError::InternalInconsistency("segment ends before it starts")); }
+    let left = beachline.predecessor(crossing).ok_or(BEACHLINE_BROKEN)?;
+    let right = beachline.successor(crossing).ok_or(BEACHLINE_BROKEN)?;
+    remove_circle_event(left, queue, beachline);
+    remove_circle_event(right, queue, beachline);
+
+    let (twin1, twin2) = dcel.add_twins();
+    set_left_site(dcel, twin1, side);
+    set_left_site(dcel, twin2, place);
+
+    let arc = Arc::new(pt, place, None);
+    let breakpoint = match beachline.nodes[right].item {
+        BeachItem::Leaf(ref right_arc) => BreakPoint::between(&arc, right_arc, twin2),
+        BeachItem::Internal(_) => return Err(BEACHLINE_BROKEN),
+    };
+    let leaf = beachline.add_node(BeachNode::make_arc(None, BeachItem::Leaf(arc)));
+    beachline.set_right_site(crossing, leaf, 0.)?;
+    if let BeachItem::Internal(ref mut crossing_breakpoint) = beachline.nodes[crossing].item {
+        crossing_breakpoint.halfedge = twin1;
+    }
+
+    let parent = beachline.nodes[right].parent;
+    let priority = beachline.next_priority();
+    let node = beachline.add_node(BeachNode { parent, left_child: Some(leaf), right_child: Some(right), item: BeachItem::Internal(breakpoint), priority });
+    beachline.replace_child(parent, right, node)?;
+    beachline.nodes[leaf].parent = Some(node);
+    beachline.nodes[right].parent = Some(node);
+    beachline.rebalance(node)?;
+    Ok(leaf)
+}
+
+// Looks for the circle event at which the arc of a leaf vanishes, in place
+// of any it had. The arc vanishes at the center of a circle touching its part
+// and those beside it, when the sweep line is as far below the center as the
+// circle is large; of those still to come, at the first at which the
+// breakpoints on either side of the arc meet. An arc never vanishes between
+// arcs of one part, or among parts of one site only, and the two sides of a
+// segment never meet at a circle event.
+fn check_circle(leaf: usize, sweep_y: f64, parts: &Parts, queue: &mut EventQueue, beachline: &mut BeachLine) -> Result<(), VoronoiError> {
+    remove_circle_event(leaf, queue, beachline);
+    let (left, right) = match (beachline.get_left_arc(Some(leaf)), beachline.get_right_arc(Some(leaf))) {
+        (Some(left), Some(right)) => (left, right),
+        _ => return Ok(()),
+    };
+    let places = [beachline.get_site_index(left)?, beachline.get_site_index(leaf)?, beachline.get_site_index(right)?];
+    let triple = [parts.turned[places[0]], parts.turned[places[1]], parts.turned[places[2]]];
+    let one_site = places.iter().all(|&place| parts.sites[place] == parts.sites[places[0]]);
+    if one_site || triple[0] == triple[2] || opposite(triple[0], triple[1]) || opposite(triple[1], triple[2]) || opposite(triple[0], triple[2]) {
+        return Ok(());
+    }
+    let (left_breakpoint, right_breakpoint) = (beachline.predecessor(leaf).ok_or(BEACHLINE_BROKEN)?, beachline.successor(leaf).ok_or(BEACHLINE_BROKEN)?);
+    let middle = beachline.get_site(Some(leaf)).ok_or(BEACHLINE_BROKEN)?;
+
+    let mut first: Option<(Point, f64)> = None;
+    for (center, radius) in touching_circles(triple) {
+        let bottom = center.y() - radius;
+        if !bottom.is_finite() { return Err(VoronoiError::DegenerateInput); }
+        let scale = (center - middle).dot(center - middle).sqrt() + radius;
+        let rounding = COCIRCULAR_TOLERANCE * (scale + center.x().abs() + center.y().abs());
+        if bottom > sweep_y + rounding || !triple.iter().all(|part| part.faces(center)) { continue; }
+        let meets = |x: f64| (x - center.x()).abs() <= MEETING_TOLERANCE * scale + rounding;
+        if meets(beachline.get_breakpoint_x(left_breakpoint, bottom)?) && meets(beachline.get_breakpoint_x(right_breakpoint, bottom)?)
+            && first.map(|(_, first_bottom)| bottom > first_bottom).unwrap_or(true) {
+            first = Some((center, bottom));
+        }
+    }
+    if let Some((center, bottom)) = first {
+        let this_event = Event::Circle(center, bottom, None, leaf, 0);
+        if let BeachItem::Leaf(ref mut arc) = beachline.nodes[leaf].item {
+            arc.site_event = Some(queue.push(this_event));
+        }
+    }
+    Ok(())
+}
+
+// Removes the arc of a leaf at a circle event, as `handle_circle_event` does
+// for a Voronoi diagram, and records the parts whose edges meet at the new vertex.
+#[allow(clippy::too_many_arguments)]
+fn close_arc(leaf: usize, center: Point, sweep_y: f64, parts: &Parts, queue: &mut EventQueue, beachline: &mut BeachLine, dcel: &mut DCEL, vertex_parts: &mut Vec<Option<[usize; 3]>>) -> Result<(), VoronoiError> {
+    let left_neighbor = beachline.get_left_arc(Some(leaf)).ok_or(BEACHLINE_BROKEN)?;
+    let right_neighbor = beachline.get_right_arc(Some(leaf)).ok_or(BEACHLINE_BROKEN)?;
+    let triple = [beachline.get_site_index(left_neighbor)?, beachline.get_site_index(leaf)?, beachline.get_site_index(right_neighbor)?];
+    let (pred, succ, parent, other) = delete_leaf(leaf, beachline, &[])?;
+
+    remove_circle_event(leaf, queue, beachline);
+    remove_circle_event(left_neighbor, queue, beachline);
+    remove_circle_event(right_neighbor, queue, beachline);
+
+    let (twin1, twin2) = dcel.add_twins();
+    set_left_site(dcel, twin1, triple[2]);
+    set_left_site(dcel, twin2, triple[0]);
+
+    let center_vertex = dcel.vertices.len();
+    dcel.vertices.push(Vertex { coordinates: center, incident_edge: twin1, alive: true });
+    vertex_parts.push(Some(triple));
+
+    let edges = [beachline.get_edge(pred)?, beachline.get_edge(succ)?, beachline.get_edge(parent)?, beachline.get_edge(other)?];
+    if edges.contains(&NIL) { return Err(VoronoiError::InternalInconsistency("sides of a segment meet at a circle event")); }
+    let [pred_edge, succ_edge, parent_edge, other_edge] = edges;
+    let pred_edge_twin = dcel.halfedges[pred_edge].twin;
+    let succ_edge_twin = dcel.halfedges[succ_edge].twin;
+
+    dcel.halfedges[parent_edge].origin = center_vertex;
+    dcel.halfedges[other_edge].origin = center_vertex;
+    dcel.halfedges[twin1].origin = center_vertex;
+
+    dcel.halfedges[pred_edge_twin].next = succ_edge;
+    dcel.halfedges[succ_edge_twin].next = twin1;
+    dcel.halfedges[twin2].next = pred_edge;
+
+    // a disappearing breakpoint whose other end lies on the same circle
+    // traced a zero-length edge, so its endpoints become one vertex
+    let radius = center.y() - sweep_y;
+    for &edge in &[pred_edge, succ_edge] {
+        let edge_twin = dcel.halfedges[edge].twin;
+        if dcel.halfedges[edge_twin].origin == NIL { continue; }
+        let offset = dcel.get_origin(edge_twin) - center;
+        if offset.dot(offset).sqrt() <= COCIRCULAR_TOLERANCE * radius {
+            trace!("Merging cocircular vertex along halfedge {}", edge);
+            contract_edge(dcel, edge)?;
+        }
+    }
+
+    if let BeachItem::Internal(ref mut breakpoint) = beachline.nodes[other].item {
+        breakpoint.halfedge = twin2;
+    }
+    beachline.free_node(leaf);
+    beachline.free_node(parent);
+
+    check_circle(left_neighbor, sweep_y, parts, queue, beachline)?;
+    check_circle(right_neighbor, sweep_y, parts, queue, beachline)
+}
+
+// Ends the edges of the breakpoints left on the beachline, each where it is
+// farther from its parts than any corner of the box is from the part to its
+// left, lowering the sweep line below the last event until it gets there.
+fn extend_breakpoints(sweep_y: f64, corners: &[Point], beachline: &BeachLine, dcel: &mut DCEL, vertex_parts: &mut Vec<Option<[usize; 3]>>) -> Result<(), VoronoiError> {
+    let (low, high) = corners.iter().fold((sweep_y, sweep_y), |(low, high), corner| (low.min(corner.y()), high.max(corner.y())));
+    let mut current_node = beachline.tree_minimum(beachline.root);
+    while let Some(node) = beachline.successor(current_node) {
+        current_node = node;
+        let (this_edge, left, right) = match beachline.nodes[node].item {
+            BeachItem::Internal(ref breakpoint) => (breakpoint.halfedge, Part::from_arc((breakpoint.left_site, breakpoint.left_end)), Part::from_arc((breakpoint.right_site, breakpoint.right_end))),
+            BeachItem::Leaf(_) => continue,
+        };
+        if this_edge == NIL { return Err(VoronoiError::InternalInconsistency("segment is left crossing the sweep line")); }
+        let reach = left.reach(corners);
+        let arc = if let Part::Side(..) = right { right } else { left };
+        let mut drop = (high - low).max(reach);
+        let end_pt = loop {
+            let yl = low - drop;
+            let x = beachline.get_breakpoint_x(node, yl)?;
+            let pt = Point::new(x, arc.arc_height(x, yl));
+            if !pt.x().is_finite() || !pt.y().is_finite() { return Err(VoronoiError::DegenerateInput); }
+            if pt.y() - yl > reach { break pt; }
+            drop *= 2.;
+        };
+        trace!("Extending halfedge {:?} to {:?}", this_edge, end_pt);
+
+        let this_twin = dcel.halfedges[this_edge].twin;
+        dcel.halfedges[this_edge].origin = dcel.vertices.len();
+        dcel.halfedges[this_twin].next = this_edge;
+        dcel.vertices.push(Vertex { coordinates: end_pt, incident_edge: this_edge, alive: true });
+        vertex_parts.push(None);
+    }
+    Ok(())
+}
+
+// The circles touching three parts, as (center, radius), each on the side of
+// any side among them. A point that is an end of a segment one of whose
+// sides is among them is touched only by circles whose centers lie on the
+// line square to the segment there.
+fn touching_circles(parts: [Part; 3]) -> Vec<(Point, f64)> {
+    let side_normal = |start: Point, end: Point| { let along = unit(end - start); Point::new(along.y(), -along.x()) };
+    for &(first, second, third) in &[(0, 1, 2), (0, 2, 1), (1, 0, 2), (1, 2, 0), (2, 0, 1), (2, 1, 0)] {
+        if let (Part::Point(end), Part::Side(start, far)) = (parts[first], parts[second]) {
+            if end != start && end != far { continue; }
+            let normal = side_normal(start, far);
+            let radius = match parts[third] {
+                Part::Point(pt) => (pt - end).dot(pt - end) / (2. * normal.dot(pt - end)),
+                Part::Side(other_start, other_far) => {
+                    let other_normal = side_normal(other_start, other_far);
+                    (other_start - end).dot(other_normal) / (normal.dot(other_normal) - 1.)
+                }
+            };
+            return if radius > 0. && radius.is_finite() { vec![(end + normal * radius, radius)] } else { vec![] };
+        }
+    }
+
+    let points: Vec<Point> = parts.iter().filter_map(|&part| if let Part::Point(pt) = part { Some(pt) } else { None }).collect();
+    let sides: Vec<(Point, Point)> = parts.iter().filter_map(|&part| if let Part::Side(start, end) = part { Some((start, side_normal(start, end))) } else { None }).collect();
+    let mut circles = vec![];
+    match (points.len(), sides.len()) {
+        (3, _) => {
+            circles.extend(circumcircle(points[0], points[1], points[2]).map(|(center, bottom)| (center, center.y() - bottom)));
+        }
+        (2, _) => {
+            // along the bisector of the points, the distance to the line is linear
+            let (from, through, normal) = (points[0], sides[0].0, sides[0].1);
+            let middle = (points[0] + points[1]) * 0.5;
+            let across = unit(square(points[1] - points[0]));
+            let (offset, rise) = ((middle - through).dot(normal), across.dot(normal));
+            let base = (middle - from).dot(middle - from);
+            for param in roots([base - offset * offset, -2. * offset * rise, 1. - rise * rise]) {
+                circles.push((middle + across * param, offset + rise * param));
+            }
+        }
+        (1, _) => {
+            let (pt, (first, first_normal), (second, second_normal)) = (points[0], sides[0], sides[1]);
+            let det = first_normal.cross(second_normal);
+            if det.abs() <= PARALLEL_TOLERANCE {
+                // facing lines, with the centers midway between them
+                if first_normal.dot(second_normal) > 0. { return vec![]; }
+                let radius = (second - first).dot(first_normal) / 2.;
+                let (base, along) = (first + first_normal * radius, square(first_normal));
+                let offset = base - pt;
+                for param in roots([offset.dot(offset) - radius * radius, 2. * offset.dot(along), 1.]) {
+                    circles.push((base + along * param, radius));
+                }
+            } else {
+                // the center is normal . c = normal . through + r for both lines
+                let solve = |a: f64, b: f64| Point::new((a * second_normal.y() - b * first_normal.y()) / det, (first_normal.x() * b - second_normal.x() * a) / det);
+                let base = solve(first.dot(first_normal), second.dot(second_normal));
+                let per = solve(1., 1.);
+                let offset = base - pt;
+                for radius in roots([offset.dot(offset), 2. * offset.dot(per), per.dot(per) - 1.]) {
+                    circles.push((base + per * radius, radius));
+                }
+            }
+        }
+        _ => {
+            // normal . c - r = normal . through for all three lines
+            let rows: Vec<[f64; 4]> = sides.iter().map(|&(through, normal)| [normal.x(), normal.y(), -1., through.dot(normal)]).collect();
+            let det3 = |col: [usize; 3]| {
+                let entry = |row: usize, place: usize| rows[row][col[place]];
+                entry(0, 0) * (entry(1, 1) * entry(2, 2) - entry(1, 2) * entry(2, 1))
+                    - entry(0, 1) * (entry(1, 0) * entry(2, 2) - entry(1, 2) * entry(2, 0))
+                    + entry(0, 2) * (entry(1, 0) * entry(2, 1) - entry(1, 1) * entry(2, 0))
+            };
+            let det = det3([0, 1, 2]);
+            if det.abs() > PARALLEL_TOLERANCE {
+                let (x, y, radius) = (det3([3, 1, 2]) / det, det3([0, 3, 2]) / det, det3([0, 1, 3]) / det);
+                circles.push((Point::new(x, y), radius));
+            }
+        }
+    }
+    circles.retain(|&(_, radius)| radius > 0. && radius.is_finite());
+    circles
+}
+
+// The points at which to bend an edge between two parts, from start to end,
+// so that it stays within `tolerance` of the parabola between a point and a
+// side. Other edges are straight, and have none.
+fn parabola_points(first: Part, second: Part, start: Point, end: Point, tolerance: f64) -> Vec<Point> {
+    let (focus, from, to) = match (first, second) {
+        (Part::Point(pt), Part::Side(from, to)) | (Part::Side(from, to), Part::Point(pt)) => (pt, from, to),
+        _ => return vec![],
+    };
+    let along = unit(to - from);
+    let normal = Point::new(along.y(), -along.x());
+    let height = (focus - from).dot(normal);
+    if focus == from || focus == to || height <= 0. { return vec![]; }
+    // halfway between the point and the line, square to the foot of the point
+    let foot = from + along * (focus - from).dot(along);
+    let curve = Curve { c0: foot + normal * (height / 2.), c1: along, c2: normal * (0.5 / height) };
+    let params = curve_params(curve, (start - foot).dot(along), (end - foot).dot(along), tolerance);
+    params[1..params.len() - 1].iter().map(|&param| curve.at(param)).collect()
+}
+
+// A curve p(t) = c0 + c1 t + c2 t^2, with c1 of unit length
+#[derive(Clone, Copy, Debug)]
+struct Curve {
+    c0: Point,
+    c1: Point,
+    c2: Point,
+}
+
+impl Curve {
+    fn at(&self, param: f64) -> Point {
+        self.c0 + self.c1 * param + self.c2 * (param * param)
+    }
+}
+
+// The coefficients of a polynomial in the parameter, of degree at most two,
+// from the constant up
+type Quadratic = [f64; 3];
+
+// The real roots of a polynomial of degree at most two. One that is zero
+// everywhere has none.
+fn roots(values: Quadratic) -> Vec<f64> {
+    let [constant, linear, square] = values;
+    if square == 0. {
+        return if linear == 0. { vec![] } else { vec![-constant / linear] };
+    }
+    let discrim = linear * linear - 4. * square * constant;
+    if discrim < 0. { return vec![]; }
+    // avoid cancellation in the root of smaller magnitude
+    let big = -(linear + linear.signum() * discrim.sqrt()) / 2.;
+    if big == 0. { return vec![0.]; }
+    vec![big / square, constant / big]
+}
+
+// Parameters from start to end, in order, at which to place the vertices of
+// a path within `tolerance` of the curve. A chord strays from the curve by
+// at most an eighth of its span squared times the bend, 2 |c2|.
+fn curve_params(curve: Curve, start: f64, end: f64, tolerance: f64) -> Vec<f64> {
+    let bend = 2. * curve.c2.dot(curve.c2).sqrt();
+    let count = if bend == 0. { 1 } else { ((end - start).abs() / (8. * tolerance / bend).sqrt()).ceil().max(1.) as usize };
+    (0..=count).map(|step| if step == count { end } else { start + (end - start) * step as f64 / count as f64 }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcel::{make_site_polygons, make_site_polygons_with_holes, signed_area};
+    use test_utils::{assert_fills_box, assert_grid_owned, clearly_nearest, face_contains};
+    use validate::Validated;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // the faces tile the box, and each location on a grid lies in a face of
+    // the site nearest it, unless it is within the tolerance of a boundary
+    fn assert_segment_cells(sites: &[Site], size: f64, tolerance: f64) {
+        let dcel = segment_voronoi(sites.to_vec(), size, tolerance, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons_with_holes(&dcel);
+        assert_fills_box(&faces, size);
+        assert_grid_owned(&faces, size, |pt| clearly_nearest(sites.len(), |index| site_distance(sites[index].ends(), pt), 4. * tolerance));
+    }
+
+    #[test]
+    fn parabola_between_point_and_segment() {
+        let sites = vec![Site::Segment(Point::new(20.0, 50.0), Point::new(80.0, 50.0)), Site::Point(Point::new(50.0, 20.0))];
+        let dcel = segment_voronoi(sites.clone(), 100., 0.01, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons(&dcel);
+        assert_eq!(faces.len(), 2);
+        // the vertices of the point's cell off the box are as near both sites
+        let (_, ref cell) = faces[1];
+        let inner: Vec<Point> = cell.iter().cloned().filter(|pt| pt.y() > 0.).collect();
+        assert!(inner.len() > 10);
+        for &pt in &inner {
+            assert!((site_distance(sites[0].ends(), pt) - site_distance(sites[1].ends(), pt)).abs() < 1e-9, "{:?} is off the bisector", pt);
+        }
+        // the curve is the parabola y = 35 + (x - 50)^2 / 60 above the segment
+        assert!(inner.iter().any(|&pt| (pt - Point::new(50., 35.)).dot(pt - Point::new(50., 35.)) < 1.));
+        assert_segment_cells(&sites, 100., 0.01);
+    }
+
+    #[test]
+    fn points_give_voronoi_diagram() {
+        let points = vec![Point::new(1.0, 2.0), Point::new(7.0, 3.0), Point::new(4.0, 8.0), Point::new(5.5, 5.0)];
+        let faces = make_site_polygons(&segment_voronoi(points.iter().map(|&pt| Site::from(pt)).collect(), 10., 0.1, &VoronoiOptions::default()).unwrap().validated().0);
+        let expected = make_site_polygons(&::voronoi::voronoi(points, 10.));
+        assert_eq!(faces.len(), expected.len());
+        for &(site, ref poly) in &faces {
+            let other = &expected.iter().find(|&&(other_site, _)| other_site == site).unwrap().1;
+            assert!((signed_area(poly) - signed_area(other)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn two_segments() {
+        // parallel, with a straight edge midway between their insides
+        let sites = vec![Site::Segment(Point::new(20.0, 30.0), Point::new(80.0, 30.0)), Site::Segment(Point::new(80.0, 70.0), Point::new(20.0, 70.0))];
+        assert_segment_cells(&sites, 100., 0.01);
+        let faces = make_site_polygons(&segment_voronoi(sites, 100., 0.01, &VoronoiOptions::default()).unwrap().validated().0);
+        assert!(faces[0].1.iter().any(|&pt| pt == Point::new(20., 50.)) && faces[0].1.iter().any(|&pt| pt == Point::new(80., 50.)));
+
+        // at an angle, with one end near the inside of the other
+        let sites = vec![Site::Segment(Point::new(10.0, 10.0), Point::new(90.0, 40.0)), Site::Segment(Point::new(50.0, 45.0), Point::new(30.0, 90.0))];
+        assert_segment_cells(&sites, 100., 0.01);
+    }
+
+    // asserts that each location lies in a face of the site given with it
+    fn assert_owned(sites: &[Site], size: f64, owned: &[(Point, usize)]) {
+        let dcel = segment_voronoi(sites.to_vec(), size, 0.01, &VoronoiOptions::default()).unwrap().validated().0;
+        let faces = make_site_polygons_with_holes(&dcel);
+        for &(pt, site) in owned {
+            assert!(faces.iter().any(|face| face.0 == site && face_contains(face, pt)), "{:?} is not in a face of site {}", pt, site);
+        }
+    }
+
+    #[test]
+    fn polylines() {
+        // an L, whose arms split the corner outside them along the diagonal
+        let sites = vec![Site::Segment(Point::new(100.0, 100.0), Point::new(500.0, 100.0)), Site::Segment(Point::new(500.0, 100.0), Point::new(500.0, 500.0))];
+        assert_segment_cells(&sites, 1000., 0.01);
+        assert_owned(&sites, 1000., &[(Point::new(700., 50.), 1), (Point::new(550., 1.), 0), (Point::new(300., 150.), 0), (Point::new(600., 300.), 1)]);
+
+        // a closed square, split inside along its diagonals
+        let corners = [Point::new(300.0, 300.0), Point::new(700.0, 300.0), Point::new(700.0, 700.0), Point::new(300.0, 700.0)];
+        let sites: Vec<Site> = (0..4).map(|side| Site::Segment(corners[side], corners[(side + 1) % 4])).collect();
+        assert_segment_cells(&sites, 1000., 0.01);
+        assert_owned(&sites, 1000., &[(Point::new(500., 350.), 0), (Point::new(650., 500.), 1), (Point::new(800., 260.), 1), (Point::new(760., 200.), 0), (Point::new(250., 400.), 3)]);
+
+        // segments in line, split square to them
+        let sites = vec![Site::Segment(Point::new(100.0, 500.0), Point::new(500.0, 500.0)), Site::Segment(Point::new(900.0, 500.0), Point::new(500.0, 500.0))];
+        assert_segment_cells(&sites, 1000., 0.01);
+        assert_owned(&sites, 1000., &[(Point::new(499., 900.), 0), (Point::new(501., 100.), 1)]);
+
+        // three segments from one end
+        let sites = vec![
+            Site::Segment(Point::new(500.0, 500.0), Point::new(100.0, 500.0)),
+            Site::Segment(Point::new(500.0, 500.0), Point::new(900.0, 600.0)),
+            Site::Segment(Point::new(500.0, 500.0), Point::new(500.0, 900.0)),
+        ];
+        assert_segment_cells(&sites, 1000., 0.01);
+        assert_owned(&sites, 1000., &[(Point::new(400., 100.), 0), (Point::new(600., 100.), 1), (Point::new(200., 600.), 0), (Point::new(700., 800.), 2)]);
+    }
+
+    #[test]
+    fn random_polygons() {
+        // closed polylines around a center, at increasing angles
+        for seed in 0..10 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let count = 3 + seed % 6;
+            let corners: Vec<Point> = (0..count).map(|corner| {
+                let angle = (corner as f64 + rng.next_f64() * 0.8) * 2. * ::std::f64::consts::PI / count as f64;
+                let radius = 10. + rng.next_f64() * 30.;
+                Point::new(50. + radius * angle.cos(), 50. + radius * angle.sin())
+            }).collect();
+            let sites: Vec<Site> = (0..count).map(|corner| Site::Segment(corners[corner], corners[(corner + 1) % count])).collect();
+            assert_segment_cells(&sites, 100., 0.01);
+        }
+    }
+
+    #[test]
+    fn many_sites() {
+        // enough sites that most pairs are too far apart to be compared
+        let mut rng = StdRng::from_seed(&[3][..]);
+        let sites: Vec<Site> = (0..100).map(|place| {
+            let center = Point::new(((place % 10) as f64 + 0.2 + rng.next_f64() * 0.6) * 10., ((place / 10) as f64 + 0.2 + rng.next_f64() * 0.6) * 10.);
+            let angle = rng.next_f64() * 2. * ::std::f64::consts::PI;
+            let offset = Point::new(angle.cos(), angle.sin()) * 1.5;
+            if place % 3 == 0 { Site::Point(center) } else { Site::Segment(center - offset, center + offset) }
+        }).collect();
+        assert_segment_cells(&sites, 100., 0.01);
+    }
+
+    #[test]
+    fn invalid_input_rejected() {
+        let options = VoronoiOptions::default();
+        let crossing = vec![
+            Site::Point(Point::new(5.0, 5.0)),
+            Site::Segment(Point::new(1.0, 1.0), Point::new(9.0, 1.0)),
+            Site::Segment(Point::new(5.0, 0.0), Point::new(5.0, 3.0)),
+        ];
+        assert_eq!(segment_voronoi(crossing, 10., 0.1, &options).err(), Some(VoronoiError::IntersectingSites { first: 1, second: 2 }));
+        let touching = vec![Site::Segment(Point::new(1.0, 1.0), Point::new(9.0, 9.0)), Site::Point(Point::new(9.0, 9.0))];
+        assert_eq!(segment_voronoi(touching, 10., 0.1, &options).err(), Some(VoronoiError::IntersectingSites { first: 0, second: 1 }));
+        // segments may share an end, but not run on from it the same way
+        let overlapping = vec![Site::Segment(Point::new(1.0, 1.0), Point::new(5.0, 1.0)), Site::Segment(Point::new(5.0, 1.0), Point::new(3.0, 1.0))];
+        assert_eq!(segment_voronoi(overlapping, 10., 0.1, &options).err(), Some(VoronoiError::IntersectingSites { first: 0, second: 1 }));
+        let crossing_from_end = vec![Site::Segment(Point::new(1.0, 1.0), Point::new(5.0, 1.0)), Site::Segment(Point::new(5.0, 1.0), Point::new(3.0, 5.0)), Site::Segment(Point::new(2.0, 4.0), Point::new(5.0, 4.0))];
+        assert_eq!(segment_voronoi(crossing_from_end, 10., 0.1, &options).err(), Some(VoronoiError::IntersectingSites { first: 1, second: 2 }));
+        let sites = vec![Site::Point(Point::new(5.0, 5.0)), Site::Segment(Point::new(1.0, f64::NAN), Point::new(9.0, 1.0))];
+        assert_eq!(segment_voronoi(sites, 10., 0.1, &options).err(), Some(VoronoiError::NonFiniteCoordinate { index: 1 }));
+        for &tolerance in &[0., -1., f64::NAN, f64::INFINITY] {
+            let sites = vec![Site::Point(Point::new(5.0, 5.0))];
+            assert_eq!(segment_voronoi(sites, 10., tolerance, &options).err(), Some(VoronoiError::InvalidTolerance));
+        }
+    }
+
+    #[test]
+    fn duplicates_merged() {
+        let sites = vec![
+            Site::Segment(Point::new(1.0, 1.0), Point::new(4.0, 2.0)),
+            Site::Point(Point::new(7.0, 7.0)),
+            Site::Segment(Point::new(4.0, 2.0), Point::new(1.0, 1.0)),
+            Site::Point(Point::new(7.0, 7.0)),
+        ];
+        let (dcel, site_map) = segment_voronoi(sites.clone(), 10., 0.1, &VoronoiOptions::default()).unwrap();
+        assert_eq!(site_map, vec![0, 1, 0, 1]);
+        assert_eq!(make_site_polygons(&dcel).len(), 2);
+
+        let options = VoronoiOptions { duplicates: DuplicateSites::Reject, ..VoronoiOptions::default() };
+        assert_eq!(segment_voronoi(sites, 10., 0.1, &options).err(), Some(VoronoiError::DuplicateSite { first: 0, duplicate: 2 }));
+    }
+
+    #[test]
+    fn random_sites() {
+        for seed in 0..10 {
+            let mut rng = StdRng::from_seed(&[seed][..]);
+            let mut sites: Vec<Site> = vec![];
+            while sites.len() < 15 {
+                let start = Point::new(rng.next_f64() * 100., rng.next_f64() * 100.);
+                // a point for every two segments
+                let site = match sites.len() % 3 {
+                    0 => Site::Point(start),
+                    _ => Site::Segment(start, start + Point::new(rng.next_f64() * 40. - 20., rng.next_f64() * 40. - 20.)),
+                };
+                // keep the sites apart, as the distance between two segments is
+                // that from an end of one to the other
+                let ends = site.ends();
+                let apart = |other: &Site| {
+                    let other_ends = other.ends();
+                    ends.iter().all(|&pt| site_distance(other_ends, pt) > 0.5) && other_ends.iter().all(|&pt| site_distance(ends, pt) > 0.5)
+                        && !segments_touch(ends, other_ends)
+                };
+                if sites.iter().all(apart) { sites.push(site); }
+            }
+            assert_segment_cells(&sites, 100., 0.01);
+        }
+    }
+}
+
@@ -0,0 +1,52 @@
+// Checks shared by the tests of the diagram functions
+use point::Point;
+use dcel::{SitePolygonWithHoles, signed_area};
+use geometry::polygon_contains;
+
+// Locations on each side of the grid that `assert_grid_owned` checks
+const GRID_SIZE: usize = 40;
+
+// Whether a location lies in a face, and not in one of its holes
+pub fn face_contains(face: &SitePolygonWithHoles, pt: Point) -> bool {
+    polygon_contains(&face.1, pt) && !face.2.iter().any(|hole| polygon_contains(hole, pt))
+}
+
+// Asserts that the faces tile a square box of the given size. Holes are
+// clockwise, so their areas count against their faces.
+pub fn assert_fills_box(faces: &[SitePolygonWithHoles], size: f64) {
+    let total_area: f64 = faces.iter()
+        .map(|(_, poly, holes)| signed_area(poly) + holes.iter().map(|hole| signed_area(hole)).sum::<f64>())
+        .sum();
+    assert!((total_area - size * size).abs() < 1e-6 * size * size, "faces cover {} of {}", total_area, size * size);
+}
+
+// The site nearest a location, by its distance from each site, unless
+// another site is nearer than that by less than the margin
+pub fn clearly_nearest<F: Fn(usize) -> f64>(count: usize, distance: F, margin: f64) -> Option<usize> {
+    let (mut nearest, mut least, mut runner_up) = (0, f64::INFINITY, f64::INFINITY);
+    for index in 0..count {
+        let current = distance(index);
+        if current < least {
+            runner_up = least;
+            nearest = index;
+            least = current;
+        } else if current < runner_up {
+            runner_up = current;
+        }
+    }
+    if runner_up - least < margin { None } else { Some(nearest) }
+}
+
+// Asserts that each location on a grid over a square box lies in a face of
+// its owner, for the locations the owner function gives one
+pub fn assert_grid_owned<F: Fn(Point) -> Option<usize>>(faces: &[SitePolygonWithHoles], size: f64, owner: F) {
+    let step = size / GRID_SIZE as f64;
+    for col in 0..GRID_SIZE {
+        for row in 0..GRID_SIZE {
+            let pt = Point::new((col as f64 + 0.3) * step, (row as f64 + 0.6) * step);
+            if let Some(site) = owner(pt) {
+                assert!(faces.iter().any(|face| face.0 == site && face_contains(face, pt)), "{:?} is not in a face of site {}", pt, site);
+            }
+        }
+    }
+}
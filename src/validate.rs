@@ -0,0 +1,412 @@
+use std::fmt;
+use std::f64::consts::PI;
+use dcel::{DCEL, VertexId, HalfEdgeId};
+use predicates::orient2d;
+use point::Point;
+use bounding_box::BoundingBox;
+
+const NIL: usize = !0;
+// Allowed error in the total turn around a vertex
+const ANGLE_TOLERANCE: f64 = 1e-6;
+// Edges shorter than this, relative to the size of the diagram, have no
+// reliable direction
+const SHORT_EDGE: f64 = 1e-9;
+// Relative error allowed in the coordinates of a vertex: edges closer than
+// this, in direction or position, may be found in either order
+const ROUNDING: f64 = 1e-12;
+
+/// A way in which a DCEL is not a consistent subdivision of the plane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The twin of a halfedge is missing, removed, or not twinned back.
+    TwinMismatch(HalfEdgeId),
+    /// The previous halfedge of the next halfedge is not this halfedge.
+    NextPrevMismatch(HalfEdgeId),
+    /// Following next pointers from a halfedge does not lead back to it.
+    OpenCycle(HalfEdgeId),
+    /// A halfedge does not end where its next halfedge starts.
+    DisconnectedNext(HalfEdgeId),
+    /// A halfedge starts and ends at the same point.
+    ZeroLengthEdge(HalfEdgeId),
+    /// A halfedge is not in the face of the rest of its cycle, or a face's
+    /// components do not lie in the face.
+    FaceMismatch(HalfEdgeId),
+    /// The incident edge of a vertex does not start from it.
+    IncidentEdgeMismatch(VertexId),
+    /// The halfedges around a vertex are not all reached, or not in
+    /// clockwise order.
+    RotationMismatch(VertexId),
+    /// Two edges cross away from their endpoints.
+    EdgesCross(HalfEdgeId, HalfEdgeId),
+    /// The numbers of vertices, edges, faces and connected components do not
+    /// satisfy Euler's formula, V - E + F = 1 + C.
+    EulerMismatch {
+        /// Number of vertices, counting one at infinity if there are infinite edges
+        vertices: usize,
+        /// Number of edges
+        edges: usize,
+        /// Number of faces
+        faces: usize,
+        /// Number of connected components
+        components: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::TwinMismatch(edge) => write!(f, "halfedge {} has a bad twin", edge.0),
+            Violation::NextPrevMismatch(edge) => write!(f, "halfedge {} is not the previous halfedge of its next", edge.0),
+            Violation::OpenCycle(edge) => write!(f, "halfedge {} is not on a closed cycle", edge.0),
+            Violation::DisconnectedNext(edge) => write!(f, "halfedge {} does not end where its next starts", edge.0),
+            Violation::ZeroLengthEdge(edge) => write!(f, "halfedge {} has no length", edge.0),
+            Violation::FaceMismatch(edge) => write!(f, "halfedge {} is not in the face of its cycle", edge.0),
+            Violation::IncidentEdgeMismatch(vertex) => write!(f, "vertex {} has a bad incident edge", vertex.0),
+            Violation::RotationMismatch(vertex) => write!(f, "halfedges around vertex {} are out of order", vertex.0),
+            Violation::EdgesCross(first, second) => write!(f, "halfedges {} and {} cross", first.0, second.0),
+            Violation::EulerMismatch { vertices, edges, faces, components } => {
+                write!(f, "{} vertices, {} edges and {} faces do not make {} components", vertices, edges, faces, components)
+            }
+        }
+    }
+}
+
+impl DCEL {
+    /// Check that the DCEL is a consistent subdivision of the plane.
+    ///
+    /// Checks that twins and next and previous halfedges match, that edges
+    /// have length, that halfedges make closed cycles each in a single face,
+    /// that vertices point at their halfedges and order them clockwise, that
+    /// no edges cross, and that Euler's formula holds. Removed entries are
+    /// skipped; the face checks and Euler's formula are skipped until faces
+    /// have been added.
+    /// Returns the violations found, which is empty for a valid DCEL.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+        check_halfedges(self, &mut violations);
+        check_cycles(self, &mut violations);
+        check_vertices(self, &mut violations);
+        check_crossings(self, &mut violations);
+        if violations.is_empty() && self.face_ids().next().is_some() {
+            check_euler(self, &mut violations);
+        }
+        violations
+    }
+}
+
+fn is_live(dcel: &DCEL, edge: usize) -> bool {
+    edge < dcel.halfedges.len() && dcel.halfedges[edge].is_alive()
+}
+
+fn check_halfedges(dcel: &DCEL, violations: &mut Vec<Violation>) {
+    for edge in dcel.halfedge_ids() {
+        let twin = dcel.halfedges[edge.0].twin;
+        if twin == edge.0 || !is_live(dcel, twin) || dcel.halfedges[twin].twin != edge.0 {
+            violations.push(Violation::TwinMismatch(edge));
+            continue;
+        }
+        let next = dcel.halfedges[edge.0].next;
+        if !is_live(dcel, next) {
+            violations.push(Violation::OpenCycle(edge));
+            continue;
+        }
+        if dcel.prev(HalfEdgeId(next)) != Some(edge) {
+            violations.push(Violation::NextPrevMismatch(edge));
+        }
+        if dcel.halfedges[next].origin != dcel.halfedges[twin].origin {
+            violations.push(Violation::DisconnectedNext(edge));
+        }
+        if let (Some(start), Some(end)) = dcel.endpoints(edge) {
+            if dcel.position(start) == dcel.position(end) {
+                violations.push(Violation::ZeroLengthEdge(edge));
+            }
+        }
+    }
+}
+
+// every cycle closes, within as many steps as there are halfedges,
+// and lies in a single face
+fn check_cycles(dcel: &DCEL, violations: &mut Vec<Violation>) {
+    let mut seen = vec![false; dcel.halfedges.len()];
+    for start in dcel.halfedge_ids() {
+        if seen[start.0] { continue; }
+        let face = dcel.face(start);
+        let mut current = start.0;
+        let mut steps = 0;
+        loop {
+            seen[current] = true;
+            if dcel.face(HalfEdgeId(current)) != face {
+                violations.push(Violation::FaceMismatch(HalfEdgeId(current)));
+            }
+            current = dcel.halfedges[current].next;
+            steps += 1;
+            if current == start.0 { break; }
+            if !is_live(dcel, current) || seen[current] || steps > dcel.halfedges.len() {
+                violations.push(Violation::OpenCycle(start));
+                break;
+            }
+        }
+    }
+
+    for face in dcel.face_ids() {
+        let components = Some(dcel.outer_component(face)).into_iter().chain(dcel.inner_components(face));
+        for edge in components {
+            if !is_live(dcel, edge.0) || dcel.face(edge) != Some(face) {
+                violations.push(Violation::FaceMismatch(edge));
+            }
+        }
+    }
+}
+
+fn check_vertices(dcel: &DCEL, violations: &mut Vec<Violation>) {
+    let positions: Vec<Point> = dcel.vertex_ids().map(|vertex| dcel.position(vertex)).collect();
+    let min_length = if positions.is_empty() { 0. } else { BoundingBox::enclosing(&positions).diagonal() * SHORT_EDGE };
+
+    let mut degrees = vec![0; dcel.vertices.len()];
+    for edge in dcel.halfedge_ids() {
+        let origin = dcel.halfedges[edge.0].origin;
+        if origin != NIL { degrees[origin] += 1; }
+    }
+
+    for vertex in dcel.vertex_ids() {
+        if degrees[vertex.0] == 0 { continue; }
+        let incident_edge = dcel.vertices[vertex.0].incident_edge;
+        if !is_live(dcel, incident_edge) || dcel.halfedges[incident_edge].origin != vertex.0 {
+            violations.push(Violation::IncidentEdgeMismatch(vertex));
+            continue;
+        }
+
+        // walk clockwise around the vertex, adding up the turns
+        // between the edges, if they all have a direction
+        let mut total_turn = 0.;
+        let mut measurable = true;
+        let mut current = incident_edge;
+        let mut steps = 0;
+        loop {
+            let twin = dcel.halfedges[current].twin;
+            let next = dcel.halfedges[twin].next;
+            if !is_live(dcel, next) || dcel.halfedges[next].origin != vertex.0 || steps >= degrees[vertex.0] {
+                steps = NIL;
+                break;
+            }
+            match (edge_direction(dcel, current, min_length), edge_direction(dcel, next, min_length)) {
+                (Some(direction), Some(next_direction)) => {
+                    let mut turn = direction.y().atan2(direction.x()) - next_direction.y().atan2(next_direction.x());
+                    if turn <= 0. { turn += 2. * PI; }
+                    total_turn += turn;
+                    let blur = direction_error(dcel, current) + direction_error(dcel, next);
+                    if turn.min(2. * PI - turn) <= blur { measurable = false; }
+                }
+                _ => { measurable = false; }
+            }
+            current = next;
+            steps += 1;
+            if current == incident_edge { break; }
+        }
+        let wound_once = !measurable || (total_turn - 2. * PI).abs() < ANGLE_TOLERANCE;
+        if steps != degrees[vertex.0] || !wound_once {
+            violations.push(Violation::RotationMismatch(vertex));
+        }
+    }
+}
+
+// The vector along a finite halfedge that is not too short to have a direction
+fn edge_direction(dcel: &DCEL, edge: usize, min_length: f64) -> Option<Point> {
+    match dcel.endpoints(HalfEdgeId(edge)) {
+        (Some(start), Some(end)) => {
+            let direction = dcel.position(end) - dcel.position(start);
+            if direction.dot(direction).sqrt() > min_length { Some(direction) } else { None }
+        }
+        _ => None,
+    }
+}
+
+// The uncertainty, in radians, in the direction of a finite halfedge from the
+// rounding of its endpoints
+fn direction_error(dcel: &DCEL, edge: usize) -> f64 {
+    match dcel.endpoints(HalfEdgeId(edge)) {
+        (Some(start), Some(end)) => {
+            let (start, end) = (dcel.position(start), dcel.position(end));
+            let direction = end - start;
+            ROUNDING * (magnitude(start) + magnitude(end)) / direction.dot(direction).sqrt()
+        }
+        _ => 0.,
+    }
+}
+
+fn magnitude(pt: Point) -> f64 {
+    pt.x().abs().max(pt.y().abs())
+}
+
+// Bounded edges may only meet at their endpoints. Edges are compared only
+// if their ranges of x overlap.
+fn check_crossings(dcel: &DCEL, violations: &mut Vec<Violation>) {
+    let mut edges: Vec<(HalfEdgeId, [Point; 2])> = dcel.halfedge_ids()
+        .filter(|&edge| edge.0 < dcel.halfedges[edge.0].twin)
+        .filter_map(|edge| match dcel.endpoints(edge) {
+            (Some(start), Some(end)) => {
+                let (start, end) = (dcel.position(start), dcel.position(end));
+                Some((edge, if start.x() <= end.x() { [start, end] } else { [end, start] }))
+            }
+            _ => None,
+        })
+        .collect();
+    edges.sort_by(|a, b| a.1[0].x().total_cmp(&b.1[0].x()));
+
+    for (index, &(edge, [a, b])) in edges.iter().enumerate() {
+        for &(other, [c, d]) in &edges[index + 1..] {
+            if c.x() > b.x() { break; }
+            // each end must be clear of the other edge by more than the rounding
+            let blur = ROUNDING * [a, b, c, d].iter().fold(0., |most: f64, &pt| most.max(magnitude(pt)));
+            let (first, second) = (b - a, d - c);
+            let (first_blur, second_blur) = (blur * first.dot(first).sqrt(), blur * second.dot(second).sqrt());
+            let (side_c, side_d) = (orient2d(a, b, c), orient2d(a, b, d));
+            let (side_a, side_b) = (orient2d(c, d, a), orient2d(c, d, b));
+            let crosses = side_c * side_d < 0. && side_a * side_b < 0.
+                && side_c.abs().min(side_d.abs()) > first_blur && side_a.abs().min(side_b.abs()) > second_blur;
+            if crosses {
+                violations.push(Violation::EdgesCross(edge, other));
+            }
+        }
+    }
+}
+
+fn check_euler(dcel: &DCEL, violations: &mut Vec<Violation>) {
+    // the vertex at infinity, if any, comes after the real vertices
+    let infinity = dcel.vertices.len();
+    let mut parents: Vec<usize> = (0..infinity + 1).collect();
+    let mut vertex_used = vec![false; infinity + 1];
+    for vertex in dcel.vertex_ids() {
+        vertex_used[vertex.0] = true;
+    }
+
+    let mut num_halfedges = 0;
+    for edge in dcel.halfedge_ids() {
+        num_halfedges += 1;
+        let (start, end) = dcel.endpoints(edge);
+        let start = start.map_or(infinity, |vertex| vertex.0);
+        let end = end.map_or(infinity, |vertex| vertex.0);
+        vertex_used[start] = true;
+        vertex_used[end] = true;
+        let (start_root, end_root) = (find_root(&mut parents, start), find_root(&mut parents, end));
+        parents[start_root] = end_root;
+    }
+
+    let vertices = vertex_used.iter().filter(|&&used| used).count();
+    let components = (0..infinity + 1).filter(|&vertex| vertex_used[vertex] && find_root(&mut parents, vertex) == vertex).count();
+    let edges = num_halfedges / 2;
+    let faces = dcel.face_ids().count();
+    if vertices + faces != edges + 1 + components {
+        violations.push(Violation::EulerMismatch { vertices, edges, faces, components });
+    }
+}
+
+fn find_root(parents: &mut [usize], vertex: usize) -> usize {
+    let mut root = vertex;
+    while parents[root] != root { root = parents[root]; }
+    // compress the path
+    let mut current = vertex;
+    while parents[current] != root {
+        let next = parents[current];
+        parents[current] = root;
+        current = next;
+    }
+    root
+}
+
+// Asserts that a diagram built by a test is valid
+#[cfg(test)]
+pub fn assert_valid(dcel: &DCEL) {
+    let violations = dcel.validate();
+    assert!(violations.is_empty(), "invalid diagram: {:?}", violations);
+}
+
+// A diagram, or a result holding one, that a test checks with `assert_valid`
+// as it builds it
+#[cfg(test)]
+pub trait Validated {
+    fn validated(self) -> Self;
+}
+
+#[cfg(test)]
+impl Validated for DCEL {
+    fn validated(self) -> Self {
+        assert_valid(&self);
+        self
+    }
+}
+
+#[cfg(test)]
+impl<T> Validated for (DCEL, T) {
+    fn validated(self) -> Self {
+        assert_valid(&self.0);
+        self
+    }
+}
+
+#[cfg(test)]
+impl<T, U> Validated for (DCEL, T, U) {
+    fn validated(self) -> Self {
+        assert_valid(&self.0);
+        self
+    }
+}
+
+#[cfg(test)]
+impl<T: Validated, E> Validated for Result<T, E> {
+    fn validated(self) -> Self {
+        self.map(Validated::validated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voronoi::voronoi;
+
+    fn three_sites() -> DCEL {
+        voronoi(vec![Point::new(2.0, 1.0), Point::new(8.0, 3.0), Point::new(4.0, 8.0)], 10.)
+    }
+
+    #[test]
+    fn diagram_is_valid() {
+        assert_eq!(three_sites().validate(), vec![]);
+    }
+
+    #[test]
+    fn broken_twin_found() {
+        let mut dcel = three_sites();
+        dcel.halfedges[0].twin = 0;
+        assert!(dcel.validate().contains(&Violation::TwinMismatch(HalfEdgeId(0))));
+    }
+
+    #[test]
+    fn broken_next_found() {
+        let mut dcel = three_sites();
+        let next = dcel.halfedges[0].next;
+        dcel.halfedges[0].next = dcel.halfedges[next].next;
+        let violations = dcel.validate();
+        assert!(violations.contains(&Violation::NextPrevMismatch(HalfEdgeId(0))));
+        assert!(violations.contains(&Violation::DisconnectedNext(HalfEdgeId(0))));
+    }
+
+    #[test]
+    fn zero_length_edge_found() {
+        let mut dcel = three_sites();
+        let (start, end) = dcel.endpoints(HalfEdgeId(0));
+        let (start, end) = (start.unwrap(), end.unwrap());
+        dcel.vertices[end.0].coordinates = dcel.position(start);
+        assert!(dcel.validate().contains(&Violation::ZeroLengthEdge(HalfEdgeId(0))));
+    }
+
+    #[test]
+    fn moved_vertex_found() {
+        // moving a vertex across the box makes its edges cross others
+        let mut dcel = three_sites();
+        let vertex = dcel.vertex_ids()
+            .find(|&vertex| { let pt = dcel.position(vertex); pt.x() > 0. && pt.x() < 10. && pt.y() > 0. && pt.y() < 10. })
+            .unwrap();
+        dcel.vertices[vertex.0].coordinates = Point::new(20.0, 5.0);
+        assert!(dcel.validate().iter().any(|violation| matches!(*violation, Violation::EdgesCross(..) | Violation::RotationMismatch(..))));
+    }
+}
@@ -1,21 +1,364 @@
 use point::Point;
-use dcel::{DCEL, Vertex, add_line, add_faces};
+use dcel::{DCEL, Vertex, Ray, add_faces, bend_edge, contract_edge, left_site, set_left_site, label_enclosed_faces};
+use clip::{clip_to_convex_polygon, normalize_convex_polygon, clip_to_polygon_with_holes, normalize_polygon_with_holes};
 use beachline::*;
 use event::*;
 use geometry::*;
+use predicates::{incircle, circumcircle, orient2d};
+use error::VoronoiError;
+use bounding_box::BoundingBox;
 
+const NIL: usize = !0;
 type TripleSite = (Point, Point, Point);
+// sites to sweep, as (input index, point)
+type IndexedSites = Vec<(usize, Point)>;
+// the swept diagram, its infinite edges and its Delaunay triangles
+type SweepResult = (DCEL, Vec<Ray>, Vec<[usize; 3]>);
 
-/// Computes the Voronoi diagram of a set of points.
+
+// Relative distance below which two circle event centers are the same vertex
+const COCIRCULAR_TOLERANCE: f64 = 1e-9;
+// Relative distance within which breakpoints meet at the center of a touching circle
+const MEETING_TOLERANCE: f64 = 1e-6;
+// Relative difference in power within which an arc meets a waiting site at its contact
+const APPEARANCE_TOLERANCE: f64 = 1e-9;
+// A neighbor or site that the beachline must have is missing
+const BEACHLINE_BROKEN: VoronoiError = VoronoiError::InternalInconsistency("beachline is missing an expected node");
+
+/// How `voronoi_with_options` treats input sites with identical coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateSites {
+    /// Keep the first of the coincident sites and map the others onto it.
+    Merge,
+    /// Fail with `VoronoiError::DuplicateSite`.
+    Reject,
+}
+
+/// Options controlling the Voronoi computation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoronoiOptions {
+    /// Treatment of coincident input sites
+    pub duplicates: DuplicateSites,
+    /// Whether to compact the resulting DCEL (see `DCEL::compact`), so that
+    /// it holds no dead entries. On by default.
+    pub compact: bool,
+}
+
+impl Default for VoronoiOptions {
+    fn default() -> Self {
+        VoronoiOptions { duplicates: DuplicateSites::Merge, compact: true }
+    }
+}
+
+/// Computes the Voronoi diagram of a set of points, clipped to a bounding box.
 /// Returns a Doubly Connected Edge List.
-pub fn voronoi(points: Vec<Point>, boxsize: f64) -> DCEL {
+///
+/// The bounding box may be a `BoundingBox`, or a size `s` for the square `[0, s] x [0, s]`.
+/// Coincident points are merged into a single site.
+///
+/// # Panics
+///
+/// This function will panic if the bounding box has no area, or on any
+/// other error reported by `try_voronoi`.
+pub fn voronoi<B: Into<BoundingBox>>(points: Vec<Point>, bbox: B) -> DCEL {
+    try_voronoi(points, bbox).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Computes the Voronoi diagram of a set of points, clipped to a bounding box,
+/// reporting failures instead of panicking.
+///
+/// Fails if the bounding box has no area, if a site has a non-finite
+/// coordinate, if the input is too extreme to compute with, or if the
+/// computation goes wrong internally. Coincident points are merged.
+pub fn try_voronoi<B: Into<BoundingBox>>(points: Vec<Point>, bbox: B) -> Result<DCEL, VoronoiError> {
+    let (dcel, _) = voronoi_with_options(points, bbox, &VoronoiOptions::default())?;
+    Ok(dcel)
+}
+
+/// Computes the Voronoi diagram of a set of points, with options.
+///
+/// Returns the Doubly Connected Edge List, together with a vector mapping
+/// the index of every input point to the index of the input point whose
+/// site represents it in the diagram. Without duplicates this is the identity.
+///
+/// Points with a NaN or infinite coordinate are refused with
+/// `VoronoiError::NonFiniteCoordinate`, naming the first such point.
+pub fn voronoi_with_options<B: Into<BoundingBox>>(points: Vec<Point>, bbox: B, options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let bbox = bbox.into();
+    if !bbox.is_valid() {
+        return Err(VoronoiError::InvalidBoundingBox);
+    }
+    voronoi_in_region(points, &bbox.corners(), options)
+}
+
+/// Computes the Voronoi diagram of a set of points, clipped to a convex polygon.
+///
+/// The polygon may be given in either orientation. The faces of the result
+/// exactly tile the polygon; sites outside it may still have a face if their
+/// cell reaches into the polygon. The second element of the result maps input
+/// indices to sites, as in `voronoi_with_options`.
+pub fn voronoi_in_convex_polygon(points: Vec<Point>, polygon: &[Point], options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let polygon = normalize_convex_polygon(polygon).ok_or(VoronoiError::InvalidClipPolygon)?;
+    voronoi_in_region(points, &polygon, options)
+}
+
+/// Computes the Voronoi diagram of a set of points, clipped to a polygon with holes.
+///
+/// The outer boundary and the holes may be given in either orientation, but
+/// must not touch or cross each other. The faces of the result are the pieces
+/// of each cell within the polygon: a site may have several faces, and a face
+/// may have holes (see `make_site_polygons_with_holes`). The holes and the
+/// outside of the polygon are faces without a site. The second element of the
+/// result maps input indices to sites, as in `voronoi_with_options`.
+pub fn voronoi_in_polygon(points: Vec<Point>, outer: &[Point], holes: &[Vec<Point>], options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let (outer, holes) = normalize_polygon_with_holes(outer, holes).ok_or(VoronoiError::InvalidClipPolygon)?;
+    let (sites, site_map) = prepare_sites(points, options)?;
+
+    // first clip to a box around the polygon, with a margin so that the
+    // rings of the polygon cross only Voronoi edges
+    let bounds = BoundingBox::enclosing(&outer);
+    let margin = Point::new(1., 1.) * (bounds.diagonal() / 8.);
+    let extent = BoundingBox::new(bounds.min - margin, bounds.max + margin);
+
+    let (mut dcel, rays, _) = sweep(&sites)?;
+    extend_edges(&extent, &rays, &mut dcel)?;
+    pull_in_far_ends(&extent, &mut dcel, &sites, &[])?;
+    clip_to_convex_polygon(&mut dcel, &extent.corners())?;
+    clip_to_polygon_with_holes(&mut dcel, &outer, &holes, &sites)?;
+    add_faces(&mut dcel)?;
+    if options.compact { dcel.compact(); }
+    Ok((dcel, site_map))
+}
+
+/// Computes the unbounded Voronoi diagram of a set of points.
+///
+/// Returns the Doubly Connected Edge List, the infinite edges as rays in
+/// counterclockwise order around the diagram, and the map from input indices
+/// to sites, as in `voronoi_with_options`. The halfedge running out along
+/// each ray is followed by the halfedge coming back along the next ray, so
+/// that every face is a closed cycle. Only bounded cells have polygons.
+/// A diagram of a single site has no edges, and so no faces.
+pub fn voronoi_unbounded(points: Vec<Point>, options: &VoronoiOptions) -> Result<(DCEL, Vec<Ray>, Vec<usize>), VoronoiError> {
+    let (sites, site_map) = prepare_sites(points, options)?;
+
+    let (mut dcel, mut rays, _) = sweep(&sites)?;
+    for (index, ray) in rays.iter().enumerate() {
+        let next_ray = rays[(index + 1) % rays.len()];
+        let next_edge = dcel.halfedges[next_ray.halfedge].twin;
+        dcel.halfedges[ray.halfedge].next = next_edge;
+    }
+    add_faces(&mut dcel)?;
+    if options.compact {
+        let halfedge_map = dcel.compact();
+        for ray in &mut rays {
+            ray.halfedge = halfedge_map[ray.halfedge].ok_or(VoronoiError::InternalInconsistency("ray along a removed halfedge"))?;
+        }
+    }
+    Ok((dcel, rays, site_map))
+}
+
+// the region is a convex polygon in counterclockwise order
+fn voronoi_in_region(points: Vec<Point>, region: &[Point], options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let (sites, site_map) = prepare_sites(points, options)?;
+    let (dcel, _) = power_in_region(&sites, &[], region, options)?;
+    Ok((dcel, site_map))
+}
+
+// The power diagram of the sites, with weights as for `power_sweep`, clipped to
+// a convex polygon in counterclockwise order. Also returns the sites whose cells are empty.
+pub fn power_in_region(sites: &[(usize, Point)], weights: &[f64], region: &[Point], options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let ((mut dcel, rays, _), empty) = power_sweep(sites, weights)?;
+    let bbox = BoundingBox::enclosing(region);
+    extend_edges(&bbox, &rays, &mut dcel)?;
+    pull_in_far_ends(&bbox, &mut dcel, sites, weights)?;
+    clip_to_convex_polygon(&mut dcel, region)?;
+    add_faces(&mut dcel)?;
+    label_enclosed_faces(&mut dcel, sites, weights, Weighting::Power)?;
+    if options.compact { dcel.compact(); }
+    Ok((dcel, empty))
+}
+
+// The additively weighted Voronoi diagram of the sites, with weights as for
+// `additive_sweep`, clipped to a convex polygon in counterclockwise order. Its edges
+// follow the hyperbolas between the sites to within `tolerance`. Also returns
+// the sites whose cells are empty.
+pub fn additive_in_region(sites: &[(usize, Point)], weights: &[f64], region: &[Point], tolerance: f64, options: &VoronoiOptions) -> Result<(DCEL, Vec<usize>), VoronoiError> {
+    let ((mut dcel, rays, _), empty) = additive_sweep(sites, weights)?;
+    let mut positions = vec![Point::new(0., 0.); weights.len()];
+    for &(index, pt) in sites { positions[index] = pt; }
+    extend_hyperbolas(&BoundingBox::enclosing(region), &rays, &mut dcel, &positions, weights)?;
+    bend_hyperbolas(&mut dcel, &positions, weights, tolerance)?;
+    clip_to_convex_polygon(&mut dcel, region)?;
+    add_faces(&mut dcel)?;
+    label_enclosed_faces(&mut dcel, sites, weights, Weighting::Additive)?;
+    if options.compact { dcel.compact(); }
+    Ok((dcel, empty))
+}
+
+// Returns the sites to sweep, as (input index, point), and the map from input indices to sites
+pub fn prepare_sites(points: Vec<Point>, options: &VoronoiOptions) -> Result<(IndexedSites, Vec<usize>), VoronoiError> {
+    if let Some(index) = points.iter().position(|pt| !pt.x().is_finite() || !pt.y().is_finite()) {
+        return Err(VoronoiError::NonFiniteCoordinate { index });
+    }
+    let site_map = find_duplicate_sites(&points);
+    if options.duplicates == DuplicateSites::Reject {
+        if let Some((duplicate, &first)) = site_map.iter().enumerate().find(|&(index, &first)| index != first) {
+            return Err(VoronoiError::DuplicateSite { first, duplicate });
+        }
+    }
+
+    let sites = points.into_iter().enumerate()
+        .filter(|&(index, _)| site_map[index] == index)
+        .collect();
+    Ok((sites, site_map))
+}
+
+// Returns the sites to sweep and their weights by input index, as
+// `prepare_sites` does for points. The heaviest of each set of coincident points
+// (the first of them, on a tie) represents it, and the input indices of the
+// others, whose cells are empty, come third.
+pub fn prepare_weighted_sites(sites: Vec<(Point, f64)>, options: &VoronoiOptions) -> Result<(IndexedSites, Vec<f64>, Vec<usize>), VoronoiError> {
+    let (points, weights): (Vec<Point>, Vec<f64>) = sites.into_iter().unzip();
+    if let Some(index) = weights.iter().position(|weight| !weight.is_finite()) {
+        return Err(VoronoiError::NonFiniteWeight { index });
+    }
+    let (sites, site_map) = prepare_sites(points, options)?;
+
+    let mut heaviest = site_map.clone();
+    for (index, &first) in site_map.iter().enumerate() {
+        if weights[index] > weights[heaviest[first]] { heaviest[first] = index; }
+    }
+    let sites = sites.into_iter().map(|(first, pt)| (heaviest[first], pt)).collect();
+    let hidden = (0..site_map.len()).filter(|&index| heaviest[site_map[index]] != index).collect();
+    Ok((sites, weights, hidden))
+}
+
+// For each point, the index of the first point with the same coordinates
+fn find_duplicate_sites(points: &[Point]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|&a, &b| points[a].cmp(&points[b]).then(a.cmp(&b)));
+
+    let mut site_map: Vec<usize> = (0..points.len()).collect();
+    for pair in order.windows(2) {
+        if points[pair[0]] == points[pair[1]] {
+            site_map[pair[1]] = site_map[pair[0]];
+        }
+    }
+    site_map
+}
+
+// Runs Fortune's algorithm. The infinite edges are returned as rays, in
+// counterclockwise order, and the halfedges coming back along them have no origin.
+// Also returns the Delaunay triangles found by the circle events, as clockwise
+// triples of site indices. sites are given as (input index, point)
+pub fn sweep(sites: &[(usize, Point)]) -> Result<SweepResult, VoronoiError> {
+    let (result, _) = power_sweep(sites, &[])?;
+    Ok(result)
+}
+
+// The sites of a power diagram by input index, and the progress of the
+// sites whose arcs have yet to reach the beachline
+struct PowerSites<'a> {
+    // amounts added to the squared distances to the sites, none negative
+    weights: &'a [f64],
+    positions: Vec<Point>,
+    // for each waiting site, the sites found so far to bound its cell
+    competitors: Vec<Vec<usize>>,
+    waiting: Vec<bool>,
+    // the waiting sites whose contacts are at the height of the sweep line
+    appearing: Vec<usize>,
+}
+
+// Runs the sweep for the power diagram of the sites, as `sweep` does for the
+// Voronoi diagram. weights holds, by input index, the amount added to the
+// squared distance to each site; it is empty for a Voronoi diagram. A site
+// with a positive weight gets an arc only once the beachline reaches its cell,
+// so it waits for its contact with the beachline instead of splitting the arc
+// above it. Also returns the sites whose cells are empty, in no particular order.
+pub fn power_sweep(sites: &[(usize, Point)], weights: &[f64]) -> Result<(SweepResult, Vec<usize>), VoronoiError> {
     trace!("Starting Voronoi Computation");
     let mut event_queue = EventQueue::new();
     let mut beachline = BeachLine::new();
-    for pt in points {
-        event_queue.push(Event::Site { 0: pt });
+    let mut power = PowerSites { weights, positions: vec![Point::new(0., 0.); weights.len()], competitors: vec![vec![]; weights.len()], waiting: vec![false; weights.len()], appearing: vec![] };
+
+    // the sites of the top row are all on the beachline at once, as strips
+    let top = sites.iter().map(|&(_, pt)| pt.y()).fold(f64::NEG_INFINITY, f64::max);
+    let mut empty = if weights.is_empty() { vec![] } else { hidden_top_sites(sites, top, weights) };
+    for &(index, pt) in sites {
+        if let Some(position) = power.positions.get_mut(index) { *position = pt; }
+        if !empty.contains(&index) { event_queue.push(Event::Site(pt, index)); }
+    }
+    let mut result = DCEL::new();
+    let mut triangles = vec![];
+
+    while let Some(this_event) = event_queue.pop() {
+        trace!("\n\n");
+        trace!("Beachline: {:?}", beachline);
+        trace!("Queue: {:?}", event_queue);
+        trace!("Popped event from queue: {:?}", this_event);
+
+        match this_event {
+            Event::Site(pt, index) if pt.y() < top && site_weight(weights, index) > 0. => {
+                let arc_above = beachline.get_arc_above(pt)?;
+                power.competitors[index].push(beachline.get_site_index(arc_above)?);
+                power.waiting[index] = true;
+                handle_appearance(pt, index, pt.y(), &mut event_queue, &mut beachline, &mut result, &mut power)?;
+            }
+            Event::Site(pt, index) => {
+                handle_site_event(pt, index, pt.y(), &mut event_queue, &mut beachline, &mut result, weights)?;
+            }
+            Event::Circle(center, height, _, leaf, _) => {
+                handle_circle_event(leaf, center, height, &mut event_queue, &mut beachline, &mut result, &mut triangles, weights)?;
+            }
+            Event::Appearance(pt, height, index) => {
+                // sites that reach the beachline at the same point may bound
+                // each other's cells, though none has an arc yet
+                power.appearing = vec![index];
+                let mut tied = vec![];
+                while let Some(&Event::Appearance(_, next_height, next_index)) = event_queue.peek() {
+                    if next_height < height - APPEARANCE_TOLERANCE * height.abs() { break; }
+                    power.appearing.push(next_index);
+                    tied.extend(event_queue.pop());
+                }
+                handle_appearance(pt, index, height, &mut event_queue, &mut beachline, &mut result, &mut power)?;
+                // the others wait for any vertices the arc makes at the same height
+                for event in tied { event_queue.push(event); }
+            }
+        }
+    }
+    let rays = if beachline.is_empty() { vec![] } else { get_rays(&beachline, &result)? };
+
+    // the sites still waiting never reached the beachline
+    empty.extend(power.waiting.iter().enumerate().filter(|&(_, &waiting)| waiting).map(|(index, _)| index));
+    Ok(((result, rays, triangles), empty))
+}
+
+// Runs the sweep for the additively weighted Voronoi diagram of the sites, in
+// which the distance to each site is reduced by its weight; weights holds them
+// by input index. The arc of a site is that of a Voronoi site over a sweep line
+// lowered by the weight, so it reaches the beachline when the sweep line is the
+// weight above the site. The cell of a site is empty if its circle, with the
+// weight for radius, lies within that of another site; the arc above it is then of
+// such a site, and the site is left out. Also returns the sites whose cells are empty.
+// The edges run straight between their vertices.
+pub fn additive_sweep(sites: &[(usize, Point)], weights: &[f64]) -> Result<(SweepResult, Vec<usize>), VoronoiError> {
+    trace!("Starting additively weighted Voronoi Computation");
+    let mut event_queue = EventQueue::new();
+    let mut beachline = BeachLine::new();
+    beachline.weighting = Weighting::Additive;
+
+    // each site is queued at the height at which its arc starts, and the
+    // sites of the top row are still swept from left to right
+    let mut positions = vec![Point::new(0., 0.); weights.len()];
+    for &(index, pt) in sites {
+        positions[index] = pt;
+        let start = pt.y() + weights[index];
+        if !start.is_finite() { return Err(VoronoiError::DegenerateInput); }
+        event_queue.push(Event::Site(Point::new(pt.x(), start), index));
     }
     let mut result = DCEL::new();
+    let mut triangles = vec![];
+    let mut empty = vec![];
 
     while let Some(this_event) = event_queue.pop() {
         trace!("\n\n");
@@ -24,53 +367,130 @@ pub fn voronoi(points: Vec<Point>, boxsize: f64) -> DCEL {
         trace!("Popped event from queue: {:?}", this_event);
 
         match this_event {
-            Event::Site(pt) => {
-                handle_site_event(pt, &mut event_queue,  &mut beachline, &mut result);
+            Event::Site(start, index) => {
+                let site = positions[index];
+                if !beachline.is_empty() {
+                    let arc_above = beachline.get_arc_above(start)?;
+                    let covering = beachline.get_site_index(arc_above)?;
+                    if weighted_distance(Weighting::Additive, positions[covering], weights[covering], site) <= -weights[index] {
+                        trace!("Site {} lies within the circle of site {}", index, covering);
+                        empty.push(index);
+                        continue;
+                    }
+                }
+                handle_site_event(site, index, start.y(), &mut event_queue, &mut beachline, &mut result, weights)?;
+            }
+            Event::Circle(center, height, _, leaf, _) => {
+                handle_circle_event(leaf, center, height, &mut event_queue, &mut beachline, &mut result, &mut triangles, weights)?;
             }
-            Event::Circle(center, _, leaf, _) => {
-                handle_circle_event(leaf, center, &mut event_queue, &mut beachline, &mut result);
+            Event::Appearance(..) => {
+                return Err(VoronoiError::InternalInconsistency("additively weighted sites do not wait"));
             }
         }
     }
-    add_bounding_box(boxsize, &beachline, &mut result);
-    add_faces(&mut result);
-    return result;
+    let rays = if beachline.is_empty() { vec![] } else { get_rays(&beachline, &result)? };
+    Ok(((result, rays, triangles), empty))
+}
+
+// The amount added to the squared distance to a site, zero for a Voronoi diagram
+fn site_weight(weights: &[f64], index: usize) -> f64 {
+    weights.get(index).cloned().unwrap_or(0.)
 }
 
-fn handle_site_event(site: Point, queue: &mut EventQueue, beachline: &mut BeachLine, result: &mut DCEL) {
+fn arc_weight(beachline: &BeachLine, arc: usize, weights: &[f64]) -> Result<f64, VoronoiError> {
+    Ok(site_weight(weights, beachline.get_site_index(arc)?))
+}
+
+// The sites of the top row whose cells are empty. Along the row, the power
+// distance to each site is x^2 plus a line in x, and the cells are the strips
+// below the pieces of the lower envelope of the lines. The sites of the row
+// are distinct, and their lines are steeper the further left the site.
+fn hidden_top_sites(sites: &[(usize, Point)], top: f64, weights: &[f64]) -> Vec<usize> {
+    let mut row: Vec<(usize, Point)> = sites.iter().filter(|&&(_, pt)| pt.y() == top).cloned().collect();
+    row.sort_by(|a, b| a.1.x().total_cmp(&b.1.x()));
+    let line = |&(index, pt): &(usize, Point)| (-2. * pt.x(), pt.x() * pt.x() + site_weight(weights, index));
+
+    let mut envelope: Vec<(usize, Point)> = vec![];
+    let mut hidden = vec![];
+    for site in row {
+        let (slope, height) = line(&site);
+        while envelope.len() >= 2 {
+            let (slope_a, height_a) = line(&envelope[envelope.len() - 2]);
+            let (slope_b, height_b) = line(&envelope[envelope.len() - 1]);
+            // the last line is hidden if the new one passes below the one
+            // before it no further right than the last line does
+            if (height - height_a) * (slope_a - slope_b) > (height_b - height_a) * (slope_a - slope) { break; }
+            if let Some((index, _)) = envelope.pop() { hidden.push(index); }
+        }
+        envelope.push(site);
+    }
+    hidden
+}
+
+// The sweep line is at `sweep_y`: at the site, unless weights make its arc start elsewhere.
+#[allow(clippy::too_many_arguments)]
+fn handle_site_event(site: Point, site_index: usize, sweep_y: f64, queue: &mut EventQueue, beachline: &mut BeachLine, result: &mut DCEL, weights: &[f64]) -> Result<(), VoronoiError> {
     trace!("Handling site event at {:?}", site);
     if beachline.is_empty() {
         trace!("Beachline was empty, inserting point.");
-        beachline.insert_point(site);
-        return;
+        beachline.insert_point(site, site_index);
+        return Ok(());
     }
 
-    let arc_above = beachline.get_arc_above(site);
+    let arc_above = beachline.get_arc_above(Point::new(site.x(), sweep_y))?;
 
     // remove false alarm from queue
     remove_circle_event(arc_above, queue, beachline);
 
-    let new_node = split_arc(arc_above, site, beachline, result);
+    let new_node = if arc_start(beachline, arc_above, weights)? == sweep_y {
+        // the sites of the top row come from left to right, so each goes to the
+        // right of the last, even where weights put the site below another strip
+        let last_arc = beachline.tree_maximum(beachline.root);
+        split_arc_horizontal(last_arc, site, site_index, beachline, result, weights)?
+    } else {
+        split_arc(arc_above, site, site_index, beachline, result, weights)?
+    };
+    check_triples_beside(new_node, sweep_y, queue, beachline, weights)
+}
+
+// The height of the sweep line at which the site of an arc reaches the beachline
+// as a vertical ray. In a power diagram, this is asked only of the top row.
+fn arc_start(beachline: &BeachLine, arc: usize, weights: &[f64]) -> Result<f64, VoronoiError> {
+    let site = beachline.get_site(Some(arc)).ok_or(BEACHLINE_BROKEN)?;
+    Ok(match beachline.weighting {
+        Weighting::Power | Weighting::Segments => site.y(),
+        Weighting::Additive => site.y() + arc_weight(beachline, arc, weights)?,
+    })
+}
+
+// Might the breakpoints on either side of the middle arc meet? For sites with
+// additive weights, this is found with the circles touching them.
+fn may_converge(triple: TripleSite, beachline: &BeachLine) -> bool {
+    beachline.weighting == Weighting::Additive || breakpoints_converge(triple)
+}
 
+// Look for circle events for the arcs on either side of a new arc
+fn check_triples_beside(new_node: usize, sweep_y: f64, queue: &mut EventQueue, beachline: &mut BeachLine, weights: &[f64]) -> Result<(), VoronoiError> {
     if let Some(left_triple) = beachline.get_leftward_triple(new_node) {
         trace!("Checking leftward triple {:?}, {:?}, {:?}", left_triple.0, left_triple.1, left_triple.2);
-        if breakpoints_converge(left_triple) {
+        if may_converge(left_triple, beachline) {
             trace!("Found converging triple");
-            let left_arc = beachline.get_left_arc(Some(new_node)).unwrap();
-            make_circle_event(left_arc, left_triple, queue, beachline);
+            let left_arc = beachline.get_left_arc(Some(new_node)).ok_or(BEACHLINE_BROKEN)?;
+            make_circle_event(left_arc, left_triple, sweep_y, queue, beachline, weights)?;
         }
     }
     if let Some(right_triple) = beachline.get_rightward_triple(new_node) {
         trace!("Checking rightward triple {:?}, {:?}, {:?}", right_triple.0, right_triple.1, right_triple.2);
-        if breakpoints_converge(right_triple) {
+        if may_converge(right_triple, beachline) {
             trace!("Found converging triple");
-            let right_arc = beachline.get_right_arc(Some(new_node)).unwrap();
-            make_circle_event(right_arc, right_triple, queue, beachline);
+            let right_arc = beachline.get_right_arc(Some(new_node)).ok_or(BEACHLINE_BROKEN)?;
+            make_circle_event(right_arc, right_triple, sweep_y, queue, beachline, weights)?;
         }
     }
+    Ok(())
 }
 
-fn remove_circle_event(this_arc: usize, queue: &mut EventQueue, beachline: &mut BeachLine) {
+pub fn remove_circle_event(this_arc: usize, queue: &mut EventQueue, beachline: &mut BeachLine) {
     let mut circle_event = None;
     if let BeachItem::Leaf(ref mut arc) = beachline.nodes[this_arc].item {
         circle_event = arc.site_event;
@@ -81,134 +501,403 @@ fn remove_circle_event(this_arc: usize, queue: &mut EventQueue, beachline: &mut
     }
 }
 
-fn make_circle_event(leaf: usize, triple: TripleSite, queue: &mut EventQueue, beachline: &mut BeachLine) {
-    if let Some(circle_center) = circle_center(triple) {
-        let circle_bottom = circle_bottom(triple).unwrap();
-        let this_event = Event::Circle {0: circle_center, 1: circle_bottom.0 - circle_center.y(), 2: leaf, 3: 0};
+fn make_circle_event(leaf: usize, triple: TripleSite, sweep_y: f64, queue: &mut EventQueue, beachline: &mut BeachLine, weights: &[f64]) -> Result<(), VoronoiError> {
+    let triple_weights = if weights.is_empty() { (0., 0., 0.) } else {
+        let left_arc = beachline.get_left_arc(Some(leaf)).ok_or(BEACHLINE_BROKEN)?;
+        let right_arc = beachline.get_right_arc(Some(leaf)).ok_or(BEACHLINE_BROKEN)?;
+        (arc_weight(beachline, left_arc, weights)?, arc_weight(beachline, leaf, weights)?, arc_weight(beachline, right_arc, weights)?)
+    };
+    if beachline.weighting == Weighting::Additive {
+        return make_touching_circle_event(leaf, triple, triple_weights, sweep_y, queue, beachline);
+    }
+    // the circle through the sites themselves is found accurately and ordered exactly
+    let (circle, sites) = if triple_weights == (0., 0., 0.) {
+        (circumcircle(triple.0, triple.1, triple.2), Some(triple))
+    } else {
+        (circle_center(triple, triple_weights).and_then(|center| circle_bottom(triple, triple_weights).map(|bottom| (center, bottom.0))), None)
+    };
+    if let Some((circle_center, circle_bottom)) = circle {
+        // the circle through sites of huge magnitude may overflow
+        if !circle_bottom.is_finite() { return Err(VoronoiError::DegenerateInput); }
+        // in a power diagram, breakpoints that turn towards each other may
+        // have passed their meeting point before the arc between them began
+        let radius = circle_center.y() - circle_bottom;
+        if !weights.is_empty() && circle_bottom > sweep_y + COCIRCULAR_TOLERANCE * radius { return Ok(()); }
+        let this_event = Event::Circle(circle_center, circle_bottom, sites, leaf, 0);
         if let BeachItem::Leaf(ref mut arc) = beachline.nodes[leaf].item {
             arc.site_event = Some(queue.push(this_event));
         }
     }
+    Ok(())
+}
+
+// The arc of a site with an additive weight vanishes at the center of a circle
+// touching the circles around its site and those beside it, whose radii are the
+// weights, when the sweep line is as far below the center as the circle is large.
+// Of the two such circles, the event is at the first one still to come at which
+// the breakpoints on either side of the arc meet, and towards which they converge:
+// the circle touches the circles around the sites where the directions to them
+// from its center meet it, and these must make a right turn, as Voronoi sites do.
+fn make_touching_circle_event(leaf: usize, triple: TripleSite, triple_weights: TripleWeight, sweep_y: f64, queue: &mut EventQueue, beachline: &mut BeachLine) -> Result<(), VoronoiError> {
+    let (left, middle, right) = triple;
+    let (left_weight, middle_weight, right_weight) = triple_weights;
+    let mut first: Option<(Point, f64)> = None;
+    for (center, radius) in apollonius_circles(triple, triple_weights) {
+        let bottom = center.y() - radius;
+        if !bottom.is_finite() { return Err(VoronoiError::DegenerateInput); }
+        let scale = (center - middle).dot(center - middle).sqrt() + radius.abs();
+        if bottom > sweep_y + COCIRCULAR_TOLERANCE * scale { continue; }
+        let left_x = get_breakpoint_x(&BreakPoint::new((left, left_weight), (middle, middle_weight), NIL), bottom, Weighting::Additive);
+        let right_x = get_breakpoint_x(&BreakPoint::new((middle, middle_weight), (right, right_weight), NIL), bottom, Weighting::Additive);
+        let meets = |x: f64| (x - center.x()).abs() <= MEETING_TOLERANCE * scale;
+        let contact = |site: Point| center + (site - center) * (1. / (site - center).dot(site - center).sqrt());
+        if !breakpoints_converge((contact(left), contact(middle), contact(right))) { continue; }
+        if meets(left_x) && meets(right_x) && first.map(|(_, first_bottom)| bottom > first_bottom).unwrap_or(true) {
+            first = Some((center, bottom));
+        }
+    }
+    if let Some((center, bottom)) = first {
+        let this_event = Event::Circle(center, bottom, None, leaf, 0);
+        if let BeachItem::Leaf(ref mut arc) = beachline.nodes[leaf].item {
+            arc.site_event = Some(queue.push(this_event));
+        }
+    }
+    Ok(())
 }
 
 #[allow(non_snake_case)]
 // return: the index of the node for the new arc
-fn split_arc(arc: usize, pt: Point, beachline: &mut BeachLine, dcel: &mut DCEL) -> usize {
+pub fn split_arc(arc: usize, pt: Point, pt_index: usize, beachline: &mut BeachLine, dcel: &mut DCEL, weights: &[f64]) -> Result<usize, VoronoiError> {
     trace!("Splitting arc {}", arc);
     let parent = beachline.nodes[arc].parent;
 
-    let mut arc_pt = Point::new(0.0, 0.0);
-    if let BeachItem::Leaf(ref this_arc) = beachline.nodes[arc].item {
-        arc_pt = this_arc.site;
-    }
+    // the arc may be the side of a segment, whose end its pieces keep
+    let (arc_pt, arc_end) = beachline.get_site_end(arc)?;
+    let arc_index = beachline.get_site_index(arc)?;
+    let (arc_weight, pt_weight) = (site_weight(weights, arc_index), site_weight(weights, pt_index));
 
     let (twin1, twin2) = dcel.add_twins();
+    set_left_site(dcel, twin1, arc_index);
+    set_left_site(dcel, twin2, pt_index);
 
-    let breakpoint_AB = BreakPoint::new(arc_pt, pt, twin1);
-    let breakpoint_BA = BreakPoint::new(pt, arc_pt, twin2);
+    let breakpoint_AB = BreakPoint { left_end: arc_end, ..BreakPoint::new((arc_pt, arc_weight), (pt, pt_weight), twin1) };
+    let breakpoint_BA = BreakPoint { right_end: arc_end, ..BreakPoint::new((pt, pt_weight), (arc_pt, arc_weight), twin2) };
 
     let internal_AB = BeachItem::Internal(breakpoint_AB);
     let internal_BA = BeachItem::Internal(breakpoint_BA);
 
-    let arc_A1 = Arc::new(arc_pt, None);
-    let arc_A2 = Arc::new(arc_pt, None);
-    let arc_B = Arc::new(pt, None);
+    let arc_A1 = Arc { end: arc_end, ..Arc::new(arc_pt, arc_index, None) };
+    let arc_A2 = Arc { end: arc_end, ..Arc::new(arc_pt, arc_index, None) };
+    let arc_B = Arc::new(pt, pt_index, None);
 
     let leaf_A1 = BeachItem::Leaf(arc_A1);
     let leaf_A2 = BeachItem::Leaf(arc_A2);
     let leaf_B = BeachItem::Leaf(arc_B);
 
-    let ind_AB = beachline.nodes.len();
-    let ind_BA = ind_AB + 1;
-    let ind_A1 = ind_AB + 2;
-    let ind_B  = ind_AB + 3;
-    let ind_A2 = ind_AB + 4;
-
-    let node_AB = BeachNode { parent: parent, left_child: Some(ind_A1), right_child: Some(ind_BA), item: internal_AB};
-    beachline.nodes.push(node_AB);
-    if let Some(parent_ind) = parent {
-        let parent_node = &mut beachline.nodes[parent_ind];
-        if parent_node.right_child.is_some() && parent_node.right_child.unwrap() == arc {
-            parent_node.right_child = Some(ind_AB);
-        } else if parent_node.left_child.is_some() && parent_node.left_child.unwrap() == arc {
-            parent_node.left_child = Some(ind_AB);
-        } else {
-            panic!("tree is borked");
-        }
+    // the leaves first, then the breakpoints above them
+    let ind_A1 = beachline.add_node(BeachNode::make_arc(None, leaf_A1));
+    let ind_B = beachline.add_node(BeachNode::make_arc(None, leaf_B));
+    let ind_A2 = beachline.add_node(BeachNode::make_arc(None, leaf_A2));
+
+    let priority_BA = beachline.next_priority();
+    let node_BA = BeachNode { parent: None, left_child: Some(ind_B), right_child: Some(ind_A2), item: internal_BA, priority: priority_BA };
+    let ind_BA = beachline.add_node(node_BA);
+
+    let priority_AB = beachline.next_priority();
+    let node_AB = BeachNode { parent, left_child: Some(ind_A1), right_child: Some(ind_BA), item: internal_AB, priority: priority_AB };
+    let ind_AB = beachline.add_node(node_AB);
+
+    beachline.nodes[ind_A1].parent = Some(ind_AB);
+    beachline.nodes[ind_BA].parent = Some(ind_AB);
+    beachline.nodes[ind_B].parent = Some(ind_BA);
+    beachline.nodes[ind_A2].parent = Some(ind_BA);
+    beachline.replace_child(parent, arc, ind_AB)?;
+    beachline.free_node(arc);
+
+    // the lower breakpoint first, so that the upper one is rebalanced
+    // from wherever the lower one leaves it
+    beachline.rebalance(ind_BA)?;
+    beachline.rebalance(ind_AB)?;
+
+    Ok(ind_B)
+}
+
+#[allow(non_snake_case)]
+// Splits an arc whose site lies at the same height as the new site.
+// This happens when the first sites of the sweep share a y coordinate:
+// the arc above is still a vertical ray, so the new arc goes beside it,
+// separated by a vertical bisector that runs upward to infinity.
+// return: the index of the node for the new arc
+pub fn split_arc_horizontal(arc: usize, pt: Point, pt_index: usize, beachline: &mut BeachLine, dcel: &mut DCEL, weights: &[f64]) -> Result<usize, VoronoiError> {
+    trace!("Splitting arc {} horizontally", arc);
+    let parent = beachline.nodes[arc].parent;
+    let arc_pt = beachline.get_site(Some(arc)).ok_or(BEACHLINE_BROKEN)?;
+    let arc_index = beachline.get_site_index(arc)?;
+
+    let ((left_pt, left_index), (right_pt, right_index)) = if pt.x() < arc_pt.x() {
+        ((pt, pt_index), (arc_pt, arc_index))
     } else {
-        beachline.root = ind_AB;
+        ((arc_pt, arc_index), (pt, pt_index))
+    };
+
+    let (twin1, twin2) = dcel.add_twins();
+    set_left_site(dcel, twin1, left_index);
+    set_left_site(dcel, twin2, right_index);
+
+    // the upper end of the bisector is at infinity, and so twin2 never
+    // gets an origin; the lower end is fixed by a circle event, if any
+
+    let breakpoint_AB = BreakPoint::new((left_pt, site_weight(weights, left_index)), (right_pt, site_weight(weights, right_index)), twin1);
+
+    let ind_A = beachline.add_node(BeachNode::make_arc(None, BeachItem::Leaf(Arc::new(left_pt, left_index, None))));
+    let ind_B = beachline.add_node(BeachNode::make_arc(None, BeachItem::Leaf(Arc::new(right_pt, right_index, None))));
+
+    let priority = beachline.next_priority();
+    let node_AB = BeachNode { parent, left_child: Some(ind_A), right_child: Some(ind_B), item: BeachItem::Internal(breakpoint_AB), priority };
+    let ind_AB = beachline.add_node(node_AB);
+
+    beachline.nodes[ind_A].parent = Some(ind_AB);
+    beachline.nodes[ind_B].parent = Some(ind_AB);
+    beachline.replace_child(parent, arc, ind_AB)?;
+    beachline.free_node(arc);
+    beachline.rebalance(ind_AB)?;
+
+    Ok(if pt == left_pt { ind_A } else { ind_B })
+}
+
+// Looks for the contact of a waiting site with the beachline, now that the
+// sweep line is at `sweep_y`. The contact is sought among the sites found to
+// bound the cell of the site so far. If it is still to come, it is queued;
+// if it is now, and no arc there is of a site nearer in power, the arc of the
+// site is added there; otherwise that site joins the competitors, and the
+// search is repeated. A site whose cell turns out empty stays waiting.
+fn handle_appearance(site: Point, site_index: usize, sweep_y: f64, queue: &mut EventQueue, beachline: &mut BeachLine, dcel: &mut DCEL, power: &mut PowerSites) -> Result<(), VoronoiError> {
+    trace!("Looking for the contact of site {} with the beachline", site_index);
+    loop {
+        let competitors: Vec<(Point, f64)> = power.competitors[site_index].iter()
+            .map(|&index| (power.positions[index], power.weights[index]))
+            .collect();
+        let contact = match power_contact(site, power.weights[site_index], &competitors) {
+            Some(contact) => contact,
+            None => return Ok(()),
+        };
+        if !contact.height.is_finite() { return Err(VoronoiError::DegenerateInput); }
+        if contact.height < sweep_y {
+            queue.push(Event::Appearance(site, contact.height, site_index));
+            return Ok(());
+        }
+
+        let arc = beachline.get_arc_above(Point::new(contact.point.x(), sweep_y))?;
+        let arc_index = beachline.get_site_index(arc)?;
+        let power_from = |index: usize| {
+            let offset = contact.point - power.positions[index];
+            offset.dot(offset) + power.weights[index]
+        };
+        let site_power = power_from(site_index);
+        // the sites of the arcs that reach the contact, and those about to,
+        // if no nearer than the site, also bound its cell
+        let mut reaching = vec![arc_index];
+        reaching.extend(power.appearing.iter().cloned());
+        for breakpoint in breakpoints_near(arc, contact.point.x(), sweep_y, APPEARANCE_TOLERANCE * site_power.sqrt(), beachline)? {
+            let gap = (beachline.get_breakpoint_x(breakpoint, sweep_y)? - contact.point.x()).abs();
+            if gap <= APPEARANCE_TOLERANCE * site_power.sqrt() {
+                let beside = beachline.predecessor(breakpoint).into_iter().chain(beachline.successor(breakpoint));
+                for neighbor in beside { reaching.push(beachline.get_site_index(neighbor)?); }
+            }
+        }
+        let new_competitor = reaching.into_iter().find(|&index| {
+            index != site_index && !power.competitors[site_index].contains(&index) && power_from(index) <= site_power + APPEARANCE_TOLERANCE * site_power
+        });
+        if let Some(index) = new_competitor {
+            power.competitors[site_index].push(index);
+            continue;
+        }
+
+        let mut met: Vec<usize> = contact.competitors.iter().map(|&index| power.competitors[site_index][index]).collect();
+        // more sites may meet there, and any two of them be beside each other
+        let also: Vec<usize> = power.competitors[site_index].iter().cloned()
+            .filter(|&index| !met.contains(&index) && (power_from(index) - site_power).abs() <= APPEARANCE_TOLERANCE * site_power)
+            .collect();
+        met.extend(also);
+        power.waiting[site_index] = false;
+        return insert_appearing_arc(site, site_index, contact.point, &met, arc, sweep_y, queue, beachline, dcel, power.weights);
     }
+}
 
-    let node_BA = BeachNode {parent: Some(ind_AB), left_child: Some(ind_B), right_child: Some(ind_A2), item: internal_BA};
-    beachline.nodes.push(node_BA);
+// The breakpoints beside an arc, and beyond them those that the arcs between
+// leave within `reach` of x, as when arcs there have no width yet
+fn breakpoints_near(arc: usize, x: f64, sweep_y: f64, reach: f64, beachline: &BeachLine) -> Result<Vec<usize>, VoronoiError> {
+    let mut result = vec![];
+    for &leftward in &[true, false] {
+        let step = |node: usize| if leftward { beachline.predecessor(node) } else { beachline.successor(node) };
+        let mut next = step(arc);
+        while let Some(breakpoint) = next {
+            result.push(breakpoint);
+            if (beachline.get_breakpoint_x(breakpoint, sweep_y)? - x).abs() > reach { break; }
+            next = step(breakpoint).and_then(step);
+        }
+    }
+    Ok(result)
+}
 
-    let node_A1 = BeachNode::make_arc(Some(ind_AB), leaf_A1);
-    beachline.nodes.push(node_A1);
+// Adds the arc of a site that has just reached the beachline at `contact`:
+// at the breakpoint between two of the sites met there, if it is a corner of
+// the cell and the breakpoint is beside `arc` or reaches the contact, and
+// otherwise in the middle of `arc`. An arc between two arcs of the same site
+// has such a breakpoint on both sides, and the one nearer the contact is taken.
+#[allow(clippy::too_many_arguments)]
+fn insert_appearing_arc(site: Point, site_index: usize, contact: Point, met: &[usize], arc: usize, sweep_y: f64, queue: &mut EventQueue, beachline: &mut BeachLine, dcel: &mut DCEL, weights: &[f64]) -> Result<(), VoronoiError> {
+    let mut corner = None;
+    let mut nearest = f64::INFINITY;
+    if met.len() >= 2 {
+        let site_power = (contact - site).dot(contact - site) + site_weight(weights, site_index);
+        for breakpoint in breakpoints_near(arc, contact.x(), sweep_y, APPEARANCE_TOLERANCE * site_power.sqrt(), beachline)? {
+            let left = beachline.predecessor(breakpoint).ok_or(BEACHLINE_BROKEN)?;
+            let right = beachline.successor(breakpoint).ok_or(BEACHLINE_BROKEN)?;
+            let sides = [beachline.get_site_index(left)?, beachline.get_site_index(right)?];
+            let distance = (beachline.get_breakpoint_x(breakpoint, sweep_y)? - contact.x()).abs();
+            // where more sites meet, the new arc goes where its site comes
+            // between theirs in counterclockwise order, as around a Delaunay face
+            let (left_pt, right_pt) = (beachline.get_site(Some(left)).ok_or(BEACHLINE_BROKEN)?, beachline.get_site(Some(right)).ok_or(BEACHLINE_BROKEN)?);
+            if sides.iter().all(|side| met.contains(side)) && orient2d(left_pt, site, right_pt) > 0. && distance < nearest {
+                corner = Some((breakpoint, left, right));
+                nearest = distance;
+            }
+        }
+    }
+
+    let new_node = if let Some((breakpoint, left, right)) = corner {
+        trace!("Site {} reaches the beachline at breakpoint {}", site_index, breakpoint);
+        remove_circle_event(left, queue, beachline);
+        remove_circle_event(right, queue, beachline);
+        split_breakpoint(breakpoint, site, site_index, contact, beachline, dcel, weights)?
+    } else {
+        trace!("Site {} reaches the beachline in arc {}", site_index, arc);
+        remove_circle_event(arc, queue, beachline);
+        split_arc(arc, site, site_index, beachline, dcel, weights)?
+    };
+    check_triples_beside(new_node, sweep_y, queue, beachline, weights)
+}
+
+#[allow(non_snake_case)]
+// Puts a new arc at a breakpoint, which ends there. The edge the breakpoint
+// traced ends at a new vertex, where the edges between the new arc and the
+// arcs on either side begin.
+// return: the index of the node for the new arc
+fn split_breakpoint(breakpoint: usize, pt: Point, pt_index: usize, vertex_pt: Point, beachline: &mut BeachLine, dcel: &mut DCEL, weights: &[f64]) -> Result<usize, VoronoiError> {
+    let left_arc = beachline.predecessor(breakpoint).ok_or(BEACHLINE_BROKEN)?;
+    let right_arc = beachline.successor(breakpoint).ok_or(BEACHLINE_BROKEN)?;
+    let (left_index, right_index) = (beachline.get_site_index(left_arc)?, beachline.get_site_index(right_arc)?);
+    let left_pt = beachline.get_site(Some(left_arc)).ok_or(BEACHLINE_BROKEN)?;
+    let right_pt = beachline.get_site(Some(right_arc)).ok_or(BEACHLINE_BROKEN)?;
+    let (left_weight, pt_weight, right_weight) = (site_weight(weights, left_index), site_weight(weights, pt_index), site_weight(weights, right_index));
+
+    let old_edge = beachline.get_edge(breakpoint)?;
+    let old_twin = dcel.halfedges[old_edge].twin;
+    let vertex = dcel.vertices.len();
+    dcel.vertices.push(Vertex { coordinates: vertex_pt, incident_edge: old_edge, alive: true });
+
+    let (edge_AB, twin_AB) = dcel.add_twins();
+    set_left_site(dcel, edge_AB, left_index);
+    set_left_site(dcel, twin_AB, pt_index);
+    let (edge_BC, twin_BC) = dcel.add_twins();
+    set_left_site(dcel, edge_BC, pt_index);
+    set_left_site(dcel, twin_BC, right_index);
 
-    let node_B = BeachNode::make_arc(Some(ind_BA), leaf_B);
-    beachline.nodes.push(node_B);
+    dcel.halfedges[old_edge].origin = vertex;
+    dcel.halfedges[twin_AB].origin = vertex;
+    dcel.halfedges[twin_BC].origin = vertex;
+    dcel.halfedges[edge_AB].next = old_edge;
+    dcel.halfedges[edge_BC].next = twin_AB;
+    dcel.halfedges[old_twin].next = twin_BC;
+
+    // the breakpoint may have only just begun, at a vertex where the cell of
+    // the new site meets its neighbors, so its edge has no length
+    if dcel.halfedges[old_twin].origin != NIL {
+        let start = dcel.get_origin(old_twin);
+        let scale = [left_pt, pt, right_pt].iter().fold(0., |most: f64, &site| most.max((site - vertex_pt).dot(site - vertex_pt).sqrt()));
+        if (start - vertex_pt).dot(start - vertex_pt).sqrt() <= COCIRCULAR_TOLERANCE * scale {
+            trace!("Merging the vertex where site {} appears along halfedge {}", pt_index, old_edge);
+            contract_edge(dcel, old_edge)?;
+        }
+    }
 
-    let node_A2 = BeachNode::make_arc(Some(ind_BA), leaf_A2);
-    beachline.nodes.push(node_A2);
+    // the breakpoint now separates the left arc from the new one, and a new
+    // breakpoint above the right arc separates the new arc from it
+    beachline.nodes[breakpoint].item = BeachItem::Internal(BreakPoint::new((left_pt, left_weight), (pt, pt_weight), edge_AB));
+    let parent = beachline.nodes[right_arc].parent;
+    let ind_B = beachline.add_node(BeachNode::make_arc(None, BeachItem::Leaf(Arc::new(pt, pt_index, None))));
+    let priority = beachline.next_priority();
+    let internal_BC = BeachItem::Internal(BreakPoint::new((pt, pt_weight), (right_pt, right_weight), edge_BC));
+    let ind_BC = beachline.add_node(BeachNode { parent, left_child: Some(ind_B), right_child: Some(right_arc), item: internal_BC, priority });
 
-    return ind_B;
+    beachline.replace_child(parent, right_arc, ind_BC)?;
+    beachline.nodes[ind_B].parent = Some(ind_BC);
+    beachline.nodes[right_arc].parent = Some(ind_BC);
+    beachline.rebalance(ind_BC)?;
+
+    Ok(ind_B)
 }
 
 // return: indices of predecessor, successor, parent, 'other'
 // where 'other' is the one of predecessor or sucessor that
 // is not the parent of the leaf.
-fn delete_leaf(leaf: usize, beachline: &mut BeachLine) -> (usize, usize, usize, usize) {
-    let pred = beachline.predecessor(leaf).unwrap();
-    let succ = beachline.successor(leaf).unwrap();
-    let parent = beachline.nodes[leaf].parent.unwrap();
-    let grandparent = beachline.nodes[parent].parent.unwrap();
+pub fn delete_leaf(leaf: usize, beachline: &mut BeachLine, weights: &[f64]) -> Result<(usize, usize, usize, usize), VoronoiError> {
+    let pred = beachline.predecessor(leaf).ok_or(BEACHLINE_BROKEN)?;
+    let succ = beachline.successor(leaf).ok_or(BEACHLINE_BROKEN)?;
+    let parent = beachline.nodes[leaf].parent.ok_or(BEACHLINE_BROKEN)?;
+    let grandparent = beachline.nodes[parent].parent.ok_or(BEACHLINE_BROKEN)?;
 
     let other = if parent == pred { succ } else { pred };
 
     // find sibling
-    let sibling;
-    if beachline.nodes[parent].right_child.unwrap() == leaf {
-        sibling = beachline.nodes[parent].left_child.unwrap();
-    } else if beachline.nodes[parent].left_child.unwrap() == leaf {
-        sibling = beachline.nodes[parent].right_child.unwrap();
+    let sibling = if beachline.nodes[parent].right_child == Some(leaf) {
+        beachline.nodes[parent].left_child
+    } else if beachline.nodes[parent].left_child == Some(leaf) {
+        beachline.nodes[parent].right_child
     } else {
-        panic!("family strife! parent does not acknowledge leaf!");
-    }
+        None
+    };
+    let sibling = sibling.ok_or(VoronoiError::InternalInconsistency("beachline parent does not acknowledge leaf"))?;
 
     // transplant the sibling to replace the parent
     beachline.nodes[sibling].parent = Some(grandparent);
-    if beachline.nodes[grandparent].left_child.unwrap() == parent {
-        beachline.nodes[grandparent].left_child = Some(sibling);
-    } else if beachline.nodes[grandparent].right_child.unwrap() == parent {
-        beachline.nodes[grandparent].right_child = Some(sibling);
-    } else {
-        panic!("family strife! grandparent does not acknowledge parent!");
-    }
+    beachline.replace_child(Some(grandparent), parent, sibling)?;
 
     // correct the site on 'other'
     if other == pred {
-        let new_other_succ = beachline.successor(other).unwrap();
-        let new_site = beachline.get_site(Some(new_other_succ)).unwrap();
-        beachline.set_right_site(other, new_site);
+        let new_other_succ = beachline.successor(other).ok_or(BEACHLINE_BROKEN)?;
+        let new_weight = arc_weight(beachline, new_other_succ, weights)?;
+        beachline.set_right_site(other, new_other_succ, new_weight)?;
     } else {
-        let new_other_pred = beachline.predecessor(other).unwrap();
-        let new_site = beachline.get_site(Some(new_other_pred)).unwrap();
-        beachline.set_left_site(other, new_site);
+        let new_other_pred = beachline.predecessor(other).ok_or(BEACHLINE_BROKEN)?;
+        let new_weight = arc_weight(beachline, new_other_pred, weights)?;
+        beachline.set_left_site(other, new_other_pred, new_weight)?;
     }
 
-    (pred, succ, parent, other)
+    Ok((pred, succ, parent, other))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_circle_event(
     leaf: usize,
     circle_center: Point,
+    sweep_y: f64,
     queue: &mut EventQueue,
     beachline: &mut BeachLine,
-    dcel: &mut DCEL) {
+    dcel: &mut DCEL,
+    triangles: &mut Vec<[usize; 3]>,
+    weights: &[f64]) -> Result<(), VoronoiError> {
 
-    let left_neighbor = beachline.get_left_arc(Some(leaf)).unwrap();
-    let right_neighbor = beachline.get_right_arc(Some(leaf)).unwrap();
-    let (pred, succ, parent, other) = delete_leaf(leaf, beachline);
+    let left_neighbor = beachline.get_left_arc(Some(leaf)).ok_or(BEACHLINE_BROKEN)?;
+    let right_neighbor = beachline.get_right_arc(Some(leaf)).ok_or(BEACHLINE_BROKEN)?;
+    let (left_site, leaf_site, right_site) = beachline.get_centered_triple(leaf).ok_or(BEACHLINE_BROKEN)?;
+    let left_index = beachline.get_site_index(left_neighbor)?;
+    let right_index = beachline.get_site_index(right_neighbor)?;
+
+    // the three sites whose cells meet at the vertex make a Delaunay triangle
+    triangles.push([left_index, beachline.get_site_index(leaf)?, right_index]);
+    let (pred, succ, parent, other) = delete_leaf(leaf, beachline, weights)?;
 
     // removing site events involving disappearing arc
     remove_circle_event(leaf, queue, beachline);
@@ -216,6 +905,8 @@ fn handle_circle_event(
     remove_circle_event(right_neighbor, queue, beachline);
 
     let (twin1, twin2) = dcel.add_twins();
+    set_left_site(dcel, twin1, right_index);
+    set_left_site(dcel, twin2, left_index);
 
     // make a vertex at the circle center
     let center_vertex = Vertex { coordinates: circle_center, incident_edge: twin1, alive: true};
@@ -223,10 +914,10 @@ fn handle_circle_event(
     dcel.vertices.push(center_vertex);
 
     // hook up next pointers on halfedges
-    let pred_edge = beachline.get_edge(pred);
-    let succ_edge = beachline.get_edge(succ);
-    let parent_edge = beachline.get_edge(parent);
-    let other_edge = beachline.get_edge(other);
+    let pred_edge = beachline.get_edge(pred)?;
+    let succ_edge = beachline.get_edge(succ)?;
+    let parent_edge = beachline.get_edge(parent)?;
+    let other_edge = beachline.get_edge(other)?;
 
     let pred_edge_twin = dcel.halfedges[pred_edge].twin;
     let succ_edge_twin = dcel.halfedges[succ_edge].twin;
@@ -239,105 +930,288 @@ fn handle_circle_event(
     dcel.halfedges[succ_edge_twin].next = twin1;
     dcel.halfedges[twin2].next = pred_edge;
 
+    // a disappearing breakpoint whose other end lies on the same circle
+    // traced a zero-length edge, so its endpoints become one vertex
+    for &breakpoint in &[pred, succ] {
+        let edge = beachline.get_edge(breakpoint)?;
+        let edge_twin = dcel.halfedges[edge].twin;
+        if dcel.halfedges[edge_twin].origin == NIL { continue; }
+        let start = dcel.get_origin(edge_twin);
+        // the exact test is for circles through the sites, not power circles
+        let vanished = if weights.is_empty() { beachline.get_vanished_site(breakpoint)? } else { None };
+        if is_cocircular((left_site, leaf_site, right_site), vanished, circle_center, start) {
+            trace!("Merging cocircular vertex along halfedge {}", edge);
+            contract_edge(dcel, edge)?;
+        }
+    }
+
     if let BeachItem::Internal(ref mut breakpoint) = beachline.nodes[other].item {
         breakpoint.halfedge = twin2;
+        breakpoint.vanished_site = Some(leaf_site);
     }
 
+    // the leaf and its parent breakpoint are gone from the tree, and
+    // their circle events are removed, so their places can be reused
+    beachline.free_node(leaf);
+    beachline.free_node(parent);
+
     if let Some(left_triple) = beachline.get_centered_triple(left_neighbor) {
         trace!("Checking leftward triple {:?}, {:?}, {:?}", left_triple.0, left_triple.1, left_triple.2);
-        if breakpoints_converge(left_triple) {
+        if may_converge(left_triple, beachline) {
             trace!("Found converging triple");
-            make_circle_event(left_neighbor, left_triple, queue, beachline);
+            make_circle_event(left_neighbor, left_triple, sweep_y, queue, beachline, weights)?;
         }
     }
     if let Some(right_triple) = beachline.get_centered_triple(right_neighbor) {
         trace!("Checking rightward triple {:?}, {:?}, {:?}", right_triple.0, right_triple.1, right_triple.2);
-        if breakpoints_converge(right_triple) {
+        if may_converge(right_triple, beachline) {
             trace!("Found converging triple");
-            make_circle_event(right_neighbor, right_triple, queue, beachline);
+            make_circle_event(right_neighbor, right_triple, sweep_y, queue, beachline, weights)?;
         }
     }
+    Ok(())
 }
 
-fn outside_bb(pt: Point, box_size: f64) -> bool {
-    let delta = 0.1;
-    pt.x() < 0. - delta || pt.x() > box_size + delta || pt.y() < 0. - delta || pt.y() > box_size + delta
-}
-
-fn add_bounding_box(boxsize: f64, beachline: &BeachLine, dcel: &mut DCEL) {
-    extend_edges(beachline, dcel);
-
-    let delta = 50.;
-    let bb_top =    [Point::new(0. - delta, 0.),         Point::new(boxsize + delta, 0.)];
-    let bb_bottom = [Point::new(0. - delta, boxsize),    Point::new(boxsize + delta, boxsize)];
-    let bb_left =   [Point::new(0.,         0. - delta), Point::new(0.,              boxsize + delta)];
-    let bb_right =  [Point::new(boxsize,    0. - delta), Point::new(boxsize,         boxsize + delta)];
-
-    add_line(bb_top, dcel);
-    add_line(bb_right, dcel);
-    add_line(bb_left, dcel);
-    add_line(bb_bottom, dcel);
-
-    dcel.set_prev();
-
-    for vert in 0..dcel.vertices.len() {
-        let this_pt = dcel.vertices[vert].coordinates;
-        if outside_bb(this_pt, boxsize) {
-            dcel.remove_vertex(vert);
-        }
+// Is the vertex at `other_center` the center of the circle through `triple`?
+// If the site whose arc vanished there is known, test it exactly, otherwise
+// (and for sites that are cocircular only up to rounding) compare the centers.
+fn is_cocircular(triple: TripleSite, vanished: Option<Point>, center: Point, other_center: Point) -> bool {
+    let (a, b, c) = triple;
+    if let Some(pt) = vanished {
+        if incircle(a, b, c, pt) == 0.0 { return true; }
     }
 
+    let radius = (a - center).dot(a - center).sqrt();
+    let offset = center - other_center;
+    offset.dot(offset).sqrt() <= COCIRCULAR_TOLERANCE * radius
 }
 
-// This just extends the edges past the end of the bounding box
-fn extend_edges(beachline: &BeachLine, dcel: &mut DCEL) {
+// The infinite edges, in counterclockwise order: the breakpoints left on the
+// beachline, from left to right, then the upward bisectors of sites at the
+// same height as the first, from right to left.
+fn get_rays(beachline: &BeachLine, dcel: &DCEL) -> Result<Vec<Ray>, VoronoiError> {
+    let mut rays = vec![];
+    let mut at_infinity = vec![false; dcel.halfedges.len()];
     let mut current_node = beachline.tree_minimum(beachline.root);
-    trace!("\n\n");
     loop {
-        match beachline.nodes[current_node].item {
-            BeachItem::Leaf(_) => {},
-            BeachItem::Internal(ref breakpoint) => {
-                let this_edge = breakpoint.halfedge;
-                trace!("Extending halfedge {:?} with breakpoint {:?}, {:?}", this_edge, breakpoint.left_site, breakpoint.right_site);
-                let this_x = get_breakpoint_x(&breakpoint, -1000.0);
-                let this_y = get_breakpoint_y(&breakpoint, -1000.0);
-
-                let vert = Vertex {coordinates: Point::new(this_x, this_y), incident_edge: this_edge, alive: true};
-                let vert_ind = dcel.vertices.len();
-
-                dcel.halfedges[this_edge].origin = vert_ind;
-                let this_twin = dcel.halfedges[this_edge].twin;
-                dcel.halfedges[this_twin].next = this_edge;
-
-                dcel.vertices.push(vert);
-            }
+        if let BeachItem::Internal(ref breakpoint) = beachline.nodes[current_node].item {
+            // the breakpoint runs along the bisector, away from the start of its edge,
+            // or away from the point of the bisector between the sites if the edge is a whole line
+            let this_edge = breakpoint.halfedge;
+            let this_twin = dcel.halfedges[this_edge].twin;
+            let separation = breakpoint.right_site - breakpoint.left_site;
+            let start = if dcel.halfedges[this_twin].origin != NIL {
+                dcel.get_origin(this_twin)
+            } else if beachline.weighting == Weighting::Additive {
+                // the point of the branch between the sites
+                let reach = (separation.dot(separation).sqrt() + breakpoint.left_weight - breakpoint.right_weight) / 2.;
+                breakpoint.left_site + separation * (reach / separation.dot(separation).sqrt())
+            } else {
+                let shift = (breakpoint.right_weight - breakpoint.left_weight) / (2. * separation.dot(separation));
+                (breakpoint.left_site + breakpoint.right_site) * 0.5 + separation * shift
+            };
+            let direction = Point::new(separation.y(), -separation.x());
+            let direction = direction * (1. / direction.dot(direction).sqrt());
+
+            at_infinity[this_edge] = true;
+            rays.push(Ray { halfedge: this_twin, origin: start, direction });
         }
         if let Some(next_node) = beachline.successor(current_node) {
             current_node = next_node;
         } else { break; }
     }
 
+    let mut upward_rays = vec![];
+    for (edge, halfedge) in dcel.halfedges.iter().enumerate() {
+        if !halfedge.is_alive() || halfedge.origin != NIL || at_infinity[edge] { continue; }
+        let this_twin = halfedge.twin;
+        let start = if dcel.halfedges[this_twin].origin != NIL {
+            dcel.get_origin(this_twin)
+        } else {
+            rays.iter().find(|ray| ray.halfedge == edge)
+                .ok_or(VoronoiError::InternalInconsistency("upward edge has no start"))?.origin
+        };
+        upward_rays.push(Ray { halfedge: this_twin, origin: start, direction: Point::new(0., 1.) });
+    }
+    upward_rays.sort_by(|a, b| b.origin.x().total_cmp(&a.origin.x()));
+    rays.extend(upward_rays);
+    Ok(rays)
+}
+
+// Extends the infinite edges of an additively weighted diagram along their
+// hyperbolas, to points beyond which the hyperbolas stay out of the bounding box
+fn extend_hyperbolas(bbox: &BoundingBox, rays: &[Ray], dcel: &mut DCEL, positions: &[Point], weights: &[f64]) -> Result<(), VoronoiError> {
+    for ray in rays {
+        let this_edge = dcel.halfedges[ray.halfedge].twin;
+        let sides = (left_site(dcel, this_edge), left_site(dcel, ray.halfedge));
+        let (left, right) = match sides {
+            (Some(left), Some(right)) => ((positions[left], weights[left]), (positions[right], weights[right])),
+            _ => return Err(VoronoiError::InternalInconsistency("infinite edge has no sites")),
+        };
+        let from_site = |pt: Point| (pt - left.0).dot(pt - left.0).sqrt();
+        let reach = from_site(ray.origin).max(from_site(bbox.center())) + bbox.diagonal();
+        let end_pt = hyperbola_far_point(left, right, ray.direction, reach).unwrap_or_else(|| ray.point_at(reach));
+        if !end_pt.x().is_finite() || !end_pt.y().is_finite() { return Err(VoronoiError::DegenerateInput); }
+        trace!("Extending halfedge {:?} to {:?}", this_edge, end_pt);
+
+        let vert = Vertex {coordinates: end_pt, incident_edge: this_edge, alive: true};
+        let vert_ind = dcel.vertices.len();
+        dcel.halfedges[this_edge].origin = vert_ind;
+        dcel.halfedges[ray.halfedge].next = this_edge;
+        dcel.vertices.push(vert);
+    }
+    Ok(())
+}
+
+// How many diagonals of the bounding box from its center an end of an edge
+// must lie before `pull_in_far_ends` pins the edge to its bisector
+const FAR_END: f64 = 1e6;
+
+// Near-collinear sites put vertices so far out that the rounding of their
+// coordinates tilts the edges through them across the bounding box. This
+// joins each such far end to the rest of its edge through a point on the
+// exact bisector of the sites on either side, outside the box, so that the
+// edges are clipped where the sites put them.
+fn pull_in_far_ends(bbox: &BoundingBox, dcel: &mut DCEL, sites: &[(usize, Point)], weights: &[f64]) -> Result<(), VoronoiError> {
+    let (center, reach) = (bbox.center(), bbox.diagonal());
+    let mut positions = vec![center; sites.iter().map(|&(index, _)| index + 1).max().unwrap_or(0)];
+    for &(index, pt) in sites { positions[index] = pt; }
+    let weight = |site: usize| weights.get(site).cloned().unwrap_or(0.);
+    let is_far = |pt: Point| (pt - center).dot(pt - center) > (FAR_END * reach) * (FAR_END * reach);
+
+    for edge in 0..dcel.halfedges.len() {
+        let twin = dcel.halfedges[edge].twin;
+        if twin < edge || !dcel.halfedges[edge].is_alive() { continue; }
+        let (left, right) = match (left_site(dcel, edge), left_site(dcel, twin)) {
+            (Some(left), Some(right)) => (left, right),
+            _ => continue,
+        };
+        let (start, end) = (dcel.get_origin(edge), dcel.get_origin(twin));
+        if !is_far(start) && !is_far(end) { continue; }
+
+        // the bisector, from its point nearest the center of the box, along the edge
+        let separation = positions[right] - positions[left];
+        let squared_separation = separation.dot(separation);
+        let through = (positions[left] + positions[right]) * 0.5 + separation * ((weight(right) - weight(left)) / (2. * squared_separation));
+        let mut direction = Point::new(separation.y(), -separation.x()) * (1. / squared_separation.sqrt());
+        if (end - start).dot(direction) < 0. { direction = direction * -1.; }
+        let nearest = through + direction * (center - through).dot(direction);
+        let (from, to) = ((start - nearest).dot(direction), (end - nearest).dot(direction));
+
+        let mut path = vec![];
+        if is_far(start) && from < -reach && to > -reach { path.push(nearest - direction * reach); }
+        if is_far(end) && to > reach && from < reach { path.push(nearest + direction * reach); }
+        bend_edge(dcel, edge, &path)?;
+    }
+    Ok(())
+}
+
+// Bends each edge of an additively weighted diagram into a path along the
+// hyperbola between the sites on either side, to within `tolerance`
+fn bend_hyperbolas(dcel: &mut DCEL, positions: &[Point], weights: &[f64], tolerance: f64) -> Result<(), VoronoiError> {
+    for edge in 0..dcel.halfedges.len() {
+        let twin = dcel.halfedges[edge].twin;
+        if twin < edge || !dcel.halfedges[edge].is_alive() { continue; }
+        let (left, right) = match (left_site(dcel, edge), left_site(dcel, twin)) {
+            (Some(left), Some(right)) => (left, right),
+            _ => continue,
+        };
+        let (start, end) = (dcel.get_origin(edge), dcel.get_origin(twin));
+        let path = hyperbola_points((positions[left], weights[left]), (positions[right], weights[right]), start, end, tolerance);
+        bend_edge(dcel, edge, &path)?;
+    }
+    Ok(())
+}
+
+// This just extends the edges past the end of the bounding box. A ray from
+// a far origin goes as far again, for `pull_in_far_ends` to pin it down.
+fn extend_edges(bbox: &BoundingBox, rays: &[Ray], dcel: &mut DCEL) -> Result<(), VoronoiError> {
+    for ray in rays {
+        let distance = (ray.origin - bbox.center()).dot(ray.origin - bbox.center()).sqrt();
+        let reach = if distance > FAR_END * bbox.diagonal() { 2. * distance } else { distance } + bbox.diagonal();
+        let end_pt = ray.point_at(reach);
+        if !end_pt.x().is_finite() || !end_pt.y().is_finite() { return Err(VoronoiError::DegenerateInput); }
+        let this_edge = dcel.halfedges[ray.halfedge].twin;
+        trace!("Extending halfedge {:?} to {:?}", this_edge, end_pt);
+
+        let vert = Vertex {coordinates: end_pt, incident_edge: this_edge, alive: true};
+        let vert_ind = dcel.vertices.len();
+        dcel.halfedges[this_edge].origin = vert_ind;
+        dcel.halfedges[ray.halfedge].next = this_edge;
+        dcel.vertices.push(vert);
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use dcel::make_polygons;
+    use dcel::{make_polygons, make_site_polygons, make_site_polygons_with_holes, signed_area};
+    use test_utils::face_contains;
+    use validate::Validated;
+    use rand::{Rng, SeedableRng, StdRng};
 
     #[test]
     fn readme_example() {
         let vor_pts = vec![Point::new(0.0, 1.0), Point::new(2.0, 3.0), Point::new(10.0, 12.0)];
-        let vor_diagram = voronoi(vor_pts, 800.);
+        let vor_diagram = voronoi(vor_pts, 800.).validated();
         let vor_polys = make_polygons(&vor_diagram);
         assert_eq!(vor_polys.len(), 3);
     }
 
     #[test]
-    #[ignore]
+    fn try_voronoi_matches_voronoi() {
+        let vor_pts = vec![Point::new(0.0, 1.0), Point::new(2.0, 3.0), Point::new(10.0, 12.0)];
+        let vor_diagram = try_voronoi(vor_pts.clone(), 800.).validated().unwrap();
+        assert_eq!(make_polygons(&vor_diagram), make_polygons(&voronoi(vor_pts, 800.).validated()));
+    }
+
+    #[test]
+    fn non_finite_site_reported() {
+        let vor_pts = vec![Point::new(0.0, 1.0), Point::new(2.0, 3.0), Point::new(f64::NAN, 12.0)];
+        assert_eq!(try_voronoi(vor_pts, 800.).validated().err(), Some(VoronoiError::NonFiniteCoordinate { index: 2 }));
+    }
+
+    #[test]
+    fn infinite_sites_reported() {
+        let vor_pts = vec![Point::new(0.0, 1.0), Point::new(2.0, f64::NEG_INFINITY), Point::new(f64::INFINITY, 3.0)];
+        assert_eq!(try_voronoi(vor_pts, 800.).validated().err(), Some(VoronoiError::NonFiniteCoordinate { index: 1 }));
+    }
+
+    #[test]
+    fn non_finite_sites_rejected_everywhere() {
+        let vor_pts = vec![Point::new(10.0, 10.0), Point::new(20.0, 30.0), Point::new(40.0, 20.0), Point::new(30.0, f64::NAN)];
+        let expected = Some(VoronoiError::NonFiniteCoordinate { index: 3 });
+        let options = VoronoiOptions { duplicates: DuplicateSites::Reject, ..VoronoiOptions::default() };
+        assert_eq!(voronoi_with_options(vor_pts.clone(), 800., &options).validated().err(), expected);
+        assert_eq!(voronoi_in_convex_polygon(vor_pts.clone(), &square(0.0, 100.0), &options).validated().err(), expected);
+        assert_eq!(voronoi_in_polygon(vor_pts.clone(), &square(0.0, 100.0), &[], &options).validated().err(), expected);
+        assert_eq!(voronoi_unbounded(vor_pts, &options).validated().err(), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "input site 2 has a non-finite coordinate")]
+    fn voronoi_panics_on_nan() {
+        voronoi(vec![Point::new(0.0, 1.0), Point::new(2.0, 3.0), Point::new(f64::NAN, f64::NAN)], 800.).validated();
+    }
+
+    #[test]
+    fn overflowing_sites_reported() {
+        let vor_pts = vec![Point::new(0.0, 0.0), Point::new(1e200, 1e200), Point::new(2e200, 0.0)];
+        assert_eq!(try_voronoi(vor_pts, 800.).validated().err(), Some(VoronoiError::DegenerateInput));
+    }
+
+    #[test]
+    #[should_panic(expected = "bounding box must have positive")]
+    fn voronoi_panics_with_error_message() {
+        voronoi(vec![Point::new(0.0, 1.0)], 0.).validated();
+    }
+
+    #[test]
     fn degenerate_example_horz() {
         let vor_pts = vec![Point::new(10.0, 1.0), Point::new(20.0, 1.0), Point::new(30.0, 1.0)];
         let num_pts = vor_pts.len();
-        let vor_diagram = voronoi(vor_pts, 800.);
+        let vor_diagram = voronoi(vor_pts, 800.).validated();
         let vor_polys = make_polygons(&vor_diagram);
         assert_eq!(vor_polys.len(), num_pts);
     }
@@ -346,8 +1220,667 @@ mod tests {
     fn degenerate_example_vert() {
         let vor_pts = vec![Point::new(1.0, 10.0), Point::new(1.0, 20.0), Point::new(1.0, 30.0), Point::new(1.0, 40.0)];
         let num_pts = vor_pts.len();
-        let vor_diagram = voronoi(vor_pts, 800.);
+        let vor_diagram = voronoi(vor_pts, 800.).validated();
+        let vor_polys = make_polygons(&vor_diagram);
+        assert_eq!(vor_polys.len(), num_pts);
+    }
+
+    // five sites within rounding of a horizontal line, four of them on the hull
+    fn nearly_collinear_sites() -> Vec<Point> {
+        vec![Point::new(53.112079767309716, 47.32552003402272), Point::new(69.84442821073355, 47.325520034022816),
+             Point::new(42.610912530667, 47.325520034022745), Point::new(42.3474864481564, 47.32552003402278),
+             Point::new(31.516998914555572, 47.3255200340228)]
+    }
+
+    #[test]
+    fn nearly_collinear_sites_bounded() {
+        assert_one_polygon_per_site(nearly_collinear_sites());
+        assert_one_polygon_per_site(vec![Point::new(9.412282379599057, 55.83823888582253), Point::new(8.390724138361016, 55.81867691319085),
+                                         Point::new(94.58080531898845, 57.469143759930915), Point::new(80.76773537713369, 57.20463520304772)]);
+    }
+
+    fn grid_points(cols: usize, rows: usize, spacing: f64, offset: f64) -> Vec<Point> {
+        let mut pts = vec![];
+        for row in 0..rows {
+            for col in 0..cols {
+                pts.push(Point::new(offset + col as f64 * spacing, offset + row as f64 * spacing));
+            }
+        }
+        pts
+    }
+
+    fn assert_one_polygon_per_site(vor_pts: Vec<Point>) {
+        let num_pts = vor_pts.len();
+        let vor_diagram = voronoi(vor_pts, 800.).validated();
         let vor_polys = make_polygons(&vor_diagram);
         assert_eq!(vor_polys.len(), num_pts);
+        assert_clean_polygons(&vor_polys);
+    }
+
+    // no polygon may contain a zero-length edge
+    fn assert_clean_polygons(polys: &[Vec<Point>]) {
+        for poly in polys {
+            for (index, &pt) in poly.iter().enumerate() {
+                assert!(pt != poly[(index + 1) % poly.len()], "repeated vertex in {:?}", poly);
+            }
+        }
+    }
+
+    fn regular_polygon(num_sides: usize, center: Point, radius: f64) -> Vec<Point> {
+        (0..num_sides).map(|i| {
+            let angle = i as f64 * 2.0 * ::std::f64::consts::PI / num_sides as f64;
+            center + Point::new(radius * angle.cos(), radius * angle.sin())
+        }).collect()
+    }
+
+    fn count_vertices_near(dcel: &DCEL, pt: Point) -> usize {
+        dcel.vertices.iter()
+            .filter(|vertex| vertex.alive)
+            .filter(|vertex| { let offset = vertex.coordinates - pt; offset.dot(offset) < 1e-12 })
+            .count()
+    }
+
+    fn beachline_depth(beachline: &BeachLine, node: usize) -> usize {
+        let children = [beachline.nodes[node].left_child, beachline.nodes[node].right_child];
+        1 + children.iter().filter_map(|&child| child).map(|child| beachline_depth(beachline, child)).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn beachline_stays_balanced_for_sorted_sites() {
+        // collinear sites have no circle events, so every arc stays on the beachline
+        let num_pts = 2000;
+        let mut queue = EventQueue::new();
+        let mut beachline = BeachLine::new();
+        let mut dcel = DCEL::new();
+        for index in 0..num_pts {
+            let pt = Point::new(index as f64, -(index as f64));
+            handle_site_event(pt, index, pt.y(), &mut queue, &mut beachline, &mut dcel, &[]).unwrap();
+        }
+        assert!(beachline_depth(&beachline, beachline.root) < 80);
+    }
+
+    #[test]
+    fn beachline_nodes_are_reused() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[2024][..]);
+        let mut queue = EventQueue::new();
+        let mut beachline = BeachLine::new();
+        let mut dcel = DCEL::new();
+        let mut triangles = vec![];
+        for index in 0..2000 {
+            queue.push(Event::Site(Point::new(rng.next_f64() * 800., rng.next_f64() * 800.), index));
+        }
+
+        let mut max_len = 0;
+        while let Some(event) = queue.pop() {
+            match event {
+                Event::Site(pt, index) => handle_site_event(pt, index, pt.y(), &mut queue, &mut beachline, &mut dcel, &[]).unwrap(),
+                Event::Circle(center, height, _, leaf, _) => handle_circle_event(leaf, center, height, &mut queue, &mut beachline, &mut dcel, &mut triangles, &[]).unwrap(),
+                Event::Appearance(..) => unreachable!(),
+            }
+            max_len = max_len.max(beachline.len());
+        }
+        // a split allocates its new nodes before the old leaf is freed
+        assert!(beachline.nodes.len() <= max_len + 1);
+        assert!(max_len < 2000);
+    }
+
+    #[test]
+    fn two_sites_same_height() {
+        assert_one_polygon_per_site(vec![Point::new(300.0, 400.0), Point::new(500.0, 400.0)]);
+    }
+
+    #[test]
+    fn horizontal_sites_out_of_order() {
+        assert_one_polygon_per_site(vec![Point::new(30.0, 1.0), Point::new(10.0, 1.0), Point::new(20.0, 1.0)]);
+    }
+
+    #[test]
+    fn horizontal_sites_then_lower_site() {
+        assert_one_polygon_per_site(vec![Point::new(100.0, 500.0), Point::new(300.0, 500.0), Point::new(500.0, 500.0), Point::new(250.0, 100.0)]);
+    }
+
+    #[test]
+    fn single_row_grid() {
+        assert_one_polygon_per_site(grid_points(8, 1, 90.0, 50.0));
+    }
+
+    #[test]
+    fn two_row_grid() {
+        assert_one_polygon_per_site(grid_points(5, 2, 110.0, 100.0));
+    }
+
+    #[test]
+    fn square_grid() {
+        assert_one_polygon_per_site(grid_points(6, 6, 120.0, 85.0));
+    }
+
+    #[test]
+    fn rectangular_grid() {
+        assert_one_polygon_per_site(grid_points(10, 3, 70.0, 60.0));
+    }
+
+    #[test]
+    fn duplicate_sites_are_merged() {
+        let vor_pts = vec![Point::new(10.0, 1.0), Point::new(200.0, 100.0), Point::new(200.0, 100.0), Point::new(300.0, 500.0), Point::new(10.0, 1.0)];
+        let (vor_diagram, site_map) = voronoi_with_options(vor_pts, 800., &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(site_map, vec![0, 1, 1, 3, 0]);
+        assert_eq!(make_polygons(&vor_diagram).len(), 3);
+    }
+
+    #[test]
+    fn duplicate_sites_merged_by_default() {
+        let vor_pts = vec![Point::new(300.0, 400.0), Point::new(300.0, 400.0), Point::new(300.0, 400.0)];
+        let vor_diagram = voronoi(vor_pts, 800.).validated();
+        assert_eq!(make_polygons(&vor_diagram).len(), 1);
+    }
+
+    #[test]
+    fn duplicate_sites_rejected() {
+        let vor_pts = vec![Point::new(10.0, 1.0), Point::new(200.0, 100.0), Point::new(300.0, 500.0), Point::new(200.0, 100.0)];
+        let options = VoronoiOptions { duplicates: DuplicateSites::Reject, ..VoronoiOptions::default() };
+        let result = voronoi_with_options(vor_pts, 800., &options).validated();
+        assert_eq!(result.err(), Some(VoronoiError::DuplicateSite { first: 1, duplicate: 3 }));
+    }
+
+    #[test]
+    fn distinct_sites_not_rejected() {
+        let vor_pts = vec![Point::new(10.0, 1.0), Point::new(200.0, 100.0), Point::new(300.0, 500.0)];
+        let options = VoronoiOptions { duplicates: DuplicateSites::Reject, ..VoronoiOptions::default() };
+        let (_, site_map) = voronoi_with_options(vor_pts, 800., &options).validated().unwrap();
+        assert_eq!(site_map, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn cocircular_square() {
+        let vor_pts = vec![Point::new(100.0, 100.0), Point::new(300.0, 100.0), Point::new(100.0, 300.0), Point::new(300.0, 300.0)];
+        let vor_diagram = voronoi(vor_pts, 800.).validated();
+        assert_eq!(count_vertices_near(&vor_diagram, Point::new(200.0, 200.0)), 1);
+        let vor_polys = make_polygons(&vor_diagram);
+        assert_eq!(vor_polys.len(), 4);
+        assert_clean_polygons(&vor_polys);
+        assert!(vor_polys.iter().all(|poly| poly.len() == 4));
+    }
+
+    #[test]
+    fn cocircular_lattice() {
+        let vor_diagram = voronoi(grid_points(4, 4, 200.0, 100.0), 800.).validated();
+        for &x in &[200.0, 400.0, 600.0] {
+            for &y in &[200.0, 400.0, 600.0] {
+                assert_eq!(count_vertices_near(&vor_diagram, Point::new(x, y)), 1);
+            }
+        }
+        let vor_polys = make_polygons(&vor_diagram);
+        assert_eq!(vor_polys.len(), 16);
+        assert_clean_polygons(&vor_polys);
+        assert!(vor_polys.iter().all(|poly| poly.len() == 4));
+    }
+
+    #[test]
+    fn cocircular_regular_polygons() {
+        let center = Point::new(390.0, 415.0);
+        for &num_sides in &[5, 6, 8, 12, 17] {
+            let vor_diagram = voronoi(regular_polygon(num_sides, center, 200.0), 800.).validated();
+            assert_eq!(count_vertices_near(&vor_diagram, center), 1);
+            let vor_polys = make_polygons(&vor_diagram);
+            assert_eq!(vor_polys.len(), num_sides);
+            assert_clean_polygons(&vor_polys);
+        }
+    }
+
+    fn polygon_contains(poly: &[Point], pt: Point) -> bool {
+        let mut inside = false;
+        for (index, &start) in poly.iter().enumerate() {
+            let end = poly[(index + 1) % poly.len()];
+            if (start.y() > pt.y()) != (end.y() > pt.y()) {
+                let x_cross = start.x() + (pt.y() - start.y()) * (end.x() - start.x()) / (end.y() - start.y());
+                if pt.x() < x_cross { inside = !inside; }
+            }
+        }
+        inside
+    }
+
+    #[test]
+    fn faces_match_input_points() {
+        let vor_pts = vec![Point::new(620.0, 130.0), Point::new(110.0, 700.0), Point::new(400.0, 420.0),
+                           Point::new(250.0, 90.0), Point::new(710.0, 610.0), Point::new(90.0, 330.0)];
+        let vor_diagram = voronoi(vor_pts.clone(), 800.).validated();
+        let site_polys = make_site_polygons(&vor_diagram);
+        assert_eq!(site_polys.iter().map(|&(site, _)| site).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+        for &(site, ref poly) in &site_polys {
+            assert!(polygon_contains(poly, vor_pts[site]));
+        }
+        assert_eq!(vor_diagram.faces.iter().filter(|face| face.site().is_none()).count(), 1);
+    }
+
+    #[test]
+    fn polygons_in_input_order() {
+        let vor_pts = grid_points(5, 4, 130.0, 70.0);
+        let vor_polys = make_polygons(&voronoi(vor_pts.clone(), 800.).validated());
+        for (pt, poly) in vor_pts.iter().zip(vor_polys.iter()) {
+            assert!(polygon_contains(poly, *pt));
+        }
+    }
+
+    #[test]
+    fn single_site_face() {
+        let site_polys = make_site_polygons(&voronoi(vec![Point::new(300.0, 200.0)], 800.).validated());
+        assert_eq!(site_polys.len(), 1);
+        assert_eq!(site_polys[0].0, 0);
+        assert!(polygon_contains(&site_polys[0].1, Point::new(300.0, 200.0)));
+    }
+
+    #[test]
+    fn merged_duplicates_keep_first_index() {
+        let vor_pts = vec![Point::new(200.0, 200.0), Point::new(600.0, 650.0), Point::new(200.0, 200.0), Point::new(600.0, 180.0)];
+        let (vor_diagram, site_map) = voronoi_with_options(vor_pts, 800., &VoronoiOptions::default()).validated().unwrap();
+        let sites: Vec<usize> = make_site_polygons(&vor_diagram).iter().map(|&(site, _)| site).collect();
+        assert_eq!(sites, vec![0, 1, 3]);
+        assert_eq!(site_map[2], 0);
+    }
+
+    // cells lie in the box, contain their sites and tile the box
+    fn assert_tiles_box(vor_pts: &[Point], bbox: BoundingBox) {
+        let vor_diagram = voronoi(vor_pts.to_vec(), bbox).validated();
+        assert_eq!(make_site_polygons(&vor_diagram).len(), vor_pts.len());
+        assert_tiles_polygon(&vor_diagram, vor_pts, &bbox.corners());
+    }
+
+    // cells lie in the counterclockwise convex polygon, contain their sites
+    // if those are inside it, and tile it
+    fn assert_tiles_polygon(vor_diagram: &DCEL, vor_pts: &[Point], polygon: &[Point]) {
+        let region_area = signed_area(polygon);
+        let tolerance = 1e-9 * BoundingBox::enclosing(polygon).diagonal();
+        let mut total_area = 0.;
+        for (site, poly) in make_site_polygons(vor_diagram) {
+            if polygon_contains(polygon, vor_pts[site]) {
+                assert!(polygon_contains(&poly, vor_pts[site]));
+            }
+            for &pt in &poly {
+                for (index, &start) in polygon.iter().enumerate() {
+                    let side = polygon[(index + 1) % polygon.len()] - start;
+                    assert!(side.cross(pt - start) >= -tolerance * side.dot(side).sqrt(), "{:?} outside polygon", pt);
+                }
+            }
+            let area = signed_area(&poly);
+            assert!(area > 0.);
+            total_area += area;
+        }
+        assert!((total_area - region_area).abs() < 1e-9 * region_area);
+    }
+
+    #[test]
+    fn negative_coordinate_box() {
+        let bbox = BoundingBox::new(Point::new(-500.0, -300.0), Point::new(-100.0, 200.0));
+        let vor_pts = vec![Point::new(-450.0, -250.0), Point::new(-120.0, 150.0), Point::new(-300.0, 0.0),
+                           Point::new(-200.0, -220.0), Point::new(-420.0, 170.0)];
+        assert_tiles_box(&vor_pts, bbox);
+    }
+
+    #[test]
+    fn non_square_box() {
+        let bbox = BoundingBox::new(Point::new(1000.0, 20.0), Point::new(3000.0, 80.0));
+        let vor_pts = vec![Point::new(1100.0, 30.0), Point::new(1500.0, 70.0), Point::new(2200.0, 50.0),
+                           Point::new(2900.0, 25.0), Point::new(2600.0, 75.0)];
+        assert_tiles_box(&vor_pts, bbox);
+    }
+
+    #[test]
+    fn tiny_box() {
+        let bbox = BoundingBox::new(Point::new(0.25, 0.5), Point::new(0.75, 0.625));
+        let vor_pts = vec![Point::new(0.3, 0.55), Point::new(0.7, 0.6), Point::new(0.5, 0.51)];
+        assert_tiles_box(&vor_pts, bbox);
+    }
+
+    #[test]
+    fn horizontal_sites_in_offset_box() {
+        let bbox = BoundingBox::new(Point::new(-5000.0, 4000.0), Point::new(-1000.0, 9000.0));
+        let vor_pts = vec![Point::new(-4000.0, 6000.0), Point::new(-3000.0, 6000.0), Point::new(-2000.0, 6000.0)];
+        assert_tiles_box(&vor_pts, bbox);
+    }
+
+    #[test]
+    fn size_is_square_box() {
+        assert_eq!(BoundingBox::from(800.), BoundingBox::new(Point::new(0.0, 0.0), Point::new(800.0, 800.0)));
+    }
+
+    #[test]
+    fn empty_box_rejected() {
+        let bbox = BoundingBox::new(Point::new(10.0, 0.0), Point::new(10.0, 50.0));
+        let result = voronoi_with_options(vec![Point::new(10.0, 20.0)], bbox, &VoronoiOptions::default()).validated();
+        assert_eq!(result.err(), Some(VoronoiError::InvalidBoundingBox));
+    }
+
+    // the box passes exactly through Voronoi vertices and along Voronoi edges
+    fn assert_vertex_count_at(vor_diagram: &DCEL, pt: Point, count: usize) {
+        assert_eq!(vor_diagram.vertices.iter().filter(|vertex| vertex.coordinates == pt).count(), count);
+    }
+
+    #[test]
+    fn vertex_on_box_side() {
+        let vor_pts = vec![Point::new(2.0, 4.0), Point::new(8.0, 4.0), Point::new(5.0, 5.0)];
+        let vor_diagram = voronoi(vor_pts.clone(), 10.).validated();
+        assert_vertex_count_at(&vor_diagram, Point::new(5.0, 0.0), 1);
+        assert_tiles_box(&vor_pts, BoundingBox::from(10.));
+    }
+
+    #[test]
+    fn bisectors_through_box_corners() {
+        let vor_pts = vec![Point::new(2.0, 5.0), Point::new(8.0, 5.0), Point::new(5.0, 2.0), Point::new(5.0, 8.0)];
+        let vor_diagram = voronoi(vor_pts.clone(), 10.).validated();
+        for &corner in &BoundingBox::from(10.).corners() {
+            assert_vertex_count_at(&vor_diagram, corner, 1);
+        }
+        for (_, poly) in make_site_polygons(&vor_diagram) {
+            assert_eq!(poly.len(), 3);
+            assert_eq!(signed_area(&poly), 25.);
+        }
+        assert_tiles_box(&vor_pts, BoundingBox::from(10.));
+
+        let vor_pts = vec![Point::new(3.0, 7.0), Point::new(7.0, 3.0)];
+        for (_, poly) in make_site_polygons(&voronoi(vor_pts.clone(), 10.).validated()) {
+            assert_eq!(poly.len(), 3);
+        }
+        assert_tiles_box(&vor_pts, BoundingBox::from(10.));
+    }
+
+    #[test]
+    fn voronoi_edges_along_box_side() {
+        let vor_pts = vec![Point::new(3.0, 2.0), Point::new(3.0, -2.0), Point::new(7.0, 2.0), Point::new(7.0, -2.0)];
+        let vor_diagram = voronoi(vor_pts.clone(), 10.).validated();
+        assert_vertex_count_at(&vor_diagram, Point::new(5.0, 0.0), 1);
+        let site_polys = make_site_polygons(&vor_diagram);
+        assert_eq!(site_polys.iter().map(|&(site, _)| site).collect::<Vec<_>>(), vec![0, 2]);
+        for (_, poly) in site_polys {
+            assert_eq!(poly.len(), 4);
+            assert_eq!(signed_area(&poly), 50.);
+        }
+    }
+
+    #[test]
+    fn symmetric_sites_in_diamond() {
+        // the cells of a grid meet the sides of the diamond at their corners
+        let polygon = vec![Point::new(400.0, 0.0), Point::new(800.0, 400.0), Point::new(400.0, 800.0), Point::new(0.0, 400.0)];
+        let vor_pts = grid_points(4, 4, 200.0, 100.0);
+        let (vor_diagram, _) = voronoi_in_convex_polygon(vor_pts.clone(), &polygon, &VoronoiOptions::default()).validated().unwrap();
+        for &corner in &polygon {
+            assert_vertex_count_at(&vor_diagram, corner, 1);
+        }
+        assert_tiles_polygon(&vor_diagram, &vor_pts, &polygon);
+    }
+
+    fn hexagon() -> Vec<Point> {
+        (0..6).map(|i| {
+            let angle = i as f64 * ::std::f64::consts::PI / 3.0;
+            Point::new(400.0 + 300.0 * angle.cos(), 400.0 + 300.0 * angle.sin())
+        }).collect()
+    }
+
+    #[test]
+    fn convex_polygon_tiled() {
+        let vor_pts = vec![Point::new(400.0, 400.0), Point::new(250.0, 310.0), Point::new(560.0, 290.0),
+                           Point::new(430.0, 610.0), Point::new(180.0, 470.0), Point::new(610.0, 480.0)];
+        let polygon = hexagon();
+        let (vor_diagram, _) = voronoi_in_convex_polygon(vor_pts.clone(), &polygon, &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(make_site_polygons(&vor_diagram).len(), vor_pts.len());
+        assert_tiles_polygon(&vor_diagram, &vor_pts, &polygon);
+    }
+
+    #[test]
+    fn clockwise_triangle_tiled() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(50.0, 100.0), Point::new(100.0, 0.0)];
+        let vor_pts = vec![Point::new(20.0, 10.0), Point::new(80.0, 10.0), Point::new(50.0, 60.0), Point::new(50.0, 20.0)];
+        let (vor_diagram, _) = voronoi_in_convex_polygon(vor_pts.clone(), &polygon, &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(make_site_polygons(&vor_diagram).len(), vor_pts.len());
+        let ccw_polygon: Vec<Point> = polygon.into_iter().rev().collect();
+        assert_tiles_polygon(&vor_diagram, &vor_pts, &ccw_polygon);
+    }
+
+    #[test]
+    fn sites_outside_polygon() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(100.0, 0.0), Point::new(0.0, 100.0)];
+        let vor_pts = vec![Point::new(20.0, 20.0), Point::new(70.0, 40.0), Point::new(-10.0, 50.0), Point::new(500.0, 500.0)];
+        let (vor_diagram, _) = voronoi_in_convex_polygon(vor_pts.clone(), &polygon, &VoronoiOptions::default()).validated().unwrap();
+        let sites: Vec<usize> = make_site_polygons(&vor_diagram).iter().map(|&(site, _)| site).collect();
+        assert_eq!(sites, vec![0, 1, 2]);
+        assert_tiles_polygon(&vor_diagram, &vor_pts, &polygon);
+    }
+
+    #[test]
+    fn polygon_within_one_cell() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(100.0, 0.0), Point::new(0.0, 100.0)];
+        let vor_pts = vec![Point::new(20.0, 20.0), Point::new(90.0, 90.0)];
+        let (vor_diagram, _) = voronoi_in_convex_polygon(vor_pts.clone(), &polygon, &VoronoiOptions::default()).validated().unwrap();
+        let sites: Vec<usize> = make_site_polygons(&vor_diagram).iter().map(|&(site, _)| site).collect();
+        assert_eq!(sites, vec![0]);
+        assert_tiles_polygon(&vor_diagram, &vor_pts, &polygon);
+    }
+
+    #[test]
+    fn single_site_fills_polygon() {
+        let polygon = hexagon();
+        let (vor_diagram, _) = voronoi_in_convex_polygon(vec![Point::new(390.0, 420.0)], &polygon, &VoronoiOptions::default()).validated().unwrap();
+        let site_polys = make_site_polygons(&vor_diagram);
+        assert_eq!(site_polys.len(), 1);
+        assert_eq!(site_polys[0].1.len(), 6);
+    }
+
+    #[test]
+    fn non_convex_clip_polygon_rejected() {
+        let polygon = vec![Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(1.0, 0.5), Point::new(2.0, 2.0), Point::new(0.0, 2.0)];
+        let result = voronoi_in_convex_polygon(vec![Point::new(0.5, 0.5)], &polygon, &VoronoiOptions::default()).validated();
+        assert_eq!(result.err(), Some(VoronoiError::InvalidClipPolygon));
+    }
+
+    fn near_ring(ring: &[Point], pt: Point, tolerance: f64) -> bool {
+        (0..ring.len()).any(|index| {
+            let start = ring[index];
+            let side = ring[(index + 1) % ring.len()] - start;
+            let t = ((pt - start).dot(side) / side.dot(side)).clamp(0., 1.);
+            let offset = pt - (start + side * t);
+            offset.dot(offset).sqrt() <= tolerance
+        })
+    }
+
+    fn in_domain(outer: &[Point], holes: &[Vec<Point>], pt: Point) -> bool {
+        polygon_contains(outer, pt) && !holes.iter().any(|hole| polygon_contains(hole, pt))
+    }
+
+    // the faces lie in the domain, contain the sites inside it, and tile it
+    fn assert_tiles_domain(vor_diagram: &DCEL, vor_pts: &[Point], outer: &[Point], holes: &[Vec<Point>]) {
+        let tolerance = 1e-9 * BoundingBox::enclosing(outer).diagonal();
+        let domain_area = signed_area(outer) + holes.iter().map(|hole| signed_area(hole)).sum::<f64>();
+        let faces = make_site_polygons_with_holes(vor_diagram);
+        let mut total_area = 0.;
+        for (_, poly, face_holes) in &faces {
+            for &pt in poly.iter().chain(face_holes.iter().flat_map(|hole| hole.iter())) {
+                let on_boundary = near_ring(outer, pt, tolerance) || holes.iter().any(|hole| near_ring(hole, pt, tolerance));
+                assert!(on_boundary || in_domain(outer, holes, pt), "{:?} outside domain", pt);
+            }
+            assert!(signed_area(poly) > 0.);
+            total_area += signed_area(poly) + face_holes.iter().map(|hole| signed_area(hole)).sum::<f64>();
+        }
+        assert!((total_area - domain_area).abs() < 1e-9 * domain_area, "area {} != {}", total_area, domain_area);
+
+        for (site, &pt) in vor_pts.iter().enumerate() {
+            if !in_domain(outer, holes, pt) { continue; }
+            assert!(faces.iter().any(|face| face.0 == site && face_contains(face, pt)), "site {} not in its face", site);
+        }
+    }
+
+    fn l_shape() -> Vec<Point> {
+        vec![Point::new(0.0, 0.0), Point::new(100.0, 0.0), Point::new(100.0, 40.0),
+             Point::new(40.0, 40.0), Point::new(40.0, 100.0), Point::new(0.0, 100.0)]
+    }
+
+    fn square(min: f64, max: f64) -> Vec<Point> {
+        vec![Point::new(min, min), Point::new(max, min), Point::new(max, max), Point::new(min, max)]
+    }
+
+    #[test]
+    fn l_shaped_domain() {
+        let vor_pts = vec![Point::new(10.0, 15.0), Point::new(70.0, 20.0), Point::new(25.0, 75.0),
+                           Point::new(30.0, 30.0), Point::new(90.0, 35.0), Point::new(15.0, 90.0)];
+        let (vor_diagram, _) = voronoi_in_polygon(vor_pts.clone(), &l_shape(), &[], &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(make_site_polygons(&vor_diagram).len(), vor_pts.len());
+        assert_tiles_domain(&vor_diagram, &vor_pts, &l_shape(), &[]);
+    }
+
+    #[test]
+    fn cell_split_by_notch() {
+        // the cell of the site in the notch reaches both arms of the L
+        let vor_pts = vec![Point::new(20.0, 20.0), Point::new(90.0, 90.0)];
+        let (vor_diagram, _) = voronoi_in_polygon(vor_pts.clone(), &l_shape(), &[], &VoronoiOptions::default()).validated().unwrap();
+        let sites: Vec<usize> = make_site_polygons(&vor_diagram).iter().map(|&(site, _)| site).collect();
+        assert_eq!(sites, vec![0, 1, 1]);
+        assert_tiles_domain(&vor_diagram, &vor_pts, &l_shape(), &[]);
+    }
+
+    #[test]
+    fn annular_domain() {
+        let holes = vec![square(35.0, 65.0)];
+        let vor_pts = vec![Point::new(10.0, 10.0), Point::new(90.0, 15.0), Point::new(80.0, 85.0),
+                           Point::new(20.0, 70.0), Point::new(50.0, 18.0), Point::new(50.0, 50.0)];
+        let (vor_diagram, _) = voronoi_in_polygon(vor_pts.clone(), &square(0.0, 100.0), &holes, &VoronoiOptions::default()).validated().unwrap();
+        let sites: Vec<usize> = make_site_polygons(&vor_diagram).iter().map(|&(site, _)| site).collect();
+        assert_eq!(sites, vec![0, 1, 2, 3, 4, 5]);
+        let cw_holes: Vec<Vec<Point>> = holes.iter().map(|hole| hole.iter().rev().cloned().collect()).collect();
+        assert_tiles_domain(&vor_diagram, &vor_pts, &square(0.0, 100.0), &cw_holes);
+    }
+
+    #[test]
+    fn hole_inside_one_cell() {
+        let holes = vec![square(5.0, 15.0)];
+        let vor_pts = vec![Point::new(20.0, 20.0), Point::new(80.0, 70.0)];
+        let (vor_diagram, _) = voronoi_in_polygon(vor_pts.clone(), &square(0.0, 100.0), &holes, &VoronoiOptions::default()).validated().unwrap();
+        let faces = make_site_polygons_with_holes(&vor_diagram);
+        assert_eq!(faces.len(), 2);
+        assert_eq!(faces[0].2.len(), 1);
+        assert!(faces[1].2.is_empty());
+        let cw_holes: Vec<Vec<Point>> = holes.iter().map(|hole| hole.iter().rev().cloned().collect()).collect();
+        assert_tiles_domain(&vor_diagram, &vor_pts, &square(0.0, 100.0), &cw_holes);
+    }
+
+    #[test]
+    fn domain_inside_one_cell() {
+        let holes = vec![square(40.0, 60.0)];
+        let vor_pts = vec![Point::new(10.0, 10.0), Point::new(500.0, 500.0)];
+        let (vor_diagram, _) = voronoi_in_polygon(vor_pts.clone(), &square(0.0, 100.0), &holes, &VoronoiOptions::default()).validated().unwrap();
+        let faces = make_site_polygons_with_holes(&vor_diagram);
+        assert_eq!(faces.len(), 1);
+        assert_eq!(faces[0].0, 0);
+        assert_eq!(faces[0].2.len(), 1);
+    }
+
+    #[test]
+    fn bisector_through_domain_corners() {
+        let vor_pts = vec![Point::new(30.0, 80.0), Point::new(80.0, 30.0)];
+        let outer = square(5.0, 95.0);
+        let (vor_diagram, _) = voronoi_in_polygon(vor_pts.clone(), &outer, &[], &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(make_site_polygons(&vor_diagram).len(), 2);
+        assert_tiles_domain(&vor_diagram, &vor_pts, &outer, &[]);
+    }
+
+    #[test]
+    fn grid_sites_in_domain() {
+        // on a coarse grid, bisectors and vertices often fall on the rings
+        let mut rng: StdRng = SeedableRng::from_seed(&[2025][..]);
+        let (outer, holes) = (square(5.0, 95.0), vec![square(35.0, 55.0)]);
+        let cw_holes: Vec<Vec<Point>> = holes.iter().map(|hole| hole.iter().rev().cloned().collect()).collect();
+        for _ in 0..50 {
+            let mut vor_pts: Vec<Point> = vec![];
+            while vor_pts.len() < 5 {
+                let pt = Point::new((rng.next_f64() * 10.).floor() * 10., (rng.next_f64() * 10.).floor() * 10.);
+                if !vor_pts.contains(&pt) { vor_pts.push(pt); }
+            }
+            let (vor_diagram, _) = voronoi_in_polygon(vor_pts.clone(), &outer, &holes, &VoronoiOptions::default()).validated().unwrap();
+            assert_tiles_domain(&vor_diagram, &vor_pts, &outer, &cw_holes);
+        }
+    }
+
+    #[test]
+    fn crossing_hole_rejected() {
+        let holes = vec![square(50.0, 150.0)];
+        let result = voronoi_in_polygon(vec![Point::new(10.0, 10.0)], &square(0.0, 100.0), &holes, &VoronoiOptions::default()).validated();
+        assert_eq!(result.err(), Some(VoronoiError::InvalidClipPolygon));
+    }
+
+    // far along each ray, the two nearest sites are equally near
+    fn assert_rays_on_bisectors(vor_pts: &[Point], rays: &[Ray]) {
+        for ray in rays {
+            let far_pt = ray.point_at(1e4);
+            let mut distances: Vec<f64> = vor_pts.iter().map(|&pt| (pt - far_pt).dot(pt - far_pt).sqrt()).collect();
+            distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert!((distances[0] - distances[1]).abs() < 1e-6, "{:?} is not on a bisector", ray);
+            assert!((ray.direction.dot(ray.direction) - 1.).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn two_sites_unbounded() {
+        let vor_pts = vec![Point::new(0.0, 0.0), Point::new(2.0, 2.0)];
+        let (vor_diagram, rays, _) = voronoi_unbounded(vor_pts.clone(), &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(rays.len(), 2);
+        assert_eq!(rays[0].origin, Point::new(1.0, 1.0));
+        assert_eq!(rays[1].origin, Point::new(1.0, 1.0));
+        assert_eq!(rays[0].direction * -1., rays[1].direction);
+        assert_rays_on_bisectors(&vor_pts, &rays);
+        assert!(make_site_polygons(&vor_diagram).is_empty());
+        assert!(vor_diagram.vertices.is_empty());
+    }
+
+    #[test]
+    fn triangle_unbounded() {
+        let vor_pts = vec![Point::new(0.0, 0.0), Point::new(4.0, 0.0), Point::new(1.0, 3.0)];
+        let (vor_diagram, rays, _) = voronoi_unbounded(vor_pts.clone(), &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(rays.len(), 3);
+        assert!(rays.iter().all(|ray| ray.origin == Point::new(2.0, 1.0)));
+        assert!(rays.iter().all(|ray| vor_diagram.halfedges[ray.halfedge].origin == 0));
+        assert_rays_on_bisectors(&vor_pts, &rays);
+        let live_faces = vor_diagram.faces.iter().filter(|face| face.site().is_some()).count();
+        assert_eq!(live_faces, 3);
+    }
+
+    #[test]
+    fn rays_counterclockwise() {
+        let vor_pts = regular_polygon(7, Point::new(390.0, 415.0), 100.0);
+        let (_, rays, _) = voronoi_unbounded(vor_pts.clone(), &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(rays.len(), 7);
+        for (index, ray) in rays.iter().enumerate() {
+            let next_ray = rays[(index + 1) % rays.len()];
+            assert!(ray.direction.cross(next_ray.direction) > 0.);
+        }
+        assert_rays_on_bisectors(&vor_pts, &rays);
+    }
+
+    #[test]
+    fn grid_unbounded() {
+        let vor_pts = grid_points(3, 3, 10.0, 5.0);
+        let (vor_diagram, rays, _) = voronoi_unbounded(vor_pts.clone(), &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(rays.len(), 8);
+        assert_rays_on_bisectors(&vor_pts, &rays);
+        let site_polys = make_site_polygons(&vor_diagram);
+        assert_eq!(site_polys.len(), 1);
+        assert_eq!(site_polys[0].0, 4);
+        let live_faces = vor_diagram.faces.iter().filter(|face| face.site().is_some()).count();
+        assert_eq!(live_faces, 9);
+    }
+
+    #[test]
+    fn nearly_collinear_sites_unbounded() {
+        let (vor_diagram, rays, _) = voronoi_unbounded(nearly_collinear_sites(), &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(rays.len(), 4);
+        assert_eq!(vor_diagram.vertices.iter().filter(|vertex| vertex.alive).count(), 4);
+    }
+
+    #[test]
+    fn horizontal_sites_unbounded() {
+        let vor_pts = vec![Point::new(10.0, 1.0), Point::new(20.0, 1.0), Point::new(30.0, 1.0)];
+        let (vor_diagram, rays, _) = voronoi_unbounded(vor_pts.clone(), &VoronoiOptions::default()).validated().unwrap();
+        assert_eq!(rays.len(), 4);
+        assert_rays_on_bisectors(&vor_pts, &rays);
+        let directions: Vec<Point> = rays.iter().map(|ray| ray.direction).collect();
+        assert_eq!(directions, vec![Point::new(0.0, -1.0), Point::new(0.0, -1.0), Point::new(0.0, 1.0), Point::new(0.0, 1.0)]);
+        let live_faces = vor_diagram.faces.iter().filter(|face| face.site().is_some()).count();
+        assert_eq!(live_faces, 3);
     }
 }